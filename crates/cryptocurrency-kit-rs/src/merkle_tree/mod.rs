@@ -2,6 +2,7 @@ use std::iter;
 
 
 use crate::common::to_keccak;
+use crate::crypto::{Hash, HASH_SIZE};
 
 #[derive(Debug, Clone)]
 pub struct MerkleTree {
@@ -17,44 +18,123 @@ pub struct MerkleNode {
 }
 
 impl MerkleTree {
-    // just build the root
-    pub fn new_merkle_tree(mut data: Vec<Vec<u8>>) -> MerkleTree {
-        let clone_data = {
-            if data.len() % 2 != 0 {
-                let clone_data = {
-                    data.last().unwrap()
-                };
-                Some(clone_data.clone())
-            } else {
-                None
+    // just build the root, the last node of a level is paired with itself if the level is odd
+    pub fn new_merkle_tree(data: Vec<Vec<u8>>) -> MerkleTree {
+        if data.is_empty() {
+            return MerkleTree { root: None };
+        }
+        let mut nodes: Vec<MerkleNode> = data.iter().map(|dataum| MerkleNode::new(dataum)).collect();
+        loop {
+            nodes = next_level(nodes);
+            if nodes.len() == 1 {
+                break;
             }
-        };
-        if clone_data.is_some() {
-            data.push(clone_data.unwrap());
         }
+        MerkleTree { root: Some(Box::new(nodes.pop().unwrap())) }
+    }
 
-        let mut nodes = vec![];
-
-        data.iter().for_each(
-            |dataum| nodes.push(MerkleNode::new(dataum)),
-        );
-
+    // the root of the first release, only the leaves are padded and the last node of an odd upper level is dropped,
+    // it is kept to verify the blocks committed with it
+    pub fn new_legacy_merkle_tree(mut data: Vec<Vec<u8>>) -> MerkleTree {
+        if data.is_empty() {
+            return MerkleTree { root: None };
+        }
+        pad_data(&mut data);
+        let mut nodes: Vec<MerkleNode> = data.iter().map(|dataum| MerkleNode::new(dataum)).collect();
         loop {
-            let mut new_level = vec![];
-            let (mut i, mut j) = (0, 0);
-            while i < &nodes.len() / 2 {
-                let node = MerkleNode::new_merkle_node(nodes[j].clone(), nodes[j + 1].clone());
-                new_level.push(node);
-                j += 2;
-                i += 1;
-            }
-            nodes = new_level;
+            nodes = nodes
+                .chunks_exact(2)
+                .map(|pair| MerkleNode::new_merkle_node(pair[0].clone(), pair[1].clone()))
+                .collect();
             if nodes.len() == 1 {
                 break;
             }
         }
         MerkleTree { root: Some(Box::new(nodes.pop().unwrap())) }
     }
+
+    /// Returns the audit path of the `index`th data, none if it is out of range
+    pub fn audit_path(data: Vec<Vec<u8>>, index: usize) -> Option<MerkleProof> {
+        if index >= data.len() {
+            return None;
+        }
+        let mut nodes: Vec<MerkleNode> = data.iter().map(|dataum| MerkleNode::new(dataum)).collect();
+        let leaf = Hash::new(&nodes[index].data);
+        let mut path = vec![];
+        let mut position = index;
+        loop {
+            pad_level(&mut nodes);
+            path.push(Hash::new(&nodes[position ^ 1].data));
+            position /= 2;
+            nodes = next_level(nodes);
+            if nodes.len() == 1 {
+                break;
+            }
+        }
+        Some(MerkleProof {
+            index: index as u64,
+            leaf,
+            path,
+        })
+    }
+}
+
+/// The siblings from the leaf to the root, it proves the leaf is the `index`th data of the tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub index: u64,
+    pub leaf: Hash,
+    pub path: Vec<Hash>,
+}
+
+/// The hash of a leaf
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    Hash::new(&MerkleNode::new(data).data)
+}
+
+/// Checks the leaf of the proof is under the root
+pub fn verify_audit_path(root: &Hash, proof: &MerkleProof) -> bool {
+    let mut position = proof.index;
+    let mut current = proof.leaf;
+    for sibling in &proof.path {
+        current = if position % 2 == 0 {
+            hash_pair(&current, sibling)
+        } else {
+            hash_pair(sibling, &current)
+        };
+        position /= 2;
+    }
+    position == 0 && current == *root
+}
+
+fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+    let mut hash_data = Vec::with_capacity(HASH_SIZE * 2);
+    hash_data.extend_from_slice(left.as_ref());
+    hash_data.extend_from_slice(right.as_ref());
+    let hash: [u8; 32] = to_keccak(&hash_data).into();
+    Hash::new(&hash)
+}
+
+fn pad_level(nodes: &mut Vec<MerkleNode>) {
+    if nodes.len() % 2 != 0 {
+        let last = nodes.last().unwrap().clone();
+        nodes.push(last);
+    }
+}
+
+fn pad_data(data: &mut Vec<Vec<u8>>) {
+    if data.len() % 2 != 0 {
+        let last = data.last().unwrap().clone();
+        data.push(last);
+    }
+}
+
+fn next_level(mut nodes: Vec<MerkleNode>) -> Vec<MerkleNode> {
+    pad_level(&mut nodes);
+    nodes
+        .chunks(2)
+        .map(|pair| MerkleNode::new_merkle_node(pair[0].clone(), pair[1].clone()))
+        .collect()
 }
 
 impl MerkleNode {
@@ -98,4 +178,42 @@ mod tests {
         let merkle_tree = MerkleTree::new_merkle_tree(vv);
         writeln!(io::stdout(), "root {:?}", merkle_tree.root.unwrap()).unwrap();
     }
+
+    #[test]
+    fn legacy_merkle_tree() {
+        let root = |tree: MerkleTree| Hash::new(&tree.root.unwrap().data);
+        assert!(MerkleTree::new_legacy_merkle_tree(vec![]).root.is_none());
+        // the trees only differ when an upper level is odd
+        (1..5_u8).for_each(|size| {
+            let data: Vec<Vec<u8>> = (0..size).map(|idx| vec![idx]).collect();
+            assert_eq!(root(MerkleTree::new_legacy_merkle_tree(data.clone())), root(MerkleTree::new_merkle_tree(data)));
+        });
+        let data: Vec<Vec<u8>> = (0..5_u8).map(|idx| vec![idx]).collect();
+        let legacy = root(MerkleTree::new_legacy_merkle_tree(data.clone()));
+        assert_ne!(legacy, root(MerkleTree::new_merkle_tree(data.clone())));
+        // the fifth leaf is dropped with its padded pair
+        assert_eq!(legacy, root(MerkleTree::new_merkle_tree(data[..4].to_vec())));
+    }
+
+    #[test]
+    fn audit_path() {
+        assert!(MerkleTree::new_merkle_tree(vec![]).root.is_none());
+        (1..12_u8).for_each(|size| {
+            let data: Vec<Vec<u8>> = (0..size).map(|idx| vec![idx, idx + 1]).collect();
+            let root = Hash::new(&MerkleTree::new_merkle_tree(data.clone()).root.unwrap().data);
+            (0..size as usize).for_each(|index| {
+                let proof = MerkleTree::audit_path(data.clone(), index).unwrap();
+                assert_eq!(proof.leaf, leaf_hash(&data[index]));
+                assert!(verify_audit_path(&root, &proof));
+
+                let mut other = proof.clone();
+                other.index ^= 1;
+                // the last leaf of an odd level is paired with itself
+                assert!(other.path[0] == other.leaf || !verify_audit_path(&root, &other));
+                other.index = proof.index + (1 << proof.path.len());
+                assert!(!verify_audit_path(&root, &other));
+            });
+            assert!(MerkleTree::audit_path(data, size as usize).is_none());
+        });
+    }
 }
\ No newline at end of file
@@ -16,7 +16,7 @@
 
 //! A definition of `StorageKey` trait and implementations for common types.
 use crate::crypto::{HASH_SIZE, Hash};
-use crate::ethkey::{Public, SIGNATURE_SIZE, Signature};
+use crate::ethkey::{Address, Public, SIGNATURE_SIZE, Signature};
 use crate::types::Zero;
 
 use byteorder::{BigEndian, ByteOrder};
@@ -265,6 +265,7 @@ macro_rules! storage_key_for_crypto_option_types {
 
 storage_key_for_crypto_types! {Signature, SIGNATURE_SIZE}
 storage_key_for_crypto_types! {Public, 64}
+storage_key_for_crypto_types! {Address, 20}
 storage_key_for_crypto_option_types! {Hash, HASH_SIZE}
 
 #[cfg(test)]
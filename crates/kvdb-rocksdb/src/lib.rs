@@ -255,6 +255,17 @@ fn col_config(config: &DatabaseConfig, block_opts: &BlockBasedOptions) -> io::Re
     Ok(opts)
 }
 
+/// Disk usage of a database.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DiskUsage {
+    /// Number of the table files.
+    pub sst_files: u64,
+    /// Total size of the table files.
+    pub sst_bytes: u64,
+    /// Total size of the write-ahead logs.
+    pub wal_bytes: u64,
+}
+
 /// Key-Value database.
 pub struct Database {
     db: RwLock<Option<DBAndColumns>>,
@@ -700,6 +711,31 @@ impl Database {
         Ok(())
     }
 
+    /// The table files and the write-ahead logs under the database directory.
+    pub fn disk_usage(&self) -> io::Result<DiskUsage> {
+        let mut usage = DiskUsage::default();
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let len = entry.metadata()?.len();
+            match entry.path().extension().and_then(|ext| ext.to_str()) {
+                Some("sst") => {
+                    usage.sst_files += 1;
+                    usage.sst_bytes += len;
+                }
+                Some("log") => usage.wal_bytes += len,
+                _ => {}
+            }
+        }
+        Ok(usage)
+    }
+
+    /// The number of keys written with `write_buffered` and not flushed yet.
+    pub fn buffered_keys(&self) -> usize {
+        let overlay: usize = self.overlay.read().iter().map(HashMap::len).sum();
+        let flushing: usize = self.flushing.read().iter().map(HashMap::len).sum();
+        overlay + flushing
+    }
+
     /// The number of non-default column families.
     pub fn num_columns(&self) -> u32 {
         self.db
@@ -961,4 +997,21 @@ mod tests {
 
         assert_eq!(db.get(None, b"foo").unwrap().unwrap().as_ref(), b"baz");
     }
+
+    #[test]
+    fn buffered_keys_and_disk_usage() {
+        let tempdir = TempDir::new("").unwrap();
+        let config = DatabaseConfig::default();
+        let db = Database::open(&config, tempdir.path().to_str().unwrap()).unwrap();
+
+        let mut batch = db.transaction();
+        batch.put(None, b"foo", b"bar");
+        batch.put(None, b"bar", b"baz");
+        db.write_buffered(batch);
+        assert_eq!(db.buffered_keys(), 2);
+
+        db.flush().unwrap();
+        assert_eq!(db.buffered_keys(), 0);
+        assert!(db.disk_usage().unwrap().wal_bytes > 0);
+    }
 }
@@ -0,0 +1,98 @@
+//! The admin endpoints under `/admin`, they are served on `127.0.0.1:admin_port` only if `admin_api` is enabled
+//! in the config.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use libp2p::PeerId;
+
+use cryptocurrency_kit::ethkey::Address;
+
+use super::ApiState;
+use crate::consensus::events::OpCMD;
+use crate::consensus::pbft::core::core::CoreSnapshot;
+use crate::consensus::pbft::core::runner::CoreHandle;
+use crate::types::block::ValidatorVote;
+
+/// A stuck core does not reply, the request fails after the timeout
+pub const INSPECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub(super) fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/admin/core", get(inspect))
+        .route("/admin/core/pause", post(pause))
+        .route("/admin/core/resume", post(resume))
+        .route("/admin/core/round-change", post(round_change))
+        .route("/admin/peers/:peer_id", delete(drop_peer))
+        .route("/admin/proposals", get(proposals).post(propose_validator))
+        .route("/admin/proposals/:address", delete(discard_proposal))
+}
+
+fn core_handle(state: &ApiState) -> Result<&CoreHandle, (StatusCode, String)> {
+    state
+        .core
+        .as_ref()
+        .ok_or_else(|| (StatusCode::NOT_IMPLEMENTED, "only the pbft engine is supported".to_string()))
+}
+
+async fn inspect(State(state): State<ApiState>) -> Result<Json<CoreSnapshot>, (StatusCode, String)> {
+    let core = core_handle(&state)?;
+    match tokio::time::timeout(INSPECT_TIMEOUT, core.inspect()).await {
+        Ok(Some(snapshot)) => Ok(Json(snapshot)),
+        Ok(None) => Err((StatusCode::SERVICE_UNAVAILABLE, "the core is stopped".to_string())),
+        Err(_) => Err((StatusCode::GATEWAY_TIMEOUT, "the core does not reply".to_string())),
+    }
+}
+
+fn send_op(state: &ApiState, op: OpCMD) -> Result<StatusCode, (StatusCode, String)> {
+    if core_handle(state)?.send_op(op) {
+        Ok(StatusCode::ACCEPTED)
+    } else {
+        Err((StatusCode::SERVICE_UNAVAILABLE, "the core is stopped".to_string()))
+    }
+}
+
+async fn pause(State(state): State<ApiState>) -> Result<StatusCode, (StatusCode, String)> {
+    send_op(&state, OpCMD::Pause)
+}
+
+async fn resume(State(state): State<ApiState>) -> Result<StatusCode, (StatusCode, String)> {
+    send_op(&state, OpCMD::Resume)
+}
+
+async fn round_change(State(state): State<ApiState>) -> Result<StatusCode, (StatusCode, String)> {
+    send_op(&state, OpCMD::RoundChange)
+}
+
+async fn drop_peer(State(state): State<ApiState>, Path(peer_id): Path<String>) -> Result<StatusCode, (StatusCode, String)> {
+    let peer_id = PeerId::from_str(&peer_id).map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    if state.server.drop_peer(&peer_id) {
+        Ok(StatusCode::OK)
+    } else {
+        Err((StatusCode::NOT_FOUND, format!("{} is not connected", peer_id.to_base58())))
+    }
+}
+
+async fn proposals(State(state): State<ApiState>) -> Json<Vec<ValidatorVote>> {
+    Json(state.chain.proposals())
+}
+
+// votes for the validator change in the blocks sealed by local node
+async fn propose_validator(State(state): State<ApiState>, Json(vote): Json<ValidatorVote>) -> Json<ValidatorVote> {
+    state.chain.propose_validator(vote.address, vote.authorize);
+    Json(vote)
+}
+
+async fn discard_proposal(State(state): State<ApiState>, Path(address): Path<Address>) -> StatusCode {
+    if state.chain.discard_proposal(&address) {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
@@ -1,39 +1,177 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
 
-use axum::{extract::State, routing::get, Json, Router};
+use axum::{
+    body::Bytes,
+    extract::{ws::WebSocketUpgrade, FromRef, Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use parking_lot::RwLock;
 
+use cryptocurrency_kit::crypto::{CryptoHash, Hash};
+use cryptocurrency_kit::ethkey::Address;
+
+use crate::common::string_to_hash;
+use crate::config::Config;
+use crate::consensus::pbft::core::runner::CoreHandle;
 use crate::core::chain::Chain;
+use crate::core::tx_pool::{submit_transaction, SafeTxPool};
+use crate::metrics;
+use crate::p2p::server::TcpServer;
+use crate::store::proof_map_index::MapProof;
+use crate::types::account::Account;
 use crate::types::block::Blocks;
+use crate::subscriber::events::{BroadcastEvent, BroadcastEventBus};
+use crate::types::transaction::{Transaction, TransactionProof};
+use crate::types::{Height, Validators};
 
-async fn blocks(State(chain): State<Arc<Chain>>) -> Json<Blocks> {
-    let last_height = chain.get_last_height();
-    let mut blocks: Blocks = Blocks(vec![]);
-    for height in 0..=last_height {
-        if let Some(block_hash) = chain.get_block_hash_by_height(height) {
-            if let Some(block) = chain.get_block_by_hash(&block_hash) {
-                blocks.0.push(block);
-            }
-        }
+pub mod admin;
+pub mod rpc;
+pub mod ws;
+
+#[derive(Clone)]
+struct ApiState {
+    chain: Arc<Chain>,
+    tx_pool: Arc<RwLock<SafeTxPool>>,
+    broadcast_bus: BroadcastEventBus,
+    server: TcpServer,
+    // none if the engine is not pbft
+    core: Option<CoreHandle>,
+}
+
+impl FromRef<ApiState> for Arc<Chain> {
+    fn from_ref(state: &ApiState) -> Self {
+        state.chain.clone()
     }
-    Json(blocks)
 }
 
-async fn transactions(State(chain): State<Arc<Chain>>) -> Json<Vec<crate::types::transaction::Transaction>> {
+/// An account with its proof against the state root of the last block,
+/// the proof also proves an absent account.
+#[derive(Serialize)]
+struct AccountProof {
+    height: Height,
+    state_root: Hash,
+    account: Option<Account>,
+    proof: MapProof,
+}
+
+#[derive(Deserialize)]
+struct Page {
+    from: Option<Height>,
+    limit: Option<u64>,
+}
+
+// at most `rpc::MAX_PAGE_SIZE` blocks from the height
+async fn blocks(State(chain): State<Arc<Chain>>, Query(page): Query<Page>) -> Json<Blocks> {
+    let limit = page.limit.unwrap_or(rpc::MAX_PAGE_SIZE).min(rpc::MAX_PAGE_SIZE);
+    Json(Blocks(chain.get_blocks(page.from.unwrap_or(0), limit)))
+}
+
+async fn transactions(State(chain): State<Arc<Chain>>) -> Json<Vec<Transaction>> {
     Json(chain.get_transactions())
 }
 
-pub fn start_api(chain: Arc<Chain>, ip: String, port: u16) {
-    let addr: SocketAddr = format!("{}:{}", ip, port).parse().expect("invalid api address");
+// puts the signed transaction into the pool and gossips it, returns the transaction hash
+async fn submit(State(state): State<ApiState>, Json(transaction): Json<Transaction>) -> Result<Json<Hash>, (StatusCode, String)> {
+    let added = submit_transaction(&state.chain, &state.tx_pool, transaction.clone())
+        .map_err(|err| (StatusCode::BAD_REQUEST, format!("{}", err)))?;
+    // the signature is verified, so it has a hash
+    let tx_hash = transaction.hash();
+    if added {
+        state.broadcast_bus.send(BroadcastEvent::Transaction(transaction));
+    }
+    Ok(Json(tx_hash))
+}
+
+async fn transaction_proof(State(chain): State<Arc<Chain>>, Path(tx_hash): Path<String>) -> Result<Json<TransactionProof>, StatusCode> {
+    let tx_hash = string_to_hash(&tx_hash).map_err(|_| StatusCode::BAD_REQUEST)?;
+    chain.get_transaction_proof(&tx_hash).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn validators(State(chain): State<Arc<Chain>>, Path(height): Path<Height>) -> Result<Json<Validators>, StatusCode> {
+    if height > chain.get_last_height() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(chain.get_validators(height)))
+}
+
+async fn account(State(chain): State<Arc<Chain>>, Path(address): Path<Address>) -> Json<AccountProof> {
+    let (header, account, proof) = chain.get_account_proof(&address);
+    Json(AccountProof {
+        height: header.height,
+        state_root: header.root,
+        account,
+        proof,
+    })
+}
+
+async fn json_rpc(State(state): State<ApiState>, body: Bytes) -> Response {
+    match rpc::handle_body(&state, &body) {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+async fn subscribe(State(state): State<ApiState>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| ws::serve(socket, state))
+}
+
+// the database usage is read on scrape, the other metrics are updated where they change
+async fn prometheus_metrics(State(chain): State<Arc<Chain>>) -> impl IntoResponse {
+    if let Some(database) = chain.get_database() {
+        metrics::observe_database(&database);
+    }
+    ([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], metrics::gather())
+}
+
+pub fn start_api(
+    config: &Config,
+    chain: Arc<Chain>,
+    tx_pool: Arc<RwLock<SafeTxPool>>,
+    broadcast_bus: BroadcastEventBus,
+    server: TcpServer,
+    core: Option<CoreHandle>,
+) {
+    let addr: SocketAddr = format!("{}:{}", config.api_ip, config.api_port).parse().expect("invalid api address");
 
     let app = Router::new()
         .route("/blocks", get(blocks))
-        .route("/transactions", get(transactions))
-        .with_state(chain);
+        .route("/transactions", get(transactions).post(submit))
+        .route("/tx/:hash/proof", get(transaction_proof))
+        .route("/validators/:height", get(validators))
+        .route("/accounts/:address", get(account))
+        .route("/rpc", post(json_rpc))
+        .route("/ws", get(subscribe))
+        .route("/metrics", get(prometheus_metrics));
+    let state = ApiState {
+        chain,
+        tx_pool,
+        broadcast_bus,
+        server,
+        core,
+    };
+    // the admin endpoints are not authenticated, so they are only served on the loopback
+    let admin = if config.admin_api {
+        let admin_addr = SocketAddr::from(([127, 0, 0, 1], config.admin_port));
+        warn!("The admin api is enabled on {}", admin_addr);
+        Some((admin_addr, admin::routes().with_state(state.clone())))
+    } else {
+        None
+    };
+    let app = app.with_state(state);
 
     std::thread::spawn(move || {
         let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
         rt.block_on(async {
+            if let Some((admin_addr, admin)) = admin {
+                let listener = tokio::net::TcpListener::bind(admin_addr).await.expect("Failed to bind admin API");
+                tokio::spawn(async move {
+                    axum::serve(listener, admin).await.expect("Admin API server error");
+                });
+            }
             let listener = tokio::net::TcpListener::bind(addr).await.expect("Failed to bind API");
             axum::serve(listener, app).await.expect("API server error");
         });
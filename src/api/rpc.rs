@@ -0,0 +1,249 @@
+//! JSON-RPC 2.0 over `POST /rpc`, a batch of requests is supported.
+
+use cryptocurrency_kit::crypto::{CryptoHash, Hash};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use super::ApiState;
+use crate::common::string_to_hash;
+use crate::core::tx_pool::submit_transaction;
+use crate::subscriber::events::BroadcastEvent;
+use crate::types::block::Block;
+use crate::types::transaction::Transaction;
+use crate::types::Height;
+
+pub const JSONRPC_VERSION: &str = "2.0";
+/// Max blocks of a page
+pub const MAX_PAGE_SIZE: u64 = 100;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// The transaction is rejected by the pool
+pub const TRANSACTION_REJECTED: i64 = -32010;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new<S: Into<String>>(code: i64, message: S) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn invalid_params<S: Into<String>>(message: S) -> Self {
+        RpcError::new(INVALID_PARAMS, message)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    // a request without id is a notification, it gets no response
+    id: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+    pub id: Value,
+}
+
+impl Response {
+    fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Response {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            result,
+            error,
+            id,
+        }
+    }
+}
+
+/// A page of the blocks, `next` is the height to continue from if there are more
+#[derive(Debug, Serialize)]
+pub struct BlockPage {
+    pub blocks: Vec<Block>,
+    pub next: Option<Height>,
+}
+
+/// A committed transaction with its location, or a pending one in the pool without it
+#[derive(Debug, Serialize)]
+pub struct TransactionInfo {
+    pub transaction: Transaction,
+    pub block_hash: Option<Hash>,
+    pub height: Option<Height>,
+    pub index: Option<u64>,
+}
+
+/// Handles the body of `POST /rpc`, returns none if there is nothing to reply(notifications only)
+pub(super) fn handle_body(state: &ApiState, body: &[u8]) -> Option<Value> {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(err) => return Some(json!(Response::new(Value::Null, Err(RpcError::new(PARSE_ERROR, err.to_string()))))),
+    };
+    match request {
+        Value::Array(requests) => {
+            if requests.is_empty() {
+                return Some(json!(Response::new(Value::Null, Err(RpcError::new(INVALID_REQUEST, "empty batch")))));
+            }
+            let responses: Vec<Response> = requests.into_iter().filter_map(|request| handle_request(state, request)).collect();
+            if responses.is_empty() {
+                None
+            } else {
+                Some(json!(responses))
+            }
+        }
+        request => handle_request(state, request).map(|response| json!(response)),
+    }
+}
+
+fn handle_request(state: &ApiState, request: Value) -> Option<Response> {
+    let request: Request = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(err) => return Some(Response::new(Value::Null, Err(RpcError::new(INVALID_REQUEST, err.to_string())))),
+    };
+    if request.jsonrpc != JSONRPC_VERSION {
+        let id = request.id.unwrap_or(Value::Null);
+        return Some(Response::new(id, Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""))));
+    }
+    let result = dispatch(state, &request.method, &request.params);
+    request.id.map(|id| Response::new(id, result))
+}
+
+fn dispatch(state: &ApiState, method: &str, params: &Value) -> Result<Value, RpcError> {
+    let chain = &state.chain;
+    match method {
+        "getLatestHeight" => Ok(json!(chain.get_last_height())),
+        "getBlockByHeight" => {
+            let height: Height = param(params, 0, "height")?;
+            Ok(json!(chain.get_block_by_height(height)))
+        }
+        "getBlockByHash" => {
+            let block_hash = hash_param(params, 0, "hash")?;
+            Ok(json!(chain.get_block_by_hash(&block_hash)))
+        }
+        "getBlocks" => {
+            let from: Height = optional_param(params, 0, "from")?.unwrap_or(0);
+            let limit: u64 = optional_param(params, 1, "limit")?.unwrap_or(MAX_PAGE_SIZE).min(MAX_PAGE_SIZE);
+            let blocks = chain.get_blocks(from, limit);
+            let next = blocks
+                .last()
+                .map(|block| block.height() + 1)
+                .filter(|next| *next <= chain.get_last_height());
+            Ok(json!(BlockPage { blocks, next }))
+        }
+        "getTransaction" => {
+            let tx_hash = hash_param(params, 0, "hash")?;
+            if let Some((transaction, location)) = chain.get_transaction(&tx_hash) {
+                return Ok(json!(TransactionInfo {
+                    transaction,
+                    block_hash: chain.get_block_hash_by_height(location.block_height),
+                    height: Some(location.block_height),
+                    index: Some(location.position_in_block),
+                }));
+            }
+            let pending = state.tx_pool.read().get_tx(&tx_hash);
+            Ok(json!(pending.map(|transaction| TransactionInfo {
+                transaction: (*transaction).clone(),
+                block_hash: None,
+                height: None,
+                index: None,
+            })))
+        }
+        "getValidators" => {
+            let height: Height = param(params, 0, "height")?;
+            if height > chain.get_last_height() {
+                return Err(RpcError::invalid_params(format!("height {} is above the last block", height)));
+            }
+            Ok(json!(chain.get_validators(height)))
+        }
+        "sendRawTransaction" => {
+            let data: String = param(params, 0, "data")?;
+            let bytes = hex::decode(data.trim_start_matches("0x")).map_err(|err| RpcError::invalid_params(err.to_string()))?;
+            let transaction: Transaction = serde_json::from_slice(&bytes).map_err(|err| RpcError::invalid_params(err.to_string()))?;
+            let added = submit_transaction(chain, &state.tx_pool, transaction.clone())
+                .map_err(|err| RpcError::new(TRANSACTION_REJECTED, format!("{}", err)))?;
+            let tx_hash = transaction.hash();
+            if added {
+                state.broadcast_bus.send(BroadcastEvent::Transaction(transaction));
+            }
+            Ok(json!(tx_hash))
+        }
+        "getPeers" => Ok(json!(state.server.peers())),
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("method not found: {}", method))),
+    }
+}
+
+/// Reads the positional param at the index or the named one
+fn optional_param<T: DeserializeOwned>(params: &Value, index: usize, name: &str) -> Result<Option<T>, RpcError> {
+    let value = match params {
+        Value::Array(params) => params.get(index),
+        Value::Object(params) => params.get(name),
+        Value::Null => None,
+        _ => return Err(RpcError::invalid_params("params must be an array or an object")),
+    };
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|err| RpcError::invalid_params(format!("invalid {}: {}", name, err))),
+    }
+}
+
+fn param<T: DeserializeOwned>(params: &Value, index: usize, name: &str) -> Result<T, RpcError> {
+    optional_param(params, index, name)?.ok_or_else(|| RpcError::invalid_params(format!("missing {}", name)))
+}
+
+fn hash_param(params: &Value, index: usize, name: &str) -> Result<Hash, RpcError> {
+    let hash: String = param(params, index, name)?;
+    string_to_hash(&hash).map_err(|err| RpcError::invalid_params(format!("invalid {}: {}", name, err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_params() {
+        let positional = json!([10, null]);
+        let named = json!({"height": 10});
+        assert_eq!(param::<u64>(&positional, 0, "height").unwrap(), 10);
+        assert_eq!(param::<u64>(&named, 0, "height").unwrap(), 10);
+        assert_eq!(optional_param::<u64>(&positional, 1, "limit").unwrap(), None);
+        assert_eq!(optional_param::<u64>(&Value::Null, 0, "from").unwrap(), None);
+
+        assert_eq!(param::<u64>(&named, 0, "hash").unwrap_err().code, INVALID_PARAMS);
+        assert_eq!(param::<u64>(&json!(["10"]), 0, "height").unwrap_err().code, INVALID_PARAMS);
+        assert_eq!(param::<u64>(&json!("10"), 0, "height").unwrap_err().code, INVALID_PARAMS);
+        assert!(hash_param(&json!(["0x12"]), 0, "hash").is_err());
+    }
+
+    #[test]
+    fn t_response() {
+        let ok = json!(Response::new(json!(1), Ok(Value::Null)));
+        assert_eq!(ok, json!({"jsonrpc": "2.0", "result": null, "id": 1}));
+        let err = json!(Response::new(json!("a"), Err(RpcError::new(METHOD_NOT_FOUND, "method not found: foo"))));
+        assert_eq!(err, json!({"jsonrpc": "2.0", "error": {"code": -32601, "message": "method not found: foo"}, "id": "a"}));
+    }
+}
@@ -0,0 +1,311 @@
+//! WebSocket subscriptions over `GET /ws`.
+//!
+//! A client sends `{"id": 1, "method": "subscribe", "params": {"topic": "newHeads", "filter": {...}}}`
+//! and gets the subscription id, then every event of the topic passing the filter is pushed as
+//! `{"method": "subscription", "params": {"subscription": id, "result": ...}}`.
+
+use std::collections::BTreeMap;
+
+use axum::extract::ws::{Message, WebSocket};
+use cryptocurrency_kit::ethkey::Address;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+
+use super::rpc::{RpcError, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR};
+use super::ApiState;
+use crate::consensus::types::View;
+use crate::subscriber::events::{BroadcastEvent, ChainEvent};
+use crate::types::block::{Block, Header};
+use crate::types::transaction::Transaction;
+
+/// Max messages buffered for a client, the events beyond it are dropped and counted as missed
+pub const MAX_PENDING_MESSAGES: usize = 256;
+/// A client is disconnected if it misses so many events in a row
+pub const MAX_MISSED_EVENTS: u64 = 4096;
+pub const MAX_SUBSCRIPTIONS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Topic {
+    NewHeads,
+    NewBlocks,
+    PendingTransactions,
+    ConsensusRoundChange,
+}
+
+/// The fields absent are not filtered, `proposer` applies to the heads, blocks and round changes,
+/// `from` and `to` apply to the transactions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Filter {
+    pub proposer: Option<Address>,
+    pub from: Option<Address>,
+    pub to: Option<Address>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    NewHead(Header),
+    NewBlock(Block),
+    PendingTransaction(Transaction),
+    RoundChange(View, Option<Address>),
+}
+
+impl Event {
+    fn topic(&self) -> Topic {
+        match self {
+            Event::NewHead(_) => Topic::NewHeads,
+            Event::NewBlock(_) => Topic::NewBlocks,
+            Event::PendingTransaction(_) => Topic::PendingTransactions,
+            Event::RoundChange(_, _) => Topic::ConsensusRoundChange,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Event::NewHead(header) => json!(header),
+            Event::NewBlock(block) => json!(block),
+            Event::PendingTransaction(transaction) => json!(transaction),
+            Event::RoundChange(view, proposer) => json!({
+                "height": view.height,
+                "round": view.round,
+                "proposer": proposer,
+            }),
+        }
+    }
+}
+
+impl Filter {
+    fn matches(&self, event: &Event) -> bool {
+        match event {
+            Event::NewHead(header) => self.proposer.map_or(true, |proposer| header.proposer == proposer),
+            Event::NewBlock(block) => self.proposer.map_or(true, |proposer| block.header().proposer == proposer),
+            Event::RoundChange(_, round_proposer) => self.proposer.map_or(true, |proposer| *round_proposer == Some(proposer)),
+            Event::PendingTransaction(transaction) => {
+                self.to.map_or(true, |to| transaction.to() == Some(&to))
+                    && self.from.map_or(true, |from| {
+                        transaction.get_sender().cloned().or_else(|| transaction.sender()) == Some(from)
+                    })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SubscribeParams {
+    topic: Topic,
+    #[serde(default)]
+    filter: Filter,
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// The subscriptions of a connection
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    next_id: u64,
+    subscriptions: BTreeMap<u64, (Topic, Filter)>,
+}
+
+impl Subscriptions {
+    pub fn subscribe(&mut self, topic: Topic, filter: Filter) -> Result<u64, RpcError> {
+        if self.subscriptions.len() >= MAX_SUBSCRIPTIONS {
+            return Err(RpcError::new(INVALID_REQUEST, format!("at most {} subscriptions", MAX_SUBSCRIPTIONS)));
+        }
+        self.next_id += 1;
+        self.subscriptions.insert(self.next_id, (topic, filter));
+        Ok(self.next_id)
+    }
+
+    pub fn unsubscribe(&mut self, id: u64) -> bool {
+        self.subscriptions.remove(&id).is_some()
+    }
+
+    pub fn has_topic(&self, topic: Topic) -> bool {
+        self.subscriptions.values().any(|(subscribed, _)| *subscribed == topic)
+    }
+
+    /// The notifications of the subscriptions the event passes
+    pub fn notifications(&self, event: &Event) -> Vec<Value> {
+        let topic = event.topic();
+        let matched: Vec<u64> = self
+            .subscriptions
+            .iter()
+            .filter(|(_, (subscribed, filter))| *subscribed == topic && filter.matches(event))
+            .map(|(id, _)| *id)
+            .collect();
+        if matched.is_empty() {
+            return vec![];
+        }
+        let result = event.to_json();
+        matched
+            .into_iter()
+            .map(|id| json!({"method": "subscription", "params": {"subscription": id, "result": result}}))
+            .collect()
+    }
+
+    fn handle_request(&mut self, text: &str) -> Value {
+        let request: Request = match serde_json::from_str(text) {
+            Ok(request) => request,
+            Err(err) => return reply(Value::Null, Err(RpcError::new(PARSE_ERROR, err.to_string()))),
+        };
+        let result = match request.method.as_str() {
+            "subscribe" => serde_json::from_value::<SubscribeParams>(request.params)
+                .map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+                .and_then(|params| self.subscribe(params.topic, params.filter))
+                .map(|id| json!(id)),
+            "unsubscribe" => serde_json::from_value::<u64>(request.params)
+                .map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+                .map(|id| json!(self.unsubscribe(id))),
+            method => Err(RpcError::new(METHOD_NOT_FOUND, format!("method not found: {}", method))),
+        };
+        reply(request.id, result)
+    }
+}
+
+fn reply(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({"id": id, "result": result}),
+        Err(error) => json!({"id": id, "error": error}),
+    }
+}
+
+fn chain_event(event: ChainEvent) -> Option<Event> {
+    match event {
+        ChainEvent::NewHeader(header) => Some(Event::NewHead(header)),
+        ChainEvent::NewBlock(block) => Some(Event::NewBlock(block)),
+        ChainEvent::RoundChange(view, proposer) => Some(Event::RoundChange(view, proposer)),
+        _ => None,
+    }
+}
+
+/// Serves a client until it closes, or it misses too many events because it reads slowly
+pub(super) async fn serve(socket: WebSocket, state: ApiState) {
+    let (mut sink, mut stream) = socket.split();
+    // a bounded buffer between the events and the socket, a slow client never blocks the events
+    let (out_tx, mut out_rx) = mpsc::channel::<Message>(MAX_PENDING_MESSAGES);
+    let writer = tokio::spawn(async move {
+        while let Some(msg) = out_rx.recv().await {
+            if sink.send(msg).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut chain_rx = state.chain.chain_event_bus().subscribe();
+    let mut broadcast_rx = state.broadcast_bus.subscribe();
+    let mut subscriptions = Subscriptions::default();
+    let mut missed: u64 = 0;
+
+    loop {
+        let event = tokio::select! {
+            msg = stream.next() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        let response = subscriptions.handle_request(&text);
+                        // a reply is dropped like a notification when the client reads slowly
+                        match out_tx.try_send(Message::Text(response.to_string())) {
+                            Ok(()) => {}
+                            Err(mpsc::error::TrySendError::Full(_)) => missed += 1,
+                            Err(mpsc::error::TrySendError::Closed(_)) => break,
+                        }
+                        None
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                }
+            }
+            event = chain_rx.recv() => {
+                match event {
+                    Ok(event) => chain_event(event),
+                    Err(RecvError::Lagged(n)) => {
+                        missed += n;
+                        None
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+            event = broadcast_rx.recv() => {
+                match event {
+                    Ok(BroadcastEvent::Transaction(transaction)) if subscriptions.has_topic(Topic::PendingTransactions) => {
+                        Some(Event::PendingTransaction(transaction))
+                    }
+                    Ok(_) => None,
+                    Err(RecvError::Lagged(n)) => {
+                        missed += n;
+                        None
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        };
+        if let Some(event) = event {
+            for notification in subscriptions.notifications(&event) {
+                match out_tx.try_send(Message::Text(notification.to_string())) {
+                    Ok(()) => {
+                        if missed > 0 {
+                            let _ = out_tx.try_send(Message::Text(json!({"method": "missed", "params": missed}).to_string()));
+                            missed = 0;
+                        }
+                    }
+                    Err(mpsc::error::TrySendError::Full(_)) => missed += 1,
+                    Err(mpsc::error::TrySendError::Closed(_)) => break,
+                }
+            }
+        }
+        if missed > MAX_MISSED_EVENTS {
+            warn!("Close the slow websocket client, missed events: {}", missed);
+            break;
+        }
+    }
+    writer.abort();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptocurrency_kit::crypto::EMPTY_HASH;
+
+    #[test]
+    fn t_subscriptions() {
+        let mut subscriptions = Subscriptions::default();
+        let heads = subscriptions.subscribe(Topic::NewHeads, Filter::default()).unwrap();
+        let filter = Filter {
+            proposer: Some(Address::from(2)),
+            ..Filter::default()
+        };
+        let proposed = subscriptions.subscribe(Topic::NewHeads, filter).unwrap();
+
+        let header = Header::new_mock(EMPTY_HASH, Address::from(1), EMPTY_HASH, 1, 1, None);
+        assert_eq!(subscriptions.notifications(&Event::NewHead(header.clone())).len(), 1);
+        let header = Header::new_mock(EMPTY_HASH, Address::from(2), EMPTY_HASH, 1, 1, None);
+        assert_eq!(subscriptions.notifications(&Event::NewHead(header.clone())).len(), 2);
+        assert!(subscriptions.notifications(&Event::RoundChange(View::new(1, 1), None)).is_empty());
+
+        assert!(subscriptions.unsubscribe(heads));
+        assert!(!subscriptions.unsubscribe(heads));
+        let notifications = subscriptions.notifications(&Event::NewHead(header));
+        assert_eq!(notifications[0]["params"]["subscription"], json!(proposed));
+    }
+
+    #[test]
+    fn t_requests() {
+        let mut subscriptions = Subscriptions::default();
+        let response = subscriptions.handle_request(r#"{"id": 1, "method": "subscribe", "params": {"topic": "pendingTransactions"}}"#);
+        assert_eq!(response, json!({"id": 1, "result": 1}));
+        assert!(subscriptions.has_topic(Topic::PendingTransactions));
+
+        let response = subscriptions.handle_request(r#"{"id": 2, "method": "subscribe", "params": {"topic": "unknown"}}"#);
+        assert_eq!(response["error"]["code"], json!(INVALID_PARAMS));
+        let response = subscriptions.handle_request(r#"{"id": 3, "method": "unsubscribe", "params": 1}"#);
+        assert_eq!(response, json!({"id": 3, "result": true}));
+        assert!(!subscriptions.has_topic(Topic::PendingTransactions));
+    }
+}
@@ -12,23 +12,29 @@ use parking_lot::RwLock;
 
 use crate::{
     common,
-    config::Config,
-    consensus::consensus::{create_bft_engine, SafeEngine},
+    config::{Config, EngineKind},
+    consensus::consensus::{create_bft_engine, create_dpos_engine, create_raft_engine, SafeEngine},
     consensus::pbft::core::core::handle_msg_middle,
+    consensus::pbft::core::runner::CoreHandle,
     core::chain::Chain,
     core::ledger::{LastMeta, Ledger},
-    core::tx_pool::{BaseTxPool, SafeTxPool},
+    core::transaction_pool::TransactionPool,
+    core::tx_pool::SafeTxPool,
     error::ChainResult,
     logger::init_log,
     minner::start_minner,
     p2p::{
+        config::Config as PeerConfig,
         discover_service::DiscoverService,
-        server::{author_handshake, TcpServer},
+        peer_manager::PeerManager,
+        server::{author_handshake, HandleMsgFn, TcpServer},
+        transport::{node_keypair, Allowlist},
         spawn_sync_subscriber,
     },
     pprof::spawn_signal_handler,
     store::schema::Schema,
     subscriber::events::BroadcastEventBus,
+    sync::service::start_sync_service,
     types::Validator,
     api::start_api,
 };
@@ -42,6 +48,7 @@ pub fn start_node(config: &str, _sender: Sender<()>) -> Result<(), String> {
     }
     let config = result.unwrap();
     let secret = Secret::from_str(&config.secret).expect("Secret is uncorrect");
+    let node_key = node_keypair(&secret)?;
     let key_pair = KeyPair::from_secret(secret).unwrap();
     let ledger = init_store(&config)?;
     let ledger: Arc<RwLock<Ledger>> = Arc::new(RwLock::new(ledger));
@@ -57,48 +64,85 @@ pub fn start_node(config: &str, _sender: Sender<()>) -> Result<(), String> {
 
     let chain = Arc::new(chain);
 
-    init_api(&config, chain.clone());
-
     let broadcast_bus = BroadcastEventBus::new(1024);
 
-    let (core_handle, mut engine) = start_consensus_engine(
+    let (handle_msg, mut engine, core_handle) = start_consensus_engine(
         &config,
         key_pair.clone(),
         chain.clone(),
+        tx_pool.clone(),
         broadcast_bus.clone(),
     );
     engine.start()?;
 
+    let local_peer_id = node_key.public().to_peer_id();
+    info!("Local peer id: {}", local_peer_id.to_base58());
+    if !config.peer_id.is_empty() && config.peer_id != local_peer_id.to_base58() {
+        warn!("The configured peer id {} is not the one of the node key, it is ignored", config.peer_id);
+    }
+    let allowlist = config
+        .p2p_allowlist
+        .iter()
+        .map(|peer_id| libp2p::PeerId::from_str(peer_id).map_err(|err| format!("Invalid peer id {} in the allowlist, err: {}", peer_id, err)))
+        .collect::<Result<Vec<_>, _>>()?;
+
     let config_clone = config.clone();
     {
         let p2p_event_bus = spawn_sync_subscriber();
         let p2p_event_bus_for_discover = p2p_event_bus.clone();
-        std::thread::spawn(move || {
-            DiscoverService::run_discover_service(
-                p2p_event_bus_for_discover,
-                libp2p::PeerId::from_str(&config_clone.peer_id).unwrap(),
-                libp2p::Multiaddr::from_str(&format!("/ip4/{}/tcp/0", config_clone.ip)).unwrap(),
-                config_clone.ttl,
-            );
-        });
+        let discover_key = node_key.clone();
+        // without the mdns the peers are found by the peer exchange from the bootstrap peers
+        if config.mdns {
+            std::thread::spawn(move || {
+                DiscoverService::run_discover_service(
+                    p2p_event_bus_for_discover,
+                    discover_key,
+                    libp2p::Multiaddr::from_str(&format!("/ip4/{}/tcp/0", config_clone.ip)).unwrap(),
+                    config_clone.ttl,
+                );
+            });
+        }
         let genesis = chain.get_genesis().hash();
         let mut p2p_rx = p2p_event_bus.subscribe();
+        let external_addresses = config
+            .external_addresses
+            .iter()
+            .map(|addr| libp2p::Multiaddr::from_str(addr).map_err(|err| format!("Invalid external address {}, err: {}", addr, err)))
+            .collect::<Result<Vec<_>, _>>()?;
+        // the validators of the last block are reserved peers like the bootstrap peers
+        let chain_for_peers = chain.clone();
+        let manager = PeerManager::new(
+            PeerConfig {
+                external_addresses,
+                ..PeerConfig::new(
+                    config.max_inbound_peers,
+                    config.max_outbound_peers,
+                    config.max_inbound_peers + config.max_outbound_peers,
+                )
+            },
+            chain.get_database().map(Schema::new),
+            Box::new(move |address| {
+                let validators = chain_for_peers.get_validators(chain_for_peers.get_last_height());
+                validators.iter().any(|validator| validator.address() == address)
+            }),
+        );
         let (server, _handle) = TcpServer::new(
-            libp2p::PeerId::from_str(&config.peer_id).unwrap(),
+            node_key,
             libp2p::Multiaddr::from_str(&format!("/ip4/{}/tcp/{}", config.ip, config.port)).unwrap(),
-            None,
+            Allowlist::new(allowlist),
+            manager,
             genesis,
             Box::new(author_handshake(genesis)),
-            Box::new(handle_msg_middle(core_handle.clone(), chain.clone())),
+            handle_msg,
         );
-        let local_peer_id = libp2p::PeerId::from_str(&config.peer_id).unwrap();
+        init_api(&config, chain.clone(), tx_pool.clone(), broadcast_bus.clone(), server.clone(), core_handle);
         for bp in &config.bootstrap_peers {
             if let (Ok(peer_id), Ok(multiaddr)) = (
                 libp2p::PeerId::from_str(&bp.peer_id),
                 libp2p::Multiaddr::from_str(&bp.multiaddr),
             ) {
                 if peer_id != local_peer_id {
-                    p2p_event_bus.send(crate::subscriber::P2PEvent::AddPeer(peer_id, vec![multiaddr]));
+                    server.add_reserved_peer(peer_id, vec![multiaddr]);
                 }
             }
         }
@@ -130,7 +174,7 @@ pub fn start_node(config: &str, _sender: Sender<()>) -> Result<(), String> {
                 while let Ok(event) = p2p_rx.recv().await {
                     match event {
                         crate::subscriber::P2PEvent::AddPeer(peer_id, addrs) => server_for_p2p.add_peer(peer_id, addrs),
-                        crate::subscriber::P2PEvent::DropPeer(_, _) => {}
+                        crate::subscriber::P2PEvent::DropPeer(peer_id, addrs) => server_for_p2p.remove_peer(&peer_id, &addrs),
                     }
                 }
             });
@@ -157,7 +201,7 @@ fn init_config(config: &str) -> Result<Config, String> {
 
 fn init_transaction_pool(_: &Config) -> SafeTxPool {
     info!("Init transaction pool successfully");
-    Box::new(BaseTxPool::new()) as SafeTxPool
+    Box::new(TransactionPool::default()) as SafeTxPool
 }
 
 fn init_store(config: &Config) -> Result<Ledger, String> {
@@ -187,21 +231,46 @@ fn init_genesis(chain: &mut Chain) -> ChainResult {
 }
 
 fn start_consensus_engine(
-    _config: &Config,
+    config: &Config,
     key_pair: KeyPair,
     chain: Arc<Chain>,
+    tx_pool: Arc<RwLock<SafeTxPool>>,
     broadcast_bus: BroadcastEventBus,
-) -> (crate::consensus::pbft::core::runner::CoreHandle, SafeEngine) {
-    info!("Init consensus engine");
-    create_bft_engine(key_pair, chain, broadcast_bus)
+) -> (Box<HandleMsgFn>, SafeEngine, Option<CoreHandle>) {
+    info!("Init consensus engine: {:?}", config.engine);
+    match config.engine {
+        EngineKind::Pbft => {
+            let (core_handle, engine) = create_bft_engine(key_pair, chain.clone(), broadcast_bus.clone());
+            let sync = start_sync_service(chain.clone(), broadcast_bus.clone(), engine.seal_verifier(), engine.header_verifier());
+            let handle_msg = handle_msg_middle(core_handle.clone(), chain, sync, engine.header_verifier(), tx_pool, broadcast_bus);
+            (Box::new(handle_msg), engine, Some(core_handle))
+        }
+        EngineKind::Raft => {
+            let (raft_handle, engine) = create_raft_engine(key_pair, chain.clone(), broadcast_bus.clone());
+            let sync = start_sync_service(chain.clone(), broadcast_bus.clone(), engine.seal_verifier(), engine.header_verifier());
+            (Box::new(handle_msg_middle(raft_handle, chain, sync, engine.header_verifier(), tx_pool, broadcast_bus)), engine, None)
+        }
+        EngineKind::Dpos => {
+            let (dpos_handle, engine) = create_dpos_engine(key_pair, chain.clone());
+            let sync = start_sync_service(chain.clone(), broadcast_bus.clone(), engine.seal_verifier(), engine.header_verifier());
+            (Box::new(handle_msg_middle(dpos_handle, chain, sync, engine.header_verifier(), tx_pool, broadcast_bus)), engine, None)
+        }
+    }
 }
 
-fn init_api(config: &Config, chain: Arc<Chain>) {
+fn init_api(
+    config: &Config,
+    chain: Arc<Chain>,
+    tx_pool: Arc<RwLock<SafeTxPool>>,
+    broadcast_bus: BroadcastEventBus,
+    server: TcpServer,
+    core_handle: Option<CoreHandle>,
+) {
     let config = config.clone();
     let chain = chain.clone();
     spawn(move || {
         info!("Start service api");
-        start_api(chain, config.api_ip, config.api_port);
+        start_api(&config, chain, tx_pool, broadcast_bus, server, core_handle);
     });
 }
 
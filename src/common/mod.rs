@@ -7,23 +7,34 @@ use std::env;
 use std::fmt::{self, Display};
 use std::net::{SocketAddr, AddrParseError};
 
-use cryptocurrency_kit::crypto::Hash;
-use cryptocurrency_kit::merkle_tree::MerkleTree;
+use cryptocurrency_kit::crypto::{Hash, EMPTY_HASH};
+use cryptocurrency_kit::merkle_tree::{MerkleProof, MerkleTree};
 use cryptocurrency_kit::storage::values::StorageValue;
 use libp2p::{
     multiaddr::Protocol,
     Multiaddr,
 };
 
+/// The merkle root of the items, `EMPTY_HASH` if there is none
 pub fn merkle_tree_root<T: StorageValue>(input: Vec<T>) -> Hash {
-    let mut v: Vec<Vec<_>> = vec![];
-    for item in input {
-        let bytes = item.into_bytes();
-        v.push(bytes);
-    }
-    let root = MerkleTree::new_merkle_tree(v).root.unwrap();
-//    info!("{:?}, len: {}", &root.data);
-    Hash::from_slice(&root.data).unwrap()
+    let v: Vec<Vec<_>> = input.into_iter().map(|item| item.into_bytes()).collect();
+    MerkleTree::new_merkle_tree(v)
+        .root
+        .map_or(EMPTY_HASH, |root| Hash::from_slice(&root.data).unwrap())
+}
+
+/// The merkle root of the first release, see `MerkleTree::new_legacy_merkle_tree`
+pub fn legacy_merkle_tree_root<T: StorageValue>(input: Vec<T>) -> Hash {
+    let v: Vec<Vec<_>> = input.into_iter().map(|item| item.into_bytes()).collect();
+    MerkleTree::new_legacy_merkle_tree(v)
+        .root
+        .map_or(EMPTY_HASH, |root| Hash::from_slice(&root.data).unwrap())
+}
+
+/// The audit path of the `index`th item to the merkle root
+pub fn merkle_tree_proof<T: StorageValue>(input: Vec<T>, index: usize) -> Option<MerkleProof> {
+    let v: Vec<Vec<_>> = input.into_iter().map(|item| item.into_bytes()).collect();
+    MerkleTree::audit_path(v, index)
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -107,6 +118,15 @@ pub fn string_to_address(s: &String) -> Result<Address, String> {
     Ok(Address::from_str(s).unwrap())
 }
 
+/// Parses a hex string with or without `0x`
+pub fn string_to_hash(s: &str) -> Result<Hash, String> {
+    let bytes = hex::decode(s.trim_start_matches("0x")).map_err(|err| err.to_string())?;
+    if bytes.len() != 32 {
+        return Err(format!("invalid hash length: {}", bytes.len()));
+    }
+    Ok(Hash::new(&bytes))
+}
+
 pub fn strings_to_addresses(strs: &Vec<String>) -> Result<Vec<Address>, String> {
     let mut addresses = Vec::new();
     for str in strs {
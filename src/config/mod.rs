@@ -4,6 +4,8 @@ use toml::value::Table;
 use toml::value::Datetime;
 
 use crate::common::random_dir;
+#[cfg(any(test, feature = "sim"))]
+use crate::consensus::pbft::core::byzantine::ByzantineMode;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct BootstrapPeer {
@@ -11,6 +13,21 @@ pub struct BootstrapPeer {
     pub multiaddr: String,
 }
 
+/// The consensus engine used to seal blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineKind {
+    Pbft,
+    Raft,
+    Dpos,
+}
+
+impl Default for EngineKind {
+    fn default() -> Self {
+        EngineKind::Pbft
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub chain_id: u64,
@@ -22,14 +39,43 @@ pub struct Config {
     pub block_period: Duration,
     #[serde(with = "serde_millis")]
     pub request_time: Duration,
+    /// The peer id of the node key derived from `secret`, a different one is ignored with a warning
+    #[serde(default)]
     pub peer_id: String,
     #[serde(with = "serde_millis")]
     pub ttl: Duration,
     pub store: String,
     pub secret: String,
+    #[serde(default)]
+    pub engine: EngineKind,
     pub genesis: Option<GenesisConfig>,
     #[serde(default)]
     pub bootstrap_peers: Vec<BootstrapPeer>,
+    /// Serves the admin endpoints(`/admin/...`) on `127.0.0.1:admin_port`, they can pause the consensus
+    /// and drop the peers
+    #[serde(default)]
+    pub admin_api: bool,
+    #[serde(default = "default_admin_port")]
+    pub admin_port: u16,
+    /// Makes the pbft validator misbehave, only for the tests and the staging networks built with the `sim` feature
+    #[cfg(any(test, feature = "sim"))]
+    #[serde(default)]
+    pub byzantine: Vec<ByzantineMode>,
+    /// The peer ids of the nodes allowed to connect, anyone can if it is empty
+    #[serde(default)]
+    pub p2p_allowlist: Vec<String>,
+    /// The peers that may connect to the node, the bootstrap peers and the validators are not counted
+    #[serde(default = "default_max_inbound_peers")]
+    pub max_inbound_peers: u64,
+    /// The peers the node dials, the bootstrap peers and the validators are not counted
+    #[serde(default = "default_max_outbound_peers")]
+    pub max_outbound_peers: u64,
+    /// The multiaddrs the peer exchange announces for the node, the listen address if it is empty
+    #[serde(default)]
+    pub external_addresses: Vec<String>,
+    /// Discovers the peers of the local network with the mdns
+    #[serde(default = "default_mdns")]
+    pub mdns: bool,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -40,6 +86,40 @@ pub struct GenesisConfig {
     pub proposer: String,
     pub gas_used: u64,
     pub extra: String,
+    // blocks between two validator set changes
+    #[serde(default = "default_epoch")]
+    pub epoch: u64,
+    // the stake a dpos delegate needs at least to be elected
+    #[serde(default = "default_min_delegate_stake")]
+    pub min_delegate_stake: u64,
+    // the first height whose transaction root pads every odd level of the merkle tree, the chains started
+    // before it was introduced set it above their last block
+    #[serde(default)]
+    pub padded_merkle_height: u64,
+}
+
+fn default_epoch() -> u64 {
+    crate::core::governance::DEFAULT_EPOCH
+}
+
+fn default_min_delegate_stake() -> u64 {
+    crate::consensus::dpos::delegates::DEFAULT_MIN_STAKE
+}
+
+fn default_admin_port() -> u16 {
+    8961
+}
+
+fn default_max_inbound_peers() -> u64 {
+    32
+}
+
+fn default_max_outbound_peers() -> u64 {
+    16
+}
+
+fn default_mdns() -> bool {
+    true
 }
 
 impl Default for Config {
@@ -52,12 +132,22 @@ impl Default for Config {
             api_port: 8960,
             block_period: Duration::from_millis(3 * 1000),
             request_time: Duration::from_millis(3 * 1000),
-            peer_id: "QmbBr2fHwLFKvHkAq1BpbEr4dvR8P6orQxHkVaxeJsJiW8".to_string(),
+            peer_id: String::new(),
             ttl: Duration::from_millis(5 * 1000),
             store: *random_dir(),
             secret: "".into(),
+            engine: EngineKind::Pbft,
             genesis: None,
             bootstrap_peers: Vec::new(),
+            admin_api: false,
+            admin_port: default_admin_port(),
+            #[cfg(any(test, feature = "sim"))]
+            byzantine: Vec::new(),
+            p2p_allowlist: Vec::new(),
+            max_inbound_peers: default_max_inbound_peers(),
+            max_outbound_peers: default_max_outbound_peers(),
+            external_addresses: Vec::new(),
+            mdns: default_mdns(),
         }
     }
 }
@@ -82,4 +172,41 @@ mod tests {
         let key_pair = KeyPair::from_secret(secret).unwrap();
         println!("{:?}, {:?}", key_pair, key_pair.address());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn t_engine_kind() {
+        #[derive(Deserialize)]
+        struct Wrap {
+            #[serde(default)]
+            engine: EngineKind,
+        }
+        let wrap: Wrap = toml::from_str(r#"engine = "raft""#).unwrap();
+        assert_eq!(wrap.engine, EngineKind::Raft);
+        let wrap: Wrap = toml::from_str(r#"engine = "dpos""#).unwrap();
+        assert_eq!(wrap.engine, EngineKind::Dpos);
+        let wrap: Wrap = toml::from_str("").unwrap();
+        assert_eq!(wrap.engine, EngineKind::Pbft);
+    }
+
+    #[test]
+    fn t_byzantine_modes() {
+        #[derive(Deserialize)]
+        struct Wrap {
+            #[serde(default)]
+            byzantine: Vec<ByzantineMode>,
+        }
+        let wrap: Wrap = toml::from_str(
+            r#"
+            [[byzantine]]
+            mode = "withhold_votes"
+            [[byzantine]]
+            mode = "delay"
+            millis = 500
+            "#,
+        )
+        .unwrap();
+        assert_eq!(wrap.byzantine, vec![ByzantineMode::WithholdVotes, ByzantineMode::Delay { millis: 500 }]);
+        let wrap: Wrap = toml::from_str("").unwrap();
+        assert!(wrap.byzantine.is_empty());
+    }
+}
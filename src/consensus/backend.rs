@@ -13,15 +13,15 @@ use lru_time_cache::LruCache;
 
 use super::{
     config::Config,
-    consensus::Engine,
+    consensus::{Engine, HeaderVerifier, SealVerifier},
     pbft::core::runner::CoreHandle,
     error::{EngineError, EngineResult},
     types::Proposal,
     validator::{fn_selector, ImplValidatorSet, ValidatorSet},
 };
 use crate::{
-    common::merkle_tree_root,
     core::chain::Chain,
+    core::governance::is_valid_vote,
     error::ChainError,
     protocol::GossipMessage,
     subscriber::events::{BroadcastEvent, BroadcastEventBus},
@@ -34,8 +34,8 @@ pub trait Backend {
     type ValidatorsType;
     /// address is the current validator's address
     fn address(&self) -> Address;
-    /// validators returns a set of current validator
-    fn validators(&self, height: Height) -> &Self::ValidatorsType;
+    /// validators returns the validator set of the height
+    fn validators(&self, height: Height) -> Self::ValidatorsType;
     /// gossip sends a message to all validators (exclude self)
     fn gossip(&mut self, vals: &dyn ValidatorSet, msg: GossipMessage) -> EngineResult;
     /// commit a proposal with seals
@@ -49,7 +49,7 @@ pub trait Backend {
     fn last_proposal(&self) -> Result<Proposal, ()>;
     fn has_proposal(&self, hash: &Hash, height: Height) -> bool;
     fn get_proposer(&self, height: Height) -> Address;
-    fn parent_validators(&self, proposal: &Proposal) -> &Self::ValidatorsType;
+    fn parent_validators(&self, proposal: &Proposal) -> Self::ValidatorsType;
     fn has_bad_proposal(&self, hash: Hash) -> bool;
 
     fn get_header_by_height(&self, height: Height) -> Option<Header>;
@@ -65,15 +65,9 @@ pub fn new_impl_backend(
     let config = Config {
         request_time: request_time as u64,
         block_period,
-        chain_id: 0,
+        chain_id: chain.config.chain_id,
     };
 
-    let addresses: Vec<Address> = chain
-        .get_validators(chain.get_last_height())
-        .iter()
-        .map(|validator| *validator.address())
-        .collect();
-    let validator_set = ImplValidatorSet::new(&addresses, Box::new(fn_selector));
     let inbound_cache = LruCache::with_capacity(1 << 10);
     let outbound_cache = LruCache::with_capacity(1 << 10);
     let proposed_block_hash = EMPTY_HASH;
@@ -84,7 +78,6 @@ pub fn new_impl_backend(
         broadcast_bus,
         started: false,
         validaor: Validator::new(keypair.address()),
-        validator_set,
         key_pair: keypair,
         inbound_cache,
         outbound_cache,
@@ -101,7 +94,6 @@ pub struct ImplBackend {
     core_handle: Option<CoreHandle>,
     broadcast_bus: BroadcastEventBus,
     validaor: Validator,
-    validator_set: ImplValidatorSet,
     key_pair: KeyPair,
     #[allow(dead_code)]
     inbound_cache: LruCache<Hash, ()>,
@@ -121,8 +113,14 @@ impl Backend for ImplBackend {
         *self.validaor.address()
     }
 
-    fn validators(&self, _: Height) -> &ImplValidatorSet {
-        &self.validator_set
+    fn validators(&self, height: Height) -> ImplValidatorSet {
+        let addresses: Vec<Address> = self
+            .chain
+            .get_validators(height)
+            .iter()
+            .map(|validator| *validator.address())
+            .collect();
+        ImplValidatorSet::new(&addresses, Box::new(fn_selector))
     }
 
     /// TODO
@@ -198,7 +196,7 @@ impl Backend for ImplBackend {
                     return (Duration::from_nanos(0), Err(EngineError::InvalidSignature));
                 }
             }
-            let transaction_hash = merkle_tree_root(transactions);
+            let transaction_hash = self.chain.transactions_root(header.height, transactions);
             if transaction_hash != header.tx_hash {
                 return (
                     Duration::from_nanos(0),
@@ -206,6 +204,9 @@ impl Backend for ImplBackend {
                 );
             }
         }
+        if let Err(err) = self.chain.verify_evidences(block) {
+            return (Duration::from_nanos(0), Err(EngineError::InvalidEvidence(format!("{}", err))));
+        }
         let result = self.verify_header(header, false);
         if let Err(ref err) = result {
             match err {
@@ -220,6 +221,15 @@ impl Backend for ImplBackend {
                 _ => return (Duration::from_nanos(0), result),
             }
         }
+
+        // re-execute the transactions
+        if let Err(err) = self.chain.verify_state(block) {
+            let err = match err {
+                ChainError::InvalidStateRoot(expect, got) => EngineError::InvalidStateRoot(expect, got),
+                err => EngineError::InvalidState(format!("{}", err)),
+            };
+            return (Duration::from_nanos(0), Err(err));
+        }
         (Duration::from_nanos(0), Ok(()))
     }
 
@@ -256,9 +266,8 @@ impl Backend for ImplBackend {
         header.map_or(*EMPTY_ADDRESS, |header| header.proposer)
     }
 
-    // TODO
-    fn parent_validators(&self, _proposal: &Proposal) -> &Self::ValidatorsType {
-        &self.validator_set
+    fn parent_validators(&self, proposal: &Proposal) -> Self::ValidatorsType {
+        self.validators(proposal.block().height() - 1)
     }
 
     /// TODO
@@ -312,6 +321,17 @@ impl Engine for ImplBackend {
         if header.time < parent_header.time + self.config.block_period {
             return Err(EngineError::InvalidTimestamp);
         }
+        if let Some(ref vote) = header.vote {
+            let addresses: Vec<Address> = self
+                .chain
+                .get_validators(header.height)
+                .iter()
+                .map(|validator| *validator.address())
+                .collect();
+            if !is_valid_vote(&addresses, vote) {
+                return Err(EngineError::InvalidVote(vote.address));
+            }
+        }
         if seal {
             self.verify_seal(header)?;
         }
@@ -320,31 +340,39 @@ impl Engine for ImplBackend {
     }
 
     fn verify_seal(&self, header: &Header) -> EngineResult {
+        let validator_set = self.validators(header.height);
+        let maj32 = validator_set.two_thirds_majority();
         // check votes
         {
-            let votes = header.votes.as_ref().ok_or(EngineError::LackVotes(
-                self.validator_set.two_thirds_majority() + 1,
-                header.votes.as_ref().unwrap().len(),
-            ))?;
-            if !votes.verify_signs(CryptoHash::hash(header), |validator| {
-                self.validator_set.get_by_address(validator).is_some()
+            let votes = header
+                .votes
+                .as_ref()
+                .ok_or_else(|| EngineError::LackVotes(maj32 + 1, 0))?;
+            if !votes.verify_signs(header.block_hash(), |validator| {
+                validator_set.get_by_address(validator).is_some()
             })
             {
                 return Err(EngineError::InvalidSignature);
             }
-            let maj32 = self.validator_set.two_thirds_majority();
-            if maj32 + 1 > votes.len() {
-                return Err(EngineError::LackVotes(maj32 + 1, votes.len()));
+            // a validator may sign more than one seal
+            let signers = votes.signers(header.block_hash());
+            if maj32 + 1 > signers.len() {
+                return Err(EngineError::LackVotes(maj32 + 1, signers.len()));
             }
         }
 
         let proposer = header.proposer;
-        self.validator_set
+        validator_set
             .get_by_address(proposer)
-            .ok_or(EngineError::Unknown("proposer is not validators".to_string()))
+            .ok_or(EngineError::Unauthorized)
             .map(|_| ())
     }
 
+    fn header_verifier(&self) -> HeaderVerifier {
+        let backend = self.clone();
+        Arc::new(move |header: &Header| backend.verify_header(header, true))
+    }
+
     fn new_chain_header(&mut self, proposal: &Proposal) -> EngineResult {
         debug!(
             "Backend handle new chain header, hash: {:?}, height: {:?}",
@@ -390,6 +418,11 @@ impl Engine for ImplBackend {
         Ok(())
     }
 
+    fn seal_verifier(&self) -> SealVerifier {
+        let backend = self.clone();
+        Arc::new(move |header: &Header| backend.verify_seal(header))
+    }
+
     fn seal(&mut self, new_block: &mut Block, abort: Receiver<()>) -> EngineResult {
         if !self.started {
             return Err(EngineError::EngineNotStarted);
@@ -10,6 +10,10 @@ use super::{
     pbft::core::core::Core,
     pbft::core::runner::CoreHandle,
     backend::new_impl_backend,
+    raft::core::RaftCore,
+    raft::runner::RaftHandle,
+    raft::backend::new_raft_backend,
+    dpos::backend::{new_dpos_backend, DposHandle},
 };
 
 use crate::{
@@ -28,10 +32,23 @@ pub trait Engine {
     fn prepare(&mut self, header: &mut Header) -> Result<(), String>;
     fn finalize(&mut self, header: &Header) -> Result<(), String>;
     fn seal(&mut self, new_block: &mut Block, abort: Receiver<()>) -> EngineResult;
+    /// Returns a seal checker that can be shared with other threads, e.g. the block sync
+    fn seal_verifier(&self) -> SealVerifier;
+    /// Returns a full header checker(`verify_header` with seal) for the blocks imported from network
+    fn header_verifier(&self) -> HeaderVerifier;
 }
 
 pub type SafeEngine = Box<dyn Engine + Send + Sync>;
 
+pub type SealVerifier = Arc<dyn Fn(&Header) -> EngineResult + Send + Sync>;
+
+pub type HeaderVerifier = Arc<dyn Fn(&Header) -> EngineResult + Send + Sync>;
+
+/// ConsensusHandle feeds the consensus payloads received from network into the engine core
+pub trait ConsensusHandle: Clone + Send + Sync + 'static {
+    fn send_message(&self, payload: Vec<u8>);
+}
+
 pub fn create_bft_engine(key_pair: KeyPair, chain: Arc<Chain>, broadcast_bus: BroadcastEventBus) -> (CoreHandle, SafeEngine) {
     info!("Create bft consensus engine");
     let mut backend = new_impl_backend(key_pair.clone(), chain.clone(), broadcast_bus);
@@ -57,3 +74,33 @@ pub fn create_bft_engine(key_pair: KeyPair, chain: Arc<Chain>, broadcast_bus: Br
     let engine_backend: SafeEngine = Box::new(backend);
     (core_handle, engine_backend)
 }
+
+pub fn create_raft_engine(key_pair: KeyPair, chain: Arc<Chain>, broadcast_bus: BroadcastEventBus) -> (RaftHandle, SafeEngine) {
+    info!("Create raft consensus engine");
+    let (core_tx, core_rx) = crossbeam::channel::unbounded();
+    let (commit_tx, commit_rx) = crossbeam::channel::unbounded();
+    let raft_handle = RaftHandle::new(core_tx);
+    let raft_handle_for_run = raft_handle.clone();
+
+    let chain_clone = chain.clone();
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().expect("runtime");
+        rt.block_on(RaftCore::run(
+            chain_clone,
+            broadcast_bus,
+            key_pair,
+            commit_tx,
+            core_rx,
+            raft_handle_for_run,
+        ));
+    });
+
+    let engine_backend: SafeEngine = Box::new(new_raft_backend(chain, raft_handle.clone(), commit_rx));
+    (raft_handle, engine_backend)
+}
+
+pub fn create_dpos_engine(key_pair: KeyPair, chain: Arc<Chain>) -> (DposHandle, SafeEngine) {
+    info!("Create dpos consensus engine");
+    let engine_backend: SafeEngine = Box::new(new_dpos_backend(key_pair, chain));
+    (DposHandle, engine_backend)
+}
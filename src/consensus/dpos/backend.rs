@@ -0,0 +1,247 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crossbeam::channel::Receiver;
+use cryptocurrency_kit::ethkey::{verify_address, Address, KeyPair, Message};
+use parking_lot::Mutex;
+
+use super::delegates::{get_active_delegates, get_block_slot_data, sync_delegates, validate_block_slot};
+use super::slot::{self, Slot};
+use crate::{
+    consensus::config::Config,
+    consensus::consensus::{ConsensusHandle, Engine, HeaderVerifier, SealVerifier},
+    consensus::error::{EngineError, EngineResult},
+    consensus::types::Proposal,
+    core::chain::Chain,
+    error::ChainError,
+    types::block::{Block, Header},
+    types::votes::Votes,
+    types::Timestamp,
+};
+
+/// DPoS exchanges no consensus message, the blocks are spread by the block gossip
+#[derive(Clone)]
+pub struct DposHandle;
+
+impl ConsensusHandle for DposHandle {
+    fn send_message(&self, _payload: Vec<u8>) {
+        trace!("DPoS ignores the consensus message");
+    }
+}
+
+pub fn new_dpos_backend(key_pair: KeyPair, chain: Arc<Chain>) -> DposBackend {
+    let config = Config {
+        request_time: chain.config.request_time.as_millis() as u64,
+        block_period: chain.config.block_period.as_secs(),
+        chain_id: chain.config.chain_id,
+    };
+    let slot = Slot::new(chain.get_genesis().header().time, config.block_period);
+    DposBackend {
+        key_pair,
+        chain,
+        slot,
+        started: false,
+        config,
+        apply_lock: Arc::new(Mutex::new(())),
+    }
+}
+
+/// Every active delegate produces a block at its own slot, the block is sealed by the
+/// signature of the producer.
+#[derive(Clone)]
+pub struct DposBackend {
+    key_pair: KeyPair,
+    chain: Arc<Chain>,
+    slot: Slot,
+    started: bool,
+    config: Config,
+    // serialize the delegate state updates
+    apply_lock: Arc<Mutex<()>>,
+}
+
+impl DposBackend {
+    fn active_delegates(&self, round: u64) -> Vec<Address> {
+        let _guard = self.apply_lock.lock();
+        get_active_delegates(&self.chain, &self.slot, round)
+    }
+
+    fn verify_slot_owner(&self, header: &Header) -> EngineResult {
+        let _guard = self.apply_lock.lock();
+        if validate_block_slot(&self.chain, &self.slot, header) {
+            Ok(())
+        } else {
+            Err(EngineError::Unauthorized)
+        }
+    }
+
+    // the first slot owned by local node from `from`, only look forward two rounds
+    fn next_own_slot(&self, from: u64) -> Option<u64> {
+        let address = self.key_pair.address();
+        (from..from + 2 * slot::DELEGATES).find(|slot_number| {
+            let delegates = self.active_delegates(slot::calc_round(*slot_number));
+            get_block_slot_data(&delegates, *slot_number) == Some(address)
+        })
+    }
+
+    // returns false if aborted
+    fn wait_until(&self, target: Timestamp, abort: &Receiver<()>) -> bool {
+        loop {
+            if abort.try_recv().is_ok() {
+                return false;
+            }
+            let now_ms = chrono::Local::now().timestamp_millis() as u64;
+            let target_ms = target * 1000;
+            if now_ms >= target_ms {
+                return true;
+            }
+            ::std::thread::sleep(Duration::from_millis((target_ms - now_ms).min(100)));
+        }
+    }
+}
+
+impl Engine for DposBackend {
+    fn start(&mut self) -> Result<(), String> {
+        if self.started {
+            panic!("Engine start only once");
+        }
+        {
+            let _guard = self.apply_lock.lock();
+            sync_delegates(&self.chain, &self.slot);
+        }
+        self.started = true;
+        info!("DPoS engine start successfully");
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), String> {
+        self.started = false;
+        Ok(())
+    }
+
+    fn author(&self, header: &Header) -> Result<Address, String> {
+        Ok(header.proposer)
+    }
+
+    fn verify_header(&self, header: &Header, seal: bool) -> EngineResult {
+        if header.height == 0 {
+            return Err(EngineError::InvalidHeight);
+        }
+        let parent_header = {
+            self.chain
+                .get_header_by_height(header.height - 1)
+                .ok_or(EngineError::UnknownAncestor(header.height, header.height - 1))?
+        };
+        if parent_header.block_hash() != header.prev_hash {
+            return Err(EngineError::Unknown(
+                format!("parent hash({:?}) != heaer.prev hash({:?})", parent_header.block_hash(), header.prev_hash),
+            ));
+        }
+        if header.time < parent_header.time + self.config.block_period {
+            return Err(EngineError::InvalidTimestamp);
+        }
+        // one block per slot
+        if header.height > 1 && self.slot.get_slot_number(header.time) <= self.slot.get_slot_number(parent_header.time) {
+            return Err(EngineError::InvalidTimestamp);
+        }
+        if seal {
+            self.verify_seal(header)?;
+        } else {
+            self.verify_slot_owner(header)?;
+        }
+        Ok(())
+    }
+
+    // the seal is the signature of the block hash by the owner of the slot
+    fn verify_seal(&self, header: &Header) -> EngineResult {
+        self.verify_slot_owner(header)?;
+        let votes = header.votes.as_ref().ok_or(EngineError::LackVotes(1, 0))?;
+        if votes.len() != 1 {
+            return Err(EngineError::LackVotes(1, votes.len()));
+        }
+        let message = Message::from_slice(header.block_hash().as_ref());
+        match verify_address(&header.proposer, &votes.votes()[0], &message) {
+            Ok(true) => Ok(()),
+            _ => Err(EngineError::InvalidSignature),
+        }
+    }
+
+    fn new_chain_header(&mut self, _proposal: &Proposal) -> EngineResult {
+        if !self.started {
+            return Err(EngineError::EngineNotStarted);
+        }
+        Ok(())
+    }
+
+    fn prepare(&mut self, header: &mut Header) -> Result<(), String> {
+        self.chain
+            .get_header_by_height(header.height - 1)
+            .ok_or("not found parent block for the header".to_string())?;
+        header.votes = None;
+        Ok(())
+    }
+
+    fn finalize(&mut self, _header: &Header) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn header_verifier(&self) -> HeaderVerifier {
+        let backend = self.clone();
+        Arc::new(move |header: &Header| backend.verify_header(header, true))
+    }
+
+    fn seal_verifier(&self) -> SealVerifier {
+        let backend = self.clone();
+        Arc::new(move |header: &Header| backend.verify_seal(header))
+    }
+
+    fn seal(&mut self, new_block: &mut Block, abort: Receiver<()>) -> EngineResult {
+        if !self.started {
+            return Err(EngineError::EngineNotStarted);
+        }
+
+        let parent_header = self
+            .chain
+            .get_header_by_height(new_block.height() - 1)
+            .ok_or(EngineError::UnknownAncestor(new_block.height(), new_block.height() - 1))?;
+        let from = self
+            .slot
+            .get_slot_number(new_block.header().time)
+            .max(self.slot.get_slot_number(parent_header.time) + 1);
+        let own_slot = match self.next_own_slot(from) {
+            Some(own_slot) => own_slot,
+            None => {
+                // not an active delegate, wait for the others' blocks
+                let deadline = self.slot.get_slot_time(slot::get_last_slot(from));
+                self.wait_until(deadline, &abort);
+                return Err(EngineError::Interrupt);
+            }
+        };
+
+        let header = new_block.mut_header();
+        header.time = self.slot.get_slot_time(own_slot);
+        self.prepare(header).map_err(EngineError::Unknown)?;
+        header.cache_hash(None);
+        info!(
+            "⛏️⛏️⛏👷️ Minnig next block, hash:{:?}, height:{:?}, slot: {}",
+            header.block_hash().short(), header.height, own_slot);
+        if !self.wait_until(header.time, &abort) {
+            trace!("seal abort, height={}", header.height);
+            return Err(EngineError::Interrupt);
+        }
+
+        let signature = header
+            .block_hash()
+            .sign(self.key_pair.secret())
+            .map_err(|err| EngineError::Unknown(format!("{:?}", err)))?;
+        header.votes = Some(Votes::new(vec![signature]));
+
+        match self.chain.insert_block(new_block) {
+            Ok(()) => {
+                self.finalize(new_block.header()).map_err(EngineError::Unknown)?;
+                Ok(())
+            }
+            Err(ChainError::Exists(_)) => Err(EngineError::Interrupt),
+            Err(err) => Err(EngineError::Unknown(format!("{}", err))),
+        }
+    }
+}
@@ -1,80 +1,231 @@
-use crate::types::{Height, transaction::Transaction, block::Block};
+use cryptocurrency_kit::ethkey::Address;
 
-use super::{util, slot};
+use crate::{
+    core::chain::Chain,
+    store::schema::Schema,
+    types::{block::{Block, Header}, transaction::Transaction, Height, ValidatorArray},
+};
 
-// TODO: add_delegate
-fn add_delegate() {}
+use super::slot::{self, Slot};
+use super::types::{Ballot, Delegate, DelegateOp};
 
-/// Generates delegates list and checks if block generator publicKey maches delegate id.
-pub fn validate_block_slot(block: Block) -> bool {
-    // get the height delegates list
-    let block_height = block.height();
-    let delegates = slot::get_active_delegates(block_height);
-    let slot_time = block.header().time as i64;
+/// The stake a delegate needs at least to be elected, if `[genesis] min_delegate_stake` is not set
+pub const DEFAULT_MIN_STAKE: u64 = 10_000;
 
-    let current_slot = slot::get_slot_number(slot_time);
-    let idx = current_slot as usize % delegates.len();
-    let delegate_id = delegates[idx];
+pub fn add_delegate(schema: &Schema, address: Address, height: Height) -> bool {
+    let mut delegates = schema.dpos_delegates();
+    if delegates.contains(&address) {
+        return false;
+    }
+    delegates.put(&address, Delegate { address, stake: 0, register_height: height });
+    true
+}
+
+fn remove_ballot(schema: &Schema, voter: &Address) {
+    let mut ballots = schema.dpos_ballots();
+    if let Some(ballot) = ballots.get(voter) {
+        let mut delegates = schema.dpos_delegates();
+        if let Some(mut delegate) = delegates.get(&ballot.delegate) {
+            delegate.stake = delegate.stake.saturating_sub(ballot.stake);
+            delegates.put(&ballot.delegate, delegate);
+        }
+        ballots.remove(voter);
+    }
+}
+
+/// Applies the delegate operation carried by the transaction, the others are ignored.
+pub fn apply_transaction(schema: &Schema, transaction: &Transaction, height: Height) {
+    let op = match DelegateOp::from_payload(transaction.payload()) {
+        Some(op) => op,
+        None => return,
+    };
+    let sender = match transaction.sender() {
+        Some(sender) => sender,
+        None => return,
+    };
+    match op {
+        DelegateOp::Register => {
+            if add_delegate(schema, sender, height) {
+                debug!("Register a new delegate, address: {:?}, height: {}", sender, height);
+            }
+        }
+        DelegateOp::Vote(delegate) => {
+            let mut delegates = schema.dpos_delegates();
+            if !delegates.contains(&delegate) {
+                return;
+            }
+            remove_ballot(schema, &sender);
+            let mut candidate = delegates.get(&delegate).unwrap();
+            candidate.stake = candidate.stake.saturating_add(transaction.amount());
+            delegates.put(&delegate, candidate);
+            schema.dpos_ballots().put(&sender, Ballot { delegate, stake: transaction.amount() });
+        }
+        DelegateOp::Unvote => remove_ballot(schema, &sender),
+    }
+}
+
+/// Elects the top `DELEGATES` delegates by stake, ties broken by address.
+/// Falls back to the genesis validators until `DELEGATES` delegates have the minimum stake.
+pub fn elect_delegates(schema: &Schema, fallback: &[Address], min_stake: u64) -> Vec<Address> {
+    let mut candidates: Vec<Delegate> = schema
+        .dpos_delegates()
+        .values()
+        .filter(|delegate| delegate.stake > 0 && delegate.stake >= min_stake)
+        .collect();
+    if candidates.len() < slot::DELEGATES as usize {
+        return fallback.to_vec();
+    }
+    candidates.sort_by(|a, b| b.stake.cmp(&a.stake).then(a.address.cmp(&b.address)));
+    candidates
+        .into_iter()
+        .take(slot::DELEGATES as usize)
+        .map(|delegate| delegate.address)
+        .collect()
+}
+
+/// The active set of a round is fixed by the state before the first block of the round.
+pub fn apply_block(schema: &Schema, slot: &Slot, block: &Block, fallback: &[Address], min_stake: u64) {
+    let round = slot::calc_round(slot.get_slot_number(block.header().time));
+    let mut active_delegates = schema.dpos_active_delegates();
+    if !active_delegates.contains(&round) {
+        let elected = elect_delegates(schema, fallback, min_stake);
+        active_delegates.put(&round, ValidatorArray::new(elected));
+    }
+    block
+        .transactions()
+        .iter()
+        .for_each(|transaction| apply_transaction(schema, transaction, block.height()));
+    schema.dpos_applied_height().set(block.height());
+}
+
+fn genesis_delegates(chain: &Chain) -> Vec<Address> {
+    chain
+        .get_validators(0)
+        .iter()
+        .map(|validator| *validator.address())
+        .collect()
+}
 
-    util::equal_delegate(delegate_id, &block.header().proposer.as_bytes())
+fn min_stake(chain: &Chain) -> u64 {
+    chain
+        .config
+        .genesis
+        .as_ref()
+        .map_or(DEFAULT_MIN_STAKE, |genesis| genesis.min_delegate_stake)
 }
 
+/// Catches the delegate state up with the chain
+pub fn sync_delegates(chain: &Chain, slot: &Slot) {
+    let fallback = genesis_delegates(chain);
+    let min_stake = min_stake(chain);
+    let last_height = chain.get_last_height();
+    let mut applied = chain
+        .get_ledger()
+        .read()
+        .get_schema()
+        .dpos_applied_height()
+        .get()
+        .unwrap_or(0);
+    while applied < last_height {
+        let block = match chain.get_block_by_height(applied + 1) {
+            Some(block) => block,
+            None => break,
+        };
+        // the delegate state is written, like the blocks
+        let ledger = chain.get_ledger().write();
+        apply_block(ledger.get_schema(), slot, &block, &fallback, min_stake);
+        applied += 1;
+    }
+}
 
-//// TODO: Opz
-//pub fn generate_delegate_list<'a>(height: Height) -> (Timespec, Vec<&'a str>){
-//   let generator_id = slot::get_active_delegates(height);
-//}
+pub fn get_active_delegates(chain: &Chain, slot: &Slot, round: u64) -> Vec<Address> {
+    sync_delegates(chain, slot);
+    let fallback = genesis_delegates(chain);
+    let ledger = chain.get_ledger().read();
+    let schema = ledger.get_schema();
+    match schema.dpos_active_delegates().get(&round) {
+        Some(delegates) => delegates.addresses().clone(),
+        None => elect_delegates(schema, &fallback, min_stake(chain)),
+    }
+}
 
-pub fn get_block_slot_data<'a>(slot: i64, height: Height) -> Option<(&'a str, i64)> {
-    let current_slot = slot;
-    let delegates = slot::get_active_delegates(height);
-    let delegate_pos = current_slot % slot::DELEGATES;
-    let delegate_id = delegates.get(delegate_pos as usize);
-    Some((delegate_id.unwrap(), slot::get_slot_time(slot)))
+/// Returns the delegate who owns the slot
+pub fn get_block_slot_data(delegates: &[Address], slot_number: u64) -> Option<Address> {
+    if delegates.is_empty() {
+        return None;
+    }
+    let delegate_pos = (slot_number % slot::DELEGATES) as usize % delegates.len();
+    delegates.get(delegate_pos).cloned()
 }
 
-// TODO:
-// height --> slot_list
-// 根据当前的slot，找到高度为height的相应见证人id以及相对应的slot
-//
-//fn get_block_slot_data<'a>(slot: i64, height: Height) -> Option<(&'a str, i64)> {
-//    let current_slot = slot;
-//    let last_slot = slot::get_last_slot(current_slot);
-//    let delegates = slot::get_active_delegates(height);
-//
-//    for _slot in current_slot..last_slot {
-//        let delegate_pos = _slot % slot::DELEGATES;
-//        let deletegate_id = delegates.get(delegate_pos as usize);
-//        if deletegate_id.is_none() {
-//            continue;
-//        }
-//        return Some((deletegate_id.unwrap(), slot::get_slot_time(_slot)));
-//    }
-//    None
-//}
-
-//
-//fn get_keys_sort_by_vote() {
-//
-//}
-//
-//// TODO
-//fn get_accounts(_: String) {
-//
-//}
+/// Generates delegates list and checks if block generator maches delegate id.
+pub fn validate_block_slot(chain: &Chain, slot: &Slot, header: &Header) -> bool {
+    let slot_number = slot.get_slot_number(header.time);
+    let delegates = get_active_delegates(chain, slot, slot::calc_round(slot_number));
+    get_block_slot_data(&delegates, slot_number) == Some(header.proposer)
+}
 
 #[cfg(test)]
 mod tests {
-    use std::io::{self, Write};
+    use std::sync::Arc;
+
+    use cryptocurrency_kit::ethkey::{Generator, KeyPair, Random};
+    use kvdb_rocksdb::Database;
+
     use super::*;
+    use crate::common::random_dir;
+    use crate::store::schema::database_config;
+
+    fn delegate_tx(key_pair: &KeyPair, amount: u64, op: DelegateOp) -> Transaction {
+        let mut transaction = Transaction::new(0, key_pair.address(), amount, 0, 0, op.into_payload());
+        transaction.sign(0, key_pair.secret());
+        transaction
+    }
 
     #[test]
-    fn test_get_block_slot_data() {
-        for height in 1..12 {
-            let slot = height - 1;
-            let (delegate_id, slot_time) = get_block_slot_data(slot, Height(height as u64)).unwrap();
-            let slot_number = super::slot::get_slot_number(slot_time);
-            writeln!(io::stdout(), "deletegate_id: {}, slot_number: {}, slot_time: {}", delegate_id, slot_number, slot_time);
+    fn test_elect_delegates() {
+        let db = Arc::new(Database::open(&database_config(), &random_dir()).unwrap());
+        let schema = Schema::new(db);
+        let fallback = vec![Address::from(1)];
+        assert_eq!(elect_delegates(&schema, &fallback, 10), fallback);
+
+        let (a, b, voter) = (Random.generate().unwrap(), Random.generate().unwrap(), Random.generate().unwrap());
+        apply_transaction(&schema, &delegate_tx(&a, 0, DelegateOp::Register), 1);
+        apply_transaction(&schema, &delegate_tx(&b, 0, DelegateOp::Register), 1);
+        // a zero stake registration does not take over the genesis validators
+        assert_eq!(elect_delegates(&schema, &fallback, 10), fallback);
+
+        // the other seats
+        for _ in 2..slot::DELEGATES {
+            let (delegate, voter) = (Random.generate().unwrap(), Random.generate().unwrap());
+            apply_transaction(&schema, &delegate_tx(&delegate, 0, DelegateOp::Register), 1);
+            apply_transaction(&schema, &delegate_tx(&voter, 10, DelegateOp::Vote(delegate.address())), 1);
         }
+        apply_transaction(&schema, &delegate_tx(&voter, 100, DelegateOp::Vote(b.address())), 2);
+        // a has no stake
+        assert_eq!(elect_delegates(&schema, &fallback, 10), fallback);
+        let backer = Random.generate().unwrap();
+        apply_transaction(&schema, &delegate_tx(&backer, 10, DelegateOp::Vote(a.address())), 2);
+        let elected = elect_delegates(&schema, &fallback, 10);
+        assert_eq!(elected.len(), slot::DELEGATES as usize);
+        assert_eq!(elected[0], b.address());
+
+        // move the ballot, b falls below the minimum
+        apply_transaction(&schema, &delegate_tx(&voter, 50, DelegateOp::Vote(a.address())), 3);
+        assert_eq!(schema.dpos_delegates().get(&a.address()).unwrap().stake, 60);
+        assert_eq!(schema.dpos_delegates().get(&b.address()).unwrap().stake, 0);
+        assert_eq!(elect_delegates(&schema, &fallback, 10), fallback);
+
+        apply_transaction(&schema, &delegate_tx(&voter, 0, DelegateOp::Unvote), 4);
+        assert_eq!(schema.dpos_delegates().get(&a.address()).unwrap().stake, 10);
+    }
+
+    #[test]
+    fn test_get_block_slot_data() {
+        let delegates = vec![Address::from(1), Address::from(2), Address::from(3)];
+        assert_eq!(get_block_slot_data(&delegates, 0), Some(Address::from(1)));
+        assert_eq!(get_block_slot_data(&delegates, 4), Some(Address::from(2)));
+        assert_eq!(get_block_slot_data(&delegates, slot::DELEGATES), Some(Address::from(1)));
+        assert_eq!(get_block_slot_data(&[], 1), None);
     }
 }
@@ -1,3 +1,4 @@
+pub mod backend;
 pub mod delegates;
 pub mod slot;
-pub mod util;
\ No newline at end of file
+pub mod types;
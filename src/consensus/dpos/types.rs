@@ -0,0 +1,47 @@
+use cryptocurrency_kit::crypto::{hash, CryptoHash, Hash};
+use cryptocurrency_kit::ethkey::Address;
+use cryptocurrency_kit::storage::values::StorageValue;
+
+use std::borrow::Cow;
+
+use crate::types::Height;
+
+/// Delegate operations, carried by the transaction payload as json.
+///
+/// The stake of a vote is the amount of the transaction, it is locked on the voter's account until
+/// the next vote or unvote. Every account holds only one ballot, voting again moves the ballot to the new delegate.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DelegateOp {
+    Register,
+    Vote(Address),
+    Unvote,
+}
+
+impl DelegateOp {
+    pub fn from_payload(payload: &[u8]) -> Option<DelegateOp> {
+        serde_json::from_slice(payload).ok()
+    }
+
+    pub fn into_payload(self) -> Vec<u8> {
+        serde_json::to_vec(&self).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delegate {
+    pub address: Address,
+    pub stake: u64,
+    pub register_height: Height,
+}
+
+implement_cryptohash_traits! {Delegate}
+implement_storagevalue_traits! {Delegate}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ballot {
+    pub delegate: Address,
+    pub stake: u64,
+}
+
+implement_cryptohash_traits! {Ballot}
+implement_storagevalue_traits! {Ballot}
@@ -1,5 +1,6 @@
 
 use cryptocurrency_kit::crypto::Hash;
+use cryptocurrency_kit::ethkey::Address;
 
 use crate::types::Height;
 
@@ -50,10 +51,18 @@ pub enum EngineError {
     InvalidTimestamp,
     #[fail(display = "Invalid transaction hash, expect: {:?}, got: {:?}", _0, _1)]
     InvalidTransactionHash(Hash, Hash),
+    #[fail(display = "Invalid state root, expect: {:?}, got: {:?}", _0, _1)]
+    InvalidStateRoot(Hash, Hash),
+    #[fail(display = "Invalid state transition, ({})", _0)]
+    InvalidState(String),
     #[fail(display = "Unauthorized")]
     Unauthorized,
     #[fail(display = "Lack votes, expect: {}, got: {}", _0, _1)]
     LackVotes(usize, usize),
+    #[fail(display = "Invalid validator vote, candidate: {:?}", _0)]
+    InvalidVote(Address),
+    #[fail(display = "Invalid evidence, ({})", _0)]
+    InvalidEvidence(String),
     #[fail(display = "Block in the future")]
     FutureBlock,
     #[fail(display = "Invalid block number")]
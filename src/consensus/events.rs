@@ -7,6 +7,11 @@ use super::types::{Proposal, View};
 pub enum OpCMD {
     Stop,
     Ping,
+    /// Stops handling the messages and the timers until `Resume`, the ones received meanwhile are dropped
+    Pause,
+    Resume,
+    /// Sends a round change for the next round as if the round change timer fired
+    RoundChange,
 }
 
 #[derive(Debug)]
@@ -7,3 +7,5 @@ pub mod backend;
 pub mod engine;
 pub mod error;
 pub mod pbft;
+pub mod dpos;
+pub mod raft;
@@ -0,0 +1,126 @@
+//! Byzantine behaviours of a validator, they test the consensus against misbehaving validators
+//! in the simulation and the staging networks. They are built only for the tests or with the `sim` feature.
+
+use std::borrow::Cow;
+use std::time::Duration;
+
+use cryptocurrency_kit::crypto::{hash, EMPTY_HASH};
+use cryptocurrency_kit::storage::values::StorageValue;
+
+use super::core::CoreState;
+use crate::{
+    consensus::types::{PrePrepare, RoundChange, Subject, View},
+    protocol::{GossipMessage, MessageType},
+    types::votes::encrypt_commit_bytes,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ByzantineMode {
+    /// proposes a second block of the same view along with every proposal
+    EquivocatePreprepare,
+    /// sends a prepare or commit for another digest along with every honest one
+    ConflictingVotes,
+    /// sends no prepare and commit
+    WithholdVotes,
+    /// holds every message back before it is gossiped
+    Delay { millis: u64 },
+    /// proposes blocks with a corrupted state root
+    InvalidProposal,
+    /// sends round changes for the next rounds along with every message
+    SpamRoundChange { rounds: u64 },
+}
+
+impl CoreState {
+    /// The messages gossiped instead of the signed one, the conflicting ones are signed here
+    /// and never recorded by the wal.
+    pub(crate) fn byzantine_messages(&self, msg: GossipMessage) -> Vec<GossipMessage> {
+        let mut messages = vec![];
+        match msg.code {
+            MessageType::Preprepare => {
+                let honest = if self.is_byzantine(&ByzantineMode::InvalidProposal) {
+                    self.tamper_preprepare(&msg, |header| header.root = hash(header.root.as_ref()))
+                } else {
+                    msg
+                };
+                if self.is_byzantine(&ByzantineMode::EquivocatePreprepare) {
+                    // the other block differs in the extra only, so it is valid as well
+                    let other = self.tamper_preprepare(&honest, |header| {
+                        let mut extra = header.extra.clone().unwrap_or_default();
+                        extra.extend_from_slice(b" equivocation");
+                        header.extra = Some(extra);
+                    });
+                    messages.push(other);
+                }
+                messages.push(honest);
+            }
+            MessageType::Prepare | MessageType::Commit => {
+                if self.is_byzantine(&ByzantineMode::WithholdVotes) {
+                    return messages;
+                }
+                if self.is_byzantine(&ByzantineMode::ConflictingVotes) {
+                    messages.push(self.conflicting_vote(&msg));
+                }
+                messages.push(msg);
+            }
+            MessageType::RoundChange => messages.push(msg),
+        }
+
+        let spam = self.byzantine.iter().find_map(|mode| match mode {
+            ByzantineMode::SpamRoundChange { rounds } => Some(*rounds),
+            _ => None,
+        });
+        if let Some(rounds) = spam {
+            let view = self.current_view();
+            for round in view.round + 1..=view.round + rounds {
+                let round_change = RoundChange {
+                    view: View::new(view.height, round),
+                    digest: EMPTY_HASH,
+                    prepared: None,
+                };
+                let spam = GossipMessage::new(MessageType::RoundChange, round_change.into_bytes(), None);
+                messages.push(self.sign(spam));
+            }
+        }
+        messages
+    }
+
+    /// How long every message is held back
+    pub(crate) fn byzantine_delay(&self) -> Option<Duration> {
+        self.byzantine.iter().find_map(|mode| match mode {
+            ByzantineMode::Delay { millis } => Some(Duration::from_millis(*millis)),
+            _ => None,
+        })
+    }
+
+    pub(crate) fn is_byzantine(&self, mode: &ByzantineMode) -> bool {
+        self.byzantine.contains(mode)
+    }
+
+    fn sign(&self, mut msg: GossipMessage) -> GossipMessage {
+        msg.signature = None;
+        self.finalize_message(&mut msg).unwrap();
+        msg
+    }
+
+    fn tamper_preprepare<F>(&self, msg: &GossipMessage, tamper: F) -> GossipMessage
+    where
+        F: FnOnce(&mut crate::types::block::Header),
+    {
+        let mut preprepare: PrePrepare = PrePrepare::from_bytes(Cow::from(msg.msg()));
+        let header = preprepare.proposal.0.mut_header();
+        tamper(header);
+        header.cache_hash(None);
+        self.sign(GossipMessage::new(MessageType::Preprepare, preprepare.into_bytes(), None))
+    }
+
+    fn conflicting_vote(&self, msg: &GossipMessage) -> GossipMessage {
+        let mut subject: Subject = Subject::from_bytes(Cow::from(msg.msg()));
+        subject.digest = hash(subject.digest.as_ref());
+        let seal = match msg.code {
+            MessageType::Commit => Some(encrypt_commit_bytes(&subject.digest, self.keypair.secret())),
+            _ => None,
+        };
+        self.sign(GossipMessage::new(msg.code.clone(), subject.into_bytes(), seal))
+    }
+}
@@ -77,7 +77,7 @@ impl HandleCommit for CoreState {
         if self.current_state.commits.len() > val_set.two_thirds_majority()
             && self.state < State::Committed
         {
-            self.current_state.lock_hash();
+            self.lock_proposal();
             self.commit();
         }
         Ok(())
@@ -1,12 +1,14 @@
 use cryptocurrency_kit::storage::values::StorageValue;
 use cryptocurrency_kit::ethkey::Address;
 use cryptocurrency_kit::ethkey::KeyPair;
+use cryptocurrency_kit::crypto::Hash;
 use libp2p::PeerId;
+use parking_lot::RwLock;
 use tokio::sync::mpsc;
 
 use crossbeam::channel::Receiver as CrossbeamReceiver;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::time::Duration;
 use std::time::Instant;
 use std::sync::Arc;
@@ -19,25 +21,45 @@ use super::{
     round_change_set::RoundChangeSet,
     round_state::RoundState,
     runner::{CoreHandle, CoreMessage},
+    timer::{CoreTimer, TimerKind, TokioTimer},
+    wal::ConsensusWal,
+    justification::{highest_prepared, new_prepared_certificate},
+    equivocation::EquivocationDetector,
 };
+#[cfg(any(test, feature = "sim"))]
+use super::byzantine::ByzantineMode;
 use crate::{
     core::chain::Chain,
+    core::tx_pool::{submit_transaction, SafeTxPool},
+    metrics,
     consensus::validator::fn_selector,
     consensus::backend::{Backend, ImplBackend},
+    consensus::consensus::{ConsensusHandle, HeaderVerifier},
     consensus::config::Config,
     consensus::error::{ConsensusError, ConsensusResult},
     consensus::events::{OpCMD, MessageEvent, NewHeaderEvent, FinalCommittedEvent, BackLogEvent, TimerEvent},
-    consensus::types::{Proposal, Request as CSRequest, Round, View},
+    consensus::types::{PreparedCertificate, Proposal, Request as CSRequest, Round, View},
     consensus::validator::{ImplValidatorSet, ValidatorSet},
     p2p::protocol::{RawMessage, P2PMsgCode},
     protocol::{GossipMessage, MessageType, State},
-    types::Validator,
+    types::{Validator, Validators},
     types::block::Blocks,
+    types::evidence::Evidence,
+    types::transaction::Transactions,
     types::Height,
-    subscriber::events::ChainEvent,
+    subscriber::events::{BroadcastEvent, BroadcastEventBus, ChainEvent},
+    sync::service::SyncHandle,
+    error::{ChainError, P2PError},
 };
 
-pub fn handle_msg_middle(core_handle: CoreHandle, chain: Arc<Chain>) -> impl Fn(PeerId, RawMessage) -> Result<(), String> + Clone {
+pub fn handle_msg_middle<H: ConsensusHandle>(
+    core_handle: H,
+    chain: Arc<Chain>,
+    sync: SyncHandle,
+    verify_header: HeaderVerifier,
+    tx_pool: Arc<RwLock<SafeTxPool>>,
+    broadcast_bus: BroadcastEventBus,
+) -> impl Fn(PeerId, RawMessage) -> Result<(), P2PError> + Clone {
     move |peer_id: PeerId, msg: RawMessage| {
         let header = msg.header();
         let payload = msg.payload().to_vec();
@@ -47,42 +69,49 @@ pub fn handle_msg_middle(core_handle: CoreHandle, chain: Arc<Chain>) -> impl Fn(
                 // Note: FutureBlockMessage retry is handled inside Core; message is processed async
             }
             P2PMsgCode::Block => {
-                let blocks: Blocks = Blocks::from_bytes(Cow::from(&payload));
+                let blocks: Blocks = serde_json::from_slice(&payload).map_err(|err| P2PError::Violation(err.to_string()))?;
                 debug!("Receive a batch block from network, size:{:?}", blocks.0.len());
-                // TODO FIXME
-                blocks.0.iter().for_each(|block| {
-                    chain.insert_block(block);
-                });
+                for block in &blocks.0 {
+                    match chain.import_block(block, &verify_header) {
+                        Ok(()) | Err(ChainError::Exists(_)) | Err(ChainError::UnknownAncestor(_)) => {}
+                        // a forged seal or signature, the others may be a fork or a clock skew
+                        Err(err @ ChainError::InvalidSeal(_))
+                        | Err(err @ ChainError::InvalidProposer(_))
+                        | Err(err @ ChainError::LackVotes(..))
+                        | Err(err @ ChainError::InvalidTransaction(_)) => {
+                            warn!("Reject the block from {}, height: {}, err: {}", peer_id.to_base58(), block.height(), err);
+                            return Err(P2PError::Violation(format!("{}", err)));
+                        }
+                        Err(err) => {
+                            warn!("Reject the block from {}, height: {}, err: {}", peer_id.to_base58(), block.height(), err);
+                            return Err(P2PError::Handle(format!("{}", err)));
+                        }
+                    }
+                }
             }
             P2PMsgCode::Sync => {
-                let height = Height::from_bytes(Cow::from(&payload));
-                debug!("Receive a new sync event from network, height: {:?}", height);
-
-                let last_height = chain.get_last_height();
-                let mut total = 0;
-                let mut batch = 0;
-                let mut blocks = Blocks(vec![]);
-                for height in height..last_height + 1  {
-                    if let Some(block) = chain.get_block_by_height(height) {
-                        blocks.0.push(block);
-                    }
-                    if batch > 20 {
-                        chain.post_event(ChainEvent::PostBlock(Some(peer_id), blocks.clone()));
-                        batch = 0;
-                        // FIXME
-                        blocks.0.clear();
-                    }
-                    if total > 100 {
-                        break;
+                sync.send_message(peer_id, payload);
+            }
+            P2PMsgCode::Transaction => {
+                let transactions: Transactions = serde_json::from_slice(&payload).map_err(|err| P2PError::Violation(err.to_string()))?;
+                debug!("Receive a batch transaction from network, size:{:?}", transactions.0.len());
+                // relays the new ones only, so the gossip stops at the nodes have them
+                for transaction in transactions.0 {
+                    match submit_transaction(&chain, &tx_pool, transaction.clone()) {
+                        Ok(true) => broadcast_bus.send(BroadcastEvent::Transaction(transaction)),
+                        Ok(false) => {}
+                        Err(err) => debug!("Reject the transaction from {}, err: {}", peer_id.to_base58(), err),
                     }
-                    batch += 1;
-                    total += 1;
                 }
-                if !blocks.0.is_empty() {
-                    chain.post_event(ChainEvent::PostBlock(Some(peer_id), blocks));
+            }
+            P2PMsgCode::Evidence => {
+                let evidence: Evidence = serde_json::from_slice(&payload).map_err(|err| P2PError::Violation(err.to_string()))?;
+                // a new evidence is relayed by the chain event it posts
+                if let Err(err) = chain.add_evidence(evidence) {
+                    debug!("Reject the evidence from {}, err: {}", peer_id.to_base58(), err);
                 }
             }
-            _ => unimplemented!()
+            ref code => return Err(P2PError::Handle(format!("unexpected message code {:?}", code))),
         }
 
         Ok(())
@@ -95,6 +124,27 @@ pub struct Core;
 
 // --- CoreState: tokio-based Core without actix ---
 
+/// What the core is doing, for the admin api
+#[derive(Debug, Clone, Serialize)]
+pub struct CoreSnapshot {
+    pub view: View,
+    pub state: String,
+    pub paused: bool,
+    pub wait_round_change: bool,
+    pub proposer: Option<Address>,
+    pub is_proposer: bool,
+    pub proposal: Option<Hash>,
+    pub lock_hash: Option<Hash>,
+    pub prepares: Vec<Address>,
+    pub commits: Vec<Address>,
+    pub round_changes: BTreeMap<Round, Vec<Address>>,
+    // the number of the future messages of every validator
+    pub backlog: BTreeMap<Address, usize>,
+    pub validators: Validators,
+    #[cfg(any(test, feature = "sim"))]
+    pub byzantine: Vec<ByzantineMode>,
+}
+
 /// Core state for tokio run loop - same fields as Core but with tokio timer/backlog
 pub struct CoreState {
     pub config: Config,
@@ -110,57 +160,198 @@ pub struct CoreState {
     pub backend: Box<dyn Backend<ValidatorsType = ImplValidatorSet>>,
     pub round_change_limiter: Instant,
     chain: Arc<Chain>,
+    wal: ConsensusWal,
+    // the highest prepared certificate of current height
+    pub prepared_certificate: Option<PreparedCertificate>,
+    // the round change messages that started current round
+    pub round_change_justification: Vec<GossipMessage>,
+    // when the state and the height were entered, for the metrics
+    state_entered: Instant,
+    height_started: Instant,
+    // paused by the admin api
+    paused: bool,
+    // the misbehaviours of a byzantine validator, empty for an honest one
+    #[cfg(any(test, feature = "sim"))]
+    pub(crate) byzantine: Vec<ByzantineMode>,
+    #[cfg(any(test, feature = "sim"))]
+    delayed_messages: u64,
+    equivocations: EquivocationDetector,
+
+    timer: Box<dyn CoreTimer>,
+}
 
-    core_handle: CoreHandle,
-    round_change_timer_handle: Option<tokio::task::JoinHandle<()>>,
-    future_preprepare_timer_handle: Option<tokio::task::JoinHandle<()>>,
+/// What the run loop does after a message is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Next {
+    Continue,
+    /// a message of the future height is received, the blocks before it should be synced
+    Sync(Height),
+    Stop,
 }
 
 impl CoreState {
+    /// Creates the core at the height after the last block, the core runs after `start`
+    pub fn new(
+        chain: Arc<Chain>,
+        backend: Box<dyn Backend<ValidatorsType = ImplValidatorSet>>,
+        key_pair: KeyPair,
+        timer: Box<dyn CoreTimer>,
+    ) -> Self {
+        let address = key_pair.address();
+        let last_block = chain.get_last_block();
+        let validators = chain.get_validators(last_block.height() + 1);
+        let addresses: Vec<Address> = validators.iter().map(|v| *v.address()).collect();
+        let validators = ImplValidatorSet::new(&addresses, Box::new(fn_selector));
+
+        let last_view = View::new(last_block.height(), 0);
+        let lock_hash = last_block.hash();
+        let current_state = RoundState::new_round_state(
+            last_view,
+            validators.clone(),
+            Some(lock_hash),
+            None,
+            None,
+        );
+        let round_change_set = RoundChangeSet::new(validators.clone(), None);
+
+        let config = Config {
+            request_time: chain.config.request_time.as_millis() as u64,
+            block_period: chain.config.block_period.as_secs(),
+            chain_id: 0,
+        };
+
+        #[cfg(any(test, feature = "sim"))]
+        let byzantine = chain.config.byzantine.clone();
+        #[cfg(any(test, feature = "sim"))]
+        if !byzantine.is_empty() {
+            warn!("The validator is byzantine, modes: {:?}", byzantine);
+        }
+        let now = timer.now();
+        CoreState {
+            config,
+            address,
+            keypair: key_pair,
+            state: State::AcceptRequest,
+            validators,
+            current_state,
+            round_change_set,
+            wait_round_change: false,
+            consensus_timestamp: Duration::from_secs(0),
+            backlog_store: HashMap::new(),
+            backend,
+            round_change_limiter: now,
+            chain,
+            wal: ConsensusWal::default(),
+            prepared_certificate: None,
+            round_change_justification: vec![],
+            state_entered: now,
+            height_started: now,
+            paused: false,
+            #[cfg(any(test, feature = "sim"))]
+            byzantine,
+            #[cfg(any(test, feature = "sim"))]
+            delayed_messages: 0,
+            equivocations: EquivocationDetector::default(),
+            timer,
+        }
+    }
+
+    /// Starts the height after the last block, or resumes the view recorded by the wal
+    pub fn start(&mut self) {
+        let wal = self.chain.get_ledger().read().get_schema().consensus_wal().get();
+        self.wal = wal.clone().unwrap_or_default();
+        self.start_new_zero_round();
+        self.replay_wal(wal);
+    }
+
+    /// Handles a message of the run loop
+    pub fn handle(&mut self, msg: CoreMessage) -> Next {
+        if self.paused && !matches!(msg, CoreMessage::Op(_) | CoreMessage::Inspect(_)) {
+            trace!("core msg dropped, the core is paused");
+            return Next::Continue;
+        }
+        match msg {
+            CoreMessage::Message(m) => {
+                trace!("core msg Message");
+                if let Err(ref e) = self.handle_message_event(m) {
+                    debug!("handle_message_event err: {:?}", e);
+                    if let ConsensusError::FutureBlockMessage(height) = e {
+                        return Next::Sync(*height);
+                    }
+                }
+            }
+            CoreMessage::NewHeader(m) => {
+                trace!("core msg NewHeader height={}", m.proposal.block().height());
+                if let Err(ref e) = self.handle_new_header(m) {
+                    debug!("handle_new_header err: {:?}", e);
+                }
+            }
+            CoreMessage::FinalCommitted(m) => {
+                trace!("core msg FinalCommitted");
+                self.handle_final_committed(m)
+            }
+            CoreMessage::BackLog(m) => {
+                trace!("core msg BackLog");
+                if let Err(ref e) = self.handle_backlog_event(m) {
+                    debug!("handle_backlog_event err: {:?}", e);
+                }
+            }
+            CoreMessage::Timer(m) => {
+                trace!("core msg Timer");
+                self.handle_timer_event(m)
+            }
+            CoreMessage::Op(op) => {
+                if self.handle_op_cmd(op) {
+                    return Next::Stop;
+                }
+            }
+            #[cfg(any(test, feature = "sim"))]
+            CoreMessage::Delayed(msg) => {
+                trace!("core msg Delayed");
+                self.send_gossip(msg)
+            }
+            CoreMessage::Inspect(reply) => {
+                let _ = reply.send(self.snapshot());
+            }
+        }
+        Next::Continue
+    }
+
+    /// The time of the core's timers
+    pub(crate) fn now(&self) -> Instant {
+        self.timer.now()
+    }
+
+    pub(crate) fn timestamp_millis(&self) -> u64 {
+        self.timer.timestamp_millis()
+    }
+
     fn add_to_backlog(&mut self, msg: GossipMessage) {
         self.backlog_store
             .entry(msg.address)
             .or_default()
             .push(msg);
+        let size: usize = self.backlog_store.values().map(Vec::len).sum();
+        metrics::CONSENSUS_BACKLOG.set(size as i64);
     }
 
     fn stop_timer(&mut self) {
-        if let Some(h) = self.round_change_timer_handle.take() {
-            h.abort();
-        }
-        if let Some(h) = self.future_preprepare_timer_handle.take() {
-            h.abort();
-        }
+        self.timer.stop(TimerKind::RoundChange);
+        self.timer.stop(TimerKind::FuturePreprepare);
     }
 
     pub(crate) fn new_round_change_timer(&mut self) {
-        if let Some(h) = self.round_change_timer_handle.take() {
-            h.abort();
-        }
-        let handle = self.core_handle.clone();
-        let timeout_ms = self.config.request_time;
-        self.round_change_timer_handle = Some(tokio::spawn(async move {
-            tokio::time::sleep(Duration::from_millis(timeout_ms)).await;
-            handle.send_timer();
-        }));
+        let timeout = Duration::from_millis(self.config.request_time);
+        self.timer.start(TimerKind::RoundChange, timeout, CoreMessage::Timer(TimerEvent {}));
     }
 
     pub(crate) fn new_round_future_preprepare_timer(&mut self, duration: Duration, msg: GossipMessage) {
-        if let Some(h) = self.future_preprepare_timer_handle.take() {
-            h.abort();
-        }
-        let handle = self.core_handle.clone();
-        self.future_preprepare_timer_handle = Some(tokio::spawn(async move {
-            tokio::time::sleep(duration).await;
-            handle.send_backlog(msg);
-        }));
+        self.timer.start(TimerKind::FuturePreprepare, duration, CoreMessage::BackLog(BackLogEvent { msg }));
     }
 
     #[allow(dead_code)]
     fn stop_future_preprepare_timer(&mut self) {
-        if let Some(h) = self.future_preprepare_timer_handle.take() {
-            h.abort();
-        }
+        self.timer.stop(TimerKind::FuturePreprepare);
     }
 
     pub(crate) fn address(&self) -> Address {
@@ -177,9 +368,41 @@ impl CoreState {
 
     pub(crate) fn set_state(&mut self, new_state: State) {
         trace!("state change, from {:?} to {:?}", self.state, new_state);
+        let now = self.timer.now();
+        metrics::observe_state(&self.state, now.duration_since(self.state_entered));
+        self.state_entered = now;
         self.state = new_state;
     }
 
+    pub(crate) fn snapshot(&self) -> CoreSnapshot {
+        let senders = |messages: Vec<GossipMessage>| -> Vec<Address> {
+            let mut addresses: Vec<Address> = messages.iter().map(|msg| msg.address).collect();
+            addresses.sort();
+            addresses
+        };
+        CoreSnapshot {
+            view: self.current_view(),
+            state: format!("{:?}", self.state),
+            paused: self.paused,
+            wait_round_change: self.wait_round_change,
+            proposer: self.validators.get_proposer().map(|validator| *validator.address()),
+            is_proposer: self.validators.is_proposer(self.address),
+            proposal: self.current_state.proposal().map(|proposal| proposal.block().hash()),
+            lock_hash: self.current_state.get_lock_hash(),
+            prepares: senders(self.current_state.prepares.values()),
+            commits: senders(self.current_state.commits.values()),
+            round_changes: self.round_change_set.votes(),
+            backlog: self
+                .backlog_store
+                .iter()
+                .map(|(address, messages)| (*address, messages.len()))
+                .collect(),
+            validators: self.validators.list(),
+            #[cfg(any(test, feature = "sim"))]
+            byzantine: self.byzantine.clone(),
+        }
+    }
+
     #[allow(dead_code)]
     fn mut_current_state(&mut self) -> &mut RoundState {
         &mut self.current_state
@@ -199,6 +422,13 @@ impl CoreState {
         self.validators
             .get_by_address(address)
             .ok_or(ConsensusError::UnauthorizedAddress)?;
+        if let Some(evidence) = self.equivocations.observe(&msg, self.current_state.height()) {
+            warn!("Validator {:?} equivocates, {:?}", address, msg.code);
+            if let Err(err) = self.chain.add_evidence(evidence) {
+                warn!("Failed to add the evidence, err: {}", err);
+            }
+        }
+        metrics::CONSENSUS_MESSAGES.with_label_values(&[metrics::message_label(&msg.code)]).inc();
         self.handle_check_message(&msg, &Validator::new(address))
     }
 
@@ -249,20 +479,171 @@ impl CoreState {
         Ok(())
     }
 
-    fn finalize_message(&self, msg: &mut GossipMessage) -> Result<(), String> {
+    pub(crate) fn finalize_message(&self, msg: &mut GossipMessage) -> Result<(), String> {
         msg.address = self.address;
         msg.set_sign(self.keypair.secret());
         Ok(())
     }
 
     pub(crate) fn broadcast(&mut self, msg: &GossipMessage) {
-        let mut copy_msg = msg.clone();
-        self.finalize_message(&mut copy_msg).unwrap();
-        if let Err(err) = self.backend.gossip(&self.validators, copy_msg) {
+        self.update_wal();
+        let checked = self.wal.check(msg);
+        if let Ok(None) = checked {
+            let mut copy_msg = msg.clone();
+            self.finalize_message(&mut copy_msg).unwrap();
+            self.wal.record(copy_msg);
+        }
+        // flush the lock and the sent message together before the message leaves
+        self.persist_wal();
+        let copy_msg = match checked {
+            Ok(Some(signed)) => signed,
+            Ok(None) => self.wal.sent.last().cloned().unwrap(),
+            Err(err) => {
+                warn!("Refuse to sign the message, err: {:?}", err);
+                return;
+            }
+        };
+        #[cfg(any(test, feature = "sim"))]
+        {
+            if !self.byzantine.is_empty() {
+                for msg in self.byzantine_messages(copy_msg) {
+                    self.gossip(msg);
+                }
+                return;
+            }
+        }
+        self.gossip(copy_msg);
+    }
+
+    /// Gossips the message, after the byzantine delay if there is one
+    fn gossip(&mut self, msg: GossipMessage) {
+        #[cfg(any(test, feature = "sim"))]
+        {
+            if let Some(delay) = self.byzantine_delay() {
+                self.delayed_messages += 1;
+                let kind = TimerKind::Delayed(self.delayed_messages);
+                self.timer.start(kind, delay, CoreMessage::Delayed(msg));
+                return;
+            }
+        }
+        self.send_gossip(msg);
+    }
+
+    fn send_gossip(&mut self, msg: GossipMessage) {
+        if let Err(err) = self.backend.gossip(&self.validators, msg) {
             error!("Failed to gossip message, err: {:?}", err);
         }
     }
 
+    // keep the wal following the current view and the locked proposal
+    pub(crate) fn sync_wal(&mut self) {
+        self.update_wal();
+        self.persist_wal();
+    }
+
+    fn update_wal(&mut self) {
+        let view = self.current_view();
+        if view.height != self.wal.view.height {
+            self.wal = ConsensusWal::new(view);
+        } else if view > self.wal.view {
+            self.wal.view = view;
+            self.wal.preprepare = None;
+        }
+        if self.prepared_certificate.is_some() {
+            self.wal.prepared = self.prepared_certificate.clone();
+        }
+        // never regress, the view is behind the wal before it is replayed
+        if view == self.wal.view && self.current_state.preprepare.is_some() {
+            self.wal.preprepare = self.current_state.preprepare.clone();
+            if self.current_state.is_locked()
+                && self.current_state.get_lock_hash() == self.current_state.proposal().map(|proposal| proposal.block().hash())
+            {
+                self.wal.locked = self.current_state.preprepare.clone();
+            }
+        }
+    }
+
+    // the whole wal is written at once under the write lock, like the blocks
+    fn persist_wal(&self) {
+        let ledger = self.chain.get_ledger().write();
+        let schema = ledger.get_schema();
+        let mut batch = schema.batch();
+        schema.consensus_wal().set_in(&mut batch, self.wal.clone());
+        schema.write_batch(batch);
+    }
+
+    /// Resumes at the view recorded by the wal and re-gossips the signed messages,
+    /// the messages sent to self restore the local votes.
+    fn replay_wal(&mut self, wal: Option<ConsensusWal>) {
+        let wal = match wal {
+            Some(ref wal) if wal.view.height == self.current_state.height() => wal.clone(),
+            _ => {
+                self.sync_wal();
+                return;
+            }
+        };
+        info!("Replay consensus wal, view: {}, sent: {}", wal.view, wal.sent.len());
+        let last_proposal = self.backend.last_proposal().unwrap();
+        let locked_hash = wal.locked.as_ref().map(|locked| locked.proposal.block().hash());
+        let preprepare = wal.preprepare.clone().or_else(|| wal.locked.clone());
+        self.current_state = RoundState::new_round_state(
+            wal.view,
+            self.validators.clone(),
+            locked_hash,
+            preprepare,
+            None,
+        );
+        metrics::observe_view(&wal.view);
+        self.round_change_set = RoundChangeSet::new(self.validators.clone(), None);
+        self.validators.calc_proposer(
+            &last_proposal.block().hash(),
+            last_proposal.block().height(),
+            wal.view.round,
+        );
+        if wal.preprepare.is_some() && wal.has_sent(MessageType::Commit) {
+            self.set_state(State::Prepared);
+        } else if wal.preprepare.is_some() && wal.has_sent(MessageType::Prepare) {
+            self.set_state(State::PrePrepared);
+        }
+        self.prepared_certificate = wal.prepared.clone();
+        self.wal = wal;
+        self.persist_wal();
+
+        let signed: Vec<GossipMessage> = self
+            .wal
+            .sent
+            .iter()
+            .filter(|msg| ConsensusWal::message_view(msg) >= self.wal.view)
+            .cloned()
+            .collect();
+        for msg in signed {
+            self.send_gossip(msg);
+        }
+        self.new_round_change_timer();
+    }
+
+    /// Locks the proposal of current view and keeps its prepared certificate if it is the highest.
+    pub(crate) fn lock_proposal(&mut self) {
+        self.current_state.lock_hash();
+        let preprepare = match self.current_state.preprepare {
+            Some(ref preprepare) => preprepare.clone(),
+            None => return,
+        };
+        let higher = self
+            .prepared_certificate
+            .as_ref()
+            .map_or(true, |certificate| certificate.view.round < preprepare.view.round);
+        if !higher || preprepare.view != self.current_view() {
+            return;
+        }
+        let mut votes = self.current_state.prepares.values();
+        votes.extend(self.current_state.commits.values());
+        if let Some(certificate) = new_prepared_certificate(&preprepare, votes, &self.validators) {
+            trace!("Prepared certificate, view: {}", certificate.view);
+            self.prepared_certificate = Some(certificate);
+        }
+    }
+
     fn update_round_state(
         &mut self,
         view: View,
@@ -290,6 +671,7 @@ impl CoreState {
         } else {
             self.current_state = RoundState::new_round_state(view, vals, None, None, None);
         }
+        metrics::observe_view(&view);
     }
 
     fn start_new_zero_round(&mut self) {
@@ -297,21 +679,25 @@ impl CoreState {
         let last_proposal = self.backend.last_proposal().unwrap();
         let last_height = last_proposal.block().height();
         let new_view = View::new(last_height + 1, 0);
-        self.validators = self.backend.validators(last_height + 1).clone();
+        self.validators = self.backend.validators(last_height + 1);
         self.round_change_set = RoundChangeSet::new(self.validators.clone(), None);
         assert_ne!(self.validators.size(), 0, "validators'size should be more than zero");
 
         self.update_round_state(new_view, self.validators.clone(), false);
         self.validators
             .calc_proposer(&last_proposal.block().hash(), last_height, new_view.round);
+        self.prepared_certificate = None;
+        self.round_change_justification.clear();
+        self.height_started = self.timer.now();
 
         self.wait_round_change = false;
         self.set_state(State::AcceptRequest);
+        self.sync_wal();
         self.new_round_change_timer();
         debug!("after start zero round");
     }
 
-    pub(crate) fn start_new_round(&mut self, round: Round, _pre_change_prove: &[u8]) {
+    pub(crate) fn start_new_round(&mut self, round: Round, justification: Vec<GossipMessage>) {
         trace!("before start new round");
         assert_ne!(round, 0, "zero round only call by self.start_new_zero_round");
         assert!(
@@ -332,12 +718,20 @@ impl CoreState {
         self.update_round_state(new_view, self.validators.clone(), true);
         self.validators
             .calc_proposer(&last_proposal.block().hash(), last_height, new_view.round);
+        let proposer = self.validators.get_proposer().map(|validator| *validator.address());
+        self.chain.post_event(ChainEvent::RoundChange(new_view, proposer));
+        metrics::CONSENSUS_ROUND_CHANGES.inc();
 
         self.wait_round_change = false;
         self.set_state(State::AcceptRequest);
+        self.round_change_justification = justification;
+        self.sync_wal();
 
         if self.validators.is_proposer(self.address) {
-            if self.current_state.is_locked() {
+            // the highest prepared proposal must be re-proposed
+            if let Some(certificate) = highest_prepared(&self.round_change_justification) {
+                self.send_preprepare(&CSRequest::new(certificate.proposal));
+            } else if self.current_state.is_locked() {
                 let r = CSRequest::new(self.current_state.proposal().unwrap().clone());
                 self.send_preprepare(&r);
             } else if let Some(ref proposal) = self.current_state.pending_request {
@@ -371,6 +765,9 @@ impl CoreState {
         let mut proposal = self.current_state.proposal().unwrap().clone();
         if let Err(_err) = self.backend.commit(&mut proposal, committed_seals) {
             error!("Failed to commit block");
+        } else {
+            let elapsed = self.timer.now().duration_since(self.height_started);
+            metrics::CONSENSUS_COMMIT_SECONDS.observe(elapsed.as_secs_f64());
         }
         debug!(
             "commit proposal, hash:{}, height:{}",
@@ -458,6 +855,28 @@ impl CoreState {
                 debug!("Recive a test message");
                 false
             }
+            OpCMD::Pause => {
+                info!("Pause the consensus core, view: {}", self.current_view());
+                self.paused = true;
+                self.stop_timer();
+                false
+            }
+            OpCMD::Resume => {
+                if self.paused {
+                    info!("Resume the consensus core, view: {}", self.current_view());
+                    self.paused = false;
+                    // the messages dropped meanwhile are recovered by a round change or the block sync
+                    self.new_round_change_timer();
+                }
+                false
+            }
+            OpCMD::RoundChange => {
+                if !self.paused {
+                    info!("Force a round change, view: {}", self.current_view());
+                    self.send_next_round_change();
+                }
+                false
+            }
         }
     }
 }
@@ -482,101 +901,29 @@ impl Core {
         });
         let mut backend = backend;
         backend.set_core_handle(core_handle.clone());
-        let core_backend: Box<dyn Backend<ValidatorsType = ImplValidatorSet> + Send + Sync> =
-            Box::new(backend) as Box<dyn Backend<ValidatorsType = ImplValidatorSet> + Send + Sync>;
-
-        let address = key_pair.address();
-        let last_block = chain.get_last_block();
-        let validators = chain.get_validators(last_block.height());
-        let addresses: Vec<Address> = validators.iter().map(|v| *v.address()).collect();
-        let validators = ImplValidatorSet::new(&addresses, Box::new(fn_selector));
-
-        let last_view = View::new(last_block.height(), 0);
-        let lock_hash = last_block.hash();
-        let current_state = RoundState::new_round_state(
-            last_view,
-            validators.clone(),
-            Some(lock_hash),
-            None,
-            None,
-        );
-        let round_change_set = RoundChangeSet::new(validators.clone(), None);
-
-        let config = Config {
-            request_time: chain.config.request_time.as_millis() as u64,
-            block_period: chain.config.block_period.as_secs(),
-            chain_id: 0,
-        };
-
-        let mut state = CoreState {
-            config,
-            address,
-            keypair: key_pair,
-            state: State::AcceptRequest,
-            validators,
-            current_state,
-            round_change_set,
-            wait_round_change: false,
-            consensus_timestamp: Duration::from_secs(0),
-            backlog_store: HashMap::new(),
-            backend: core_backend,
-            round_change_limiter: Instant::now(),
-            chain: chain.clone(),
-            core_handle,
-            round_change_timer_handle: None,
-            future_preprepare_timer_handle: None,
-        };
-
-        state.start_new_zero_round();
+        let timer = Box::new(TokioTimer::new(core_handle));
+        let mut state = CoreState::new(chain.clone(), Box::new(backend), key_pair, timer);
+        state.start();
 
         info!("core run loop started");
 
         let mut msg_count: u64 = 0;
         while let Some(msg) = rx.recv().await {
             msg_count += 1;
-            match msg {
-                CoreMessage::Message(m) => {
-                    trace!("core msg #{} Message", msg_count);
-                    if let Err(ref e) = state.handle_message_event(m) {
-                        if let ConsensusError::FutureBlockMessage(height) = e {
-                            let height = *height;
-                            let chain = state.chain.clone();
-                            tokio::spawn(async move {
-                                tokio::time::sleep(Duration::from_secs(1)).await;
-                                let last_height = chain.get_last_height();
-                                if last_height < height {
-                                    chain.post_event(ChainEvent::SyncBlock(last_height + 1));
-                                }
-                            });
+            trace!("core msg #{}", msg_count);
+            match state.handle(msg) {
+                Next::Continue => {}
+                Next::Sync(height) => {
+                    let chain = chain.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        let last_height = chain.get_last_height();
+                        if last_height < height {
+                            chain.post_event(ChainEvent::SyncBlock(last_height + 1));
                         }
-                        debug!("handle_message_event err: {:?}", e);
-                    }
-                }
-                CoreMessage::NewHeader(m) => {
-                    trace!("core msg #{} NewHeader height={}", msg_count, m.proposal.block().height());
-                    if let Err(ref e) = state.handle_new_header(m) {
-                        debug!("handle_new_header err: {:?}", e);
-                    }
-                }
-                CoreMessage::FinalCommitted(m) => {
-                    trace!("core msg #{} FinalCommitted", msg_count);
-                    state.handle_final_committed(m)
-                }
-                CoreMessage::BackLog(m) => {
-                    trace!("core msg #{} BackLog", msg_count);
-                    if let Err(ref e) = state.handle_backlog_event(m) {
-                        debug!("handle_backlog_event err: {:?}", e);
-                    }
-                }
-                CoreMessage::Timer(m) => {
-                    trace!("core msg #{} Timer", msg_count);
-                    state.handle_timer_event(m)
-                }
-                CoreMessage::Op(op) => {
-                    if state.handle_op_cmd(op) {
-                        break;
-                    }
+                    });
                 }
+                Next::Stop => break,
             }
         }
 
@@ -0,0 +1,82 @@
+//! Catches the validators that sign two different proposals or votes of the same view.
+
+use cryptocurrency_kit::crypto::Hash;
+use cryptocurrency_kit::ethkey::Address;
+
+use std::collections::HashMap;
+
+use crate::{
+    consensus::types::Round,
+    protocol::GossipMessage,
+    types::evidence::{message_digest, Evidence},
+    types::Height,
+};
+
+#[derive(Default)]
+pub struct EquivocationDetector {
+    height: Height,
+    // the first message of every validator, kind and round of the height, with its digest
+    signed: HashMap<(Address, u8, Round), (Hash, GossipMessage)>,
+}
+
+impl EquivocationDetector {
+    /// Records the message of a validator, the signer must have been recovered.
+    /// Returns the evidence if the validator signed another digest of the view before.
+    pub fn observe(&mut self, msg: &GossipMessage, height: Height) -> Option<Evidence> {
+        if self.height != height {
+            self.signed.clear();
+            self.height = height;
+        }
+        let (view, digest) = message_digest(msg).ok()?;
+        if view.height != height {
+            return None;
+        }
+        let key = (msg.address, msg.code.clone() as u8, view.round);
+        match self.signed.get(&key) {
+            Some((first_digest, _)) if *first_digest == digest => None,
+            Some((_, first)) => Evidence::new(first.clone(), msg.clone()).ok(),
+            None => {
+                self.signed.insert(key, (digest, msg.clone()));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::types::{Subject, View};
+    use crate::protocol::MessageType;
+    use cryptocurrency_kit::crypto::hash;
+    use cryptocurrency_kit::ethkey::{Generator, KeyPair, Random};
+    use cryptocurrency_kit::storage::values::StorageValue;
+
+    fn vote(key_pair: &KeyPair, view: View, digest: Hash) -> GossipMessage {
+        let subject = Subject { view, digest };
+        let mut msg = GossipMessage::new(MessageType::Commit, subject.into_bytes(), None);
+        msg.set_sign(key_pair.secret());
+        msg.address().unwrap();
+        msg
+    }
+
+    #[test]
+    fn t_observe() {
+        let key_pair = Random.generate().unwrap();
+        let mut detector = EquivocationDetector::default();
+        let first = vote(&key_pair, View::new(2, 0), hash(b"a"));
+        assert!(detector.observe(&first, 2).is_none());
+        // the same vote again and a vote of the next round are fine
+        assert!(detector.observe(&first, 2).is_none());
+        assert!(detector.observe(&vote(&key_pair, View::new(2, 1), hash(b"b")), 2).is_none());
+
+        let second = vote(&key_pair, View::new(2, 0), hash(b"b"));
+        let evidence = detector.observe(&second, 2).unwrap();
+        assert_eq!(evidence.offender().unwrap(), key_pair.address());
+
+        // the votes of the last height are forgotten
+        let next = vote(&key_pair, View::new(3, 0), hash(b"c"));
+        assert!(detector.observe(&next, 3).is_none());
+        assert!(detector.observe(&second, 3).is_none());
+    }
+}
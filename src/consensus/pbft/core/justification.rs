@@ -0,0 +1,179 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use cryptocurrency_kit::ethkey::Address;
+use cryptocurrency_kit::storage::values::StorageValue;
+
+use crate::{
+    consensus::error::{ConsensusError, ConsensusResult},
+    consensus::types::{PrePrepare, PreparedCertificate, RoundChange, Subject},
+    consensus::validator::{ImplValidatorSet, ValidatorSet},
+    protocol::{GossipMessage, MessageType},
+};
+
+// recovers the signer and checks it is a validator
+fn signer(msg: &GossipMessage, val_set: &ImplValidatorSet) -> Result<Address, ConsensusError> {
+    let mut msg = msg.clone();
+    let address = msg.address().map_err(ConsensusError::Unknown)?;
+    val_set
+        .get_by_address(address)
+        .ok_or(ConsensusError::UnauthorizedAddress)?;
+    Ok(address)
+}
+
+/// Builds the certificate from the votes of the view, None if the votes are not enough.
+pub fn new_prepared_certificate(
+    preprepare: &PrePrepare,
+    votes: Vec<GossipMessage>,
+    val_set: &ImplValidatorSet,
+) -> Option<PreparedCertificate> {
+    let certificate = PreparedCertificate {
+        view: preprepare.view,
+        proposal: preprepare.proposal.clone(),
+        prepares: votes,
+    };
+    verify_prepared_certificate(&certificate, val_set).ok()?;
+    Some(certificate)
+}
+
+/// The certificate holds more than 2/3 distinct validators' votes for the proposal in its view
+pub fn verify_prepared_certificate(
+    certificate: &PreparedCertificate,
+    val_set: &ImplValidatorSet,
+) -> ConsensusResult {
+    let digest = certificate.proposal.block().hash();
+    if certificate.proposal.block().height() != certificate.view.height {
+        return Err(ConsensusError::InvalidMessage);
+    }
+    let mut signers = HashSet::new();
+    for prepare in &certificate.prepares {
+        if prepare.code != MessageType::Prepare && prepare.code != MessageType::Commit {
+            return Err(ConsensusError::InvalidMessage);
+        }
+        let subject: Subject = Subject::from_bytes(Cow::from(prepare.msg()));
+        if subject.view != certificate.view || subject.digest != digest {
+            return Err(ConsensusError::InconsistentSubject);
+        }
+        signers.insert(signer(prepare, val_set)?);
+    }
+    if signers.len() <= val_set.two_thirds_majority() {
+        return Err(ConsensusError::Unknown(format!(
+            "prepared certificate lacks votes, got: {}",
+            signers.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the highest prepared certificate carried by the round change messages
+pub fn highest_prepared(round_changes: &[GossipMessage]) -> Option<PreparedCertificate> {
+    round_changes
+        .iter()
+        .filter(|msg| msg.code == MessageType::RoundChange)
+        .filter_map(|msg| RoundChange::from_bytes(Cow::from(msg.msg())).prepared)
+        .max_by(|a, b| a.view.round.cmp(&b.view.round))
+}
+
+/// A preprepare of round > 0 is justified by more than 2/3 round change messages for its view,
+/// and it must re-propose the proposal of the highest prepared certificate among them.
+pub fn verify_justification(preprepare: &PrePrepare, val_set: &ImplValidatorSet) -> ConsensusResult {
+    if preprepare.view.round == 0 {
+        return Ok(());
+    }
+    let mut signers = HashSet::new();
+    for msg in &preprepare.justification {
+        if msg.code != MessageType::RoundChange {
+            return Err(ConsensusError::InvalidMessage);
+        }
+        let round_change: RoundChange = RoundChange::from_bytes(Cow::from(msg.msg()));
+        if round_change.view != preprepare.view {
+            return Err(ConsensusError::InconsistentSubject);
+        }
+        if let Some(ref certificate) = round_change.prepared {
+            if certificate.view.height != preprepare.view.height
+                || certificate.view.round >= preprepare.view.round
+            {
+                return Err(ConsensusError::InvalidMessage);
+            }
+            verify_prepared_certificate(certificate, val_set)?;
+        }
+        signers.insert(signer(msg, val_set)?);
+    }
+    if signers.len() <= val_set.two_thirds_majority() {
+        return Err(ConsensusError::Unknown(format!(
+            "round change justification lacks messages, got: {}",
+            signers.len()
+        )));
+    }
+    if let Some(certificate) = highest_prepared(&preprepare.justification) {
+        if certificate.proposal.block().hash() != preprepare.proposal.block().hash() {
+            return Err(ConsensusError::Unknown(
+                "preprepare does not re-propose the highest prepared proposal".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::types::{Proposal, View};
+    use crate::consensus::validator::fn_selector;
+    use crate::types::block::{Block, Header};
+    use cryptocurrency_kit::crypto::EMPTY_HASH;
+    use cryptocurrency_kit::ethkey::{Address, Generator, KeyPair, Random};
+
+    fn new_proposal(height: u64, time: u64) -> Proposal {
+        let header = Header::new_mock(EMPTY_HASH, Address::from(1), EMPTY_HASH, height, time, None);
+        Proposal::new(Block::new(header, vec![]))
+    }
+
+    fn signed(code: MessageType, payload: Vec<u8>, key_pair: &KeyPair) -> GossipMessage {
+        let mut msg = GossipMessage::new(code, payload, None);
+        msg.set_sign(key_pair.secret());
+        msg
+    }
+
+    #[test]
+    fn t_verify_justification() {
+        let key_pairs: Vec<KeyPair> = (0..4).map(|_| Random.generate().unwrap()).collect();
+        let addresses: Vec<Address> = key_pairs.iter().map(|key_pair| key_pair.address()).collect();
+        let val_set = ImplValidatorSet::new(&addresses, Box::new(fn_selector));
+
+        let prepared_view = View::new(5, 0);
+        let proposal = new_proposal(5, 100);
+        let subject = Subject { view: prepared_view, digest: proposal.block().hash() };
+        let prepares: Vec<GossipMessage> = key_pairs[..3]
+            .iter()
+            .map(|key_pair| signed(MessageType::Prepare, subject.clone().into_bytes(), key_pair))
+            .collect();
+        let preprepare = PrePrepare::new(prepared_view, proposal.clone());
+        let certificate = new_prepared_certificate(&preprepare, prepares.clone(), &val_set).unwrap();
+        assert!(new_prepared_certificate(&preprepare, prepares[..2].to_vec(), &val_set).is_none());
+
+        let view = View::new(5, 1);
+        let round_changes: Vec<GossipMessage> = key_pairs[..3]
+            .iter()
+            .enumerate()
+            .map(|(i, key_pair)| {
+                let prepared = if i == 0 { Some(certificate.clone()) } else { None };
+                let round_change = RoundChange { view, digest: EMPTY_HASH, prepared };
+                signed(MessageType::RoundChange, round_change.into_bytes(), key_pair)
+            })
+            .collect();
+
+        let mut preprepare = PrePrepare::new(view, proposal);
+        preprepare.justification = round_changes.clone();
+        assert!(verify_justification(&preprepare, &val_set).is_ok());
+
+        // another block is not allowed
+        let mut other = PrePrepare::new(view, new_proposal(5, 200));
+        other.justification = round_changes.clone();
+        assert!(verify_justification(&other, &val_set).is_err());
+
+        // lack round changes
+        preprepare.justification = round_changes[..2].to_vec();
+        assert!(verify_justification(&preprepare, &val_set).is_err());
+    }
+}
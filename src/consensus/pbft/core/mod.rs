@@ -2,7 +2,7 @@ pub mod core;
 pub mod runner;
 pub mod round_state;
 pub mod back_log;
-mod timer;
+pub mod timer;
 pub mod types;
 mod round_change_set;
 pub mod new_header;
@@ -10,4 +10,9 @@ pub mod request;
 pub mod preprepare;
 pub mod prepare;
 pub mod commit;
-pub mod round_change;
\ No newline at end of file
+pub mod round_change;
+pub mod wal;
+pub mod justification;
+#[cfg(any(test, feature = "sim"))]
+pub mod byzantine;
+pub mod equivocation;
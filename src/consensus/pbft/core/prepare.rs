@@ -46,12 +46,12 @@ impl HandlePrepare for CoreState {
         if self.current_state.is_locked()
             && subject.digest == *self.current_state.get_lock_hash().as_ref().unwrap()
         {
-            self.current_state.lock_hash();
+            self.lock_proposal();
             self.set_state(State::Prepared);
             self.send_commit();
         }
         if self.current_state.get_prepare_or_commit_size() > self.val_set().two_thirds_majority() {
-            self.current_state.lock_hash();
+            self.lock_proposal();
             self.set_state(State::Prepared);
             self.send_commit();
         }
@@ -12,6 +12,7 @@ use crate::{
 };
 
 use super::{
+    justification::verify_justification,
     round_change::HandleRoundChange,
     core::CoreState,
     commit::HandleCommit,
@@ -27,7 +28,10 @@ pub trait HandlePreprepare {
 impl HandlePreprepare for CoreState {
     fn send_preprepare(&mut self, request: &Request<Proposal>) {
         if self.current_state.height() == request.proposal().block().height() && self.is_proposer() {
-            let preprepre = PrePrepare::new(self.current_view(), request.proposal.clone());
+            let mut preprepre = PrePrepare::new(self.current_view(), request.proposal.clone());
+            if preprepre.view.round > 0 {
+                preprepre.justification = self.round_change_justification.clone();
+            }
             self.broadcast(&GossipMessage::new(
                 MessageType::Preprepare,
                 preprepre.into_bytes(),
@@ -51,7 +55,7 @@ impl HandlePreprepare for CoreState {
                         return Err(ConsensusError::Engine(EngineError::InvalidProposal));
                     }
                     let pre_height = block.height() - 1;
-                    let mut val_set = self.backend.validators(pre_height).clone();
+                    let mut val_set = self.backend.validators(block.height());
                     let _ = self.backend.get_proposer(pre_height);
                     val_set.calc_proposer(&block.header().prev_hash, pre_height, preprepare.view.round);
                     if val_set.is_proposer(*src.address())
@@ -69,6 +73,7 @@ impl HandlePreprepare for CoreState {
         if !self.val_set().is_proposer(*src.address()) {
             return Err(ConsensusError::NotFromProposer);
         }
+        verify_justification(&preprepare, self.val_set())?;
 
         let (d, result) = self.backend.verify(&preprepare.proposal);
         if let Err(ref err) = result {
@@ -90,6 +95,12 @@ impl HandlePreprepare for CoreState {
                     <CoreState as HandlePreprepare>::accetp(self, &preprepare);
                     self.set_state(State::Prepared);
                     self.send_commit();
+                } else if preprepare.view.round > 0 {
+                    // the justification proves no other proposal can be committed, safe to unlock
+                    self.current_state.unlock_hash();
+                    <CoreState as HandlePreprepare>::accetp(self, &preprepare);
+                    self.set_state(State::PrePrepared);
+                    self.send_prepare();
                 } else {
                     self.send_next_round_change();
                 }
@@ -1,5 +1,4 @@
 use std::borrow::Cow;
-use std::time::Instant;
 use std::time::Duration;
 
 use cryptocurrency_kit::crypto::EMPTY_HASH;
@@ -8,12 +7,13 @@ use cryptocurrency_kit::storage::values::StorageValue;
 use crate::{
     consensus::error::{ConsensusError, ConsensusResult},
     consensus::validator::ValidatorSet,
-    consensus::types::{Round, Subject, View},
+    consensus::types::{Round, RoundChange, Subject, View},
     protocol::{GossipMessage, MessageType},
     types::Validator,
 };
 
 use super::core::CoreState;
+use super::justification::verify_prepared_certificate;
 
 pub trait HandleRoundChange {
     fn send_next_round_change(&mut self);
@@ -35,28 +35,33 @@ impl HandleRoundChange for CoreState {
     }
 
     fn send_round_change(&mut self, round: Round) {
-        if Instant::now().duration_since(self.round_change_limiter) <= Duration::from_millis(50) {
+        let now = self.now();
+        if now.duration_since(self.round_change_limiter) <= Duration::from_millis(50) {
             debug!("Skip round change sent");
             self.new_round_change_timer();
             return;
         }
-        self.round_change_limiter = Instant::now();
+        self.round_change_limiter = now;
 
         if self.current_view().round < round {
             self.catchup_round(round);
         }
         let current_view = self.current_view();
 
-        let subject = Subject {
+        let prepared = self.prepared_certificate.clone();
+        let round_change = RoundChange {
             view: View::new(current_view.height, round),
-            digest: EMPTY_HASH,
+            digest: prepared
+                .as_ref()
+                .map_or(EMPTY_HASH, |certificate| certificate.proposal.block().hash()),
+            prepared,
         };
         debug!(
             "Vote for round change, current:{}, vote: {}",
             current_view.round, round
         );
-        let mut msg = GossipMessage::new(MessageType::RoundChange, subject.into_bytes(), None);
-        msg.create_time = chrono::Local::now().timestamp_millis() as u64;
+        let mut msg = GossipMessage::new(MessageType::RoundChange, round_change.into_bytes(), None);
+        msg.create_time = self.timestamp_millis();
         self.broadcast(&msg);
     }
 
@@ -69,6 +74,13 @@ impl HandleRoundChange for CoreState {
             subject
         );
         self.check_message(MessageType::RoundChange, &subject.view)?;
+        let round_change: RoundChange = RoundChange::from_bytes(Cow::from(msg.msg()));
+        if let Some(ref certificate) = round_change.prepared {
+            if certificate.view.height != subject.view.height || certificate.view.round >= subject.view.round {
+                return Err(ConsensusError::InvalidMessage);
+            }
+            verify_prepared_certificate(certificate, self.val_set())?;
+        }
         let current_view = self.current_view();
         let current_val_set = self.val_set().clone();
         if current_view.round > subject.view.round && subject.view.round > 0 {
@@ -93,7 +105,12 @@ impl HandleRoundChange for CoreState {
             && (current_view.round < subject.view.round)
         {
             self.send_round_change(subject.view.round);
-            self.start_new_round(subject.view.round, &[]);
+            let justification = self
+                .round_change_set
+                .round_change_set(&subject.view.round)
+                .map(|round_changes| round_changes.values())
+                .unwrap_or_default();
+            self.start_new_round(subject.view.round, justification);
             return Ok(());
         } else if self.wait_round_change && current_view.round < subject.view.round {
             return Err(ConsensusError::FutureRoundMessage);
@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+use cryptocurrency_kit::ethkey::Address;
 
 use crate::{
     protocol::{MessageManage, GossipMessage},
@@ -74,6 +76,14 @@ impl RoundChangeSet<ImplValidatorSet> {
         max
     }
 
+    /// The senders of the round change messages of every round
+    pub fn votes(&self) -> BTreeMap<Round, Vec<Address>> {
+        self.round_changes
+            .iter()
+            .map(|(round, messages)| (*round, messages.values().iter().map(|msg| msg.address).collect()))
+            .collect()
+    }
+
     pub fn print_info(&self) {
         for round_change in &self.round_changes {
             debug!("round:{:?}, size:{:?}", round_change.0, round_change.1.len());
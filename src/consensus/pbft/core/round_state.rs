@@ -109,7 +109,6 @@ impl RoundState
     }
 
     // 解锁提案
-    #[allow(dead_code)]
     pub(crate) fn unlock_hash(&mut self) {
         trace!(
             "Unlock proposal, hash:{}",
@@ -2,9 +2,12 @@
 
 use crossbeam::channel::Sender;
 
+use crate::consensus::consensus::ConsensusHandle;
 use crate::consensus::events::{MessageEvent, NewHeaderEvent, FinalCommittedEvent, BackLogEvent, TimerEvent, OpCMD};
 use crate::consensus::types::Proposal;
 
+use super::core::CoreSnapshot;
+
 /// Messages that can be sent to the Core
 #[derive(Debug)]
 pub enum CoreMessage {
@@ -14,6 +17,10 @@ pub enum CoreMessage {
     BackLog(BackLogEvent),
     Timer(TimerEvent),
     Op(OpCMD),
+    /// a message held back by the byzantine delay, it is gossiped now
+    #[cfg(any(test, feature = "sim"))]
+    Delayed(crate::protocol::GossipMessage),
+    Inspect(tokio::sync::oneshot::Sender<CoreSnapshot>),
 }
 
 /// Handle for sending messages to the Core (replaces Addr<Core>)
@@ -27,6 +34,10 @@ impl CoreHandle {
         Self { tx }
     }
 
+    pub(crate) fn send(&self, msg: CoreMessage) {
+        let _ = self.tx.try_send(msg);
+    }
+
     pub fn send_message(&self, payload: Vec<u8>) {
         let _ = self.tx.try_send(CoreMessage::Message(MessageEvent { payload }));
     }
@@ -50,4 +61,21 @@ impl CoreHandle {
     pub fn send_stop(&self) {
         let _ = self.tx.try_send(CoreMessage::Op(OpCMD::Stop));
     }
+
+    pub fn send_op(&self, op: OpCMD) -> bool {
+        self.tx.try_send(CoreMessage::Op(op)).is_ok()
+    }
+
+    /// Takes a snapshot of the core, none if the core is stopped
+    pub async fn inspect(&self) -> Option<CoreSnapshot> {
+        let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+        self.tx.try_send(CoreMessage::Inspect(reply_tx)).ok()?;
+        reply_rx.await.ok()
+    }
+}
+
+impl ConsensusHandle for CoreHandle {
+    fn send_message(&self, payload: Vec<u8>) {
+        CoreHandle::send_message(self, payload)
+    }
 }
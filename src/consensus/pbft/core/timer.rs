@@ -1 +1,66 @@
-// Timer - replaced by tokio::spawn + tokio::time::sleep in CoreState
+//! Timers of the core, a node runs them on tokio and the simulation on its virtual clock.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::runner::{CoreHandle, CoreMessage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimerKind {
+    RoundChange,
+    FuturePreprepare,
+    /// a message held back by the byzantine delay, every one has a timer of its own
+    #[cfg(any(test, feature = "sim"))]
+    Delayed(u64),
+}
+
+pub trait CoreTimer: Send {
+    /// Sends the message to the core after the delay, the pending timer of the kind is replaced
+    fn start(&mut self, kind: TimerKind, delay: Duration, msg: CoreMessage);
+    fn stop(&mut self, kind: TimerKind);
+    /// The time of the timers, the round change limiter and the metrics use it
+    fn now(&self) -> Instant;
+    /// The wall clock in milliseconds, it stamps the messages
+    fn timestamp_millis(&self) -> u64;
+}
+
+pub struct TokioTimer {
+    core_handle: CoreHandle,
+    tasks: HashMap<TimerKind, tokio::task::JoinHandle<()>>,
+}
+
+impl TokioTimer {
+    pub fn new(core_handle: CoreHandle) -> Self {
+        TokioTimer {
+            core_handle,
+            tasks: HashMap::new(),
+        }
+    }
+}
+
+impl CoreTimer for TokioTimer {
+    fn start(&mut self, kind: TimerKind, delay: Duration, msg: CoreMessage) {
+        self.stop(kind);
+        self.tasks.retain(|_, task| !task.is_finished());
+        let handle = self.core_handle.clone();
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            handle.send(msg);
+        });
+        self.tasks.insert(kind, task);
+    }
+
+    fn stop(&mut self, kind: TimerKind) {
+        if let Some(task) = self.tasks.remove(&kind) {
+            task.abort();
+        }
+    }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn timestamp_millis(&self) -> u64 {
+        chrono::Local::now().timestamp_millis() as u64
+    }
+}
@@ -0,0 +1,114 @@
+use cryptocurrency_kit::crypto::{hash, CryptoHash, Hash};
+use cryptocurrency_kit::storage::values::StorageValue;
+
+use std::borrow::Cow;
+
+use crate::{
+    consensus::error::ConsensusError,
+    consensus::types::{PrePrepare, PreparedCertificate, Subject, View},
+    protocol::{GossipMessage, MessageType},
+};
+
+/// Consensus write-ahead log of the current height.
+///
+/// It is flushed before any message is signed and sent, so a restarted validator
+/// resumes at the same view and never signs two different messages for one (code, view).
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ConsensusWal {
+    pub view: View,
+    // the locked proposal, it survives round changes
+    pub locked: Option<PrePrepare>,
+    // the proposal accepted in current view
+    pub preprepare: Option<PrePrepare>,
+    // the highest prepared certificate of the height
+    #[serde(default)]
+    pub prepared: Option<PreparedCertificate>,
+    // signed messages sent in the height
+    pub sent: Vec<GossipMessage>,
+}
+
+implement_cryptohash_traits! {ConsensusWal}
+implement_storagevalue_traits! {ConsensusWal}
+
+impl ConsensusWal {
+    pub fn new(view: View) -> Self {
+        ConsensusWal {
+            view,
+            locked: None,
+            preprepare: None,
+            prepared: None,
+            sent: vec![],
+        }
+    }
+
+    /// the view that the message votes for
+    pub fn message_view(msg: &GossipMessage) -> View {
+        match msg.code {
+            MessageType::Preprepare => PrePrepare::from_bytes(Cow::from(msg.msg())).view,
+            _ => Subject::from_bytes(Cow::from(msg.msg())).view,
+        }
+    }
+
+    pub fn find(&self, code: &MessageType, view: &View) -> Option<&GossipMessage> {
+        self.sent
+            .iter()
+            .find(|sent| sent.code == *code && Self::message_view(sent) == *view)
+    }
+
+    /// Returns the signed copy if the same message has been sent before,
+    /// or an error if it conflicts with the one sent before.
+    pub fn check(&self, msg: &GossipMessage) -> Result<Option<GossipMessage>, ConsensusError> {
+        let view = Self::message_view(msg);
+        match self.find(&msg.code, &view) {
+            Some(sent) if sent.msg == msg.msg => Ok(Some(sent.clone())),
+            Some(_) => Err(ConsensusError::Unknown(format!(
+                "conflict with the sent {:?} message, view: {}",
+                msg.code, view
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn record(&mut self, msg: GossipMessage) {
+        self.sent.push(msg);
+    }
+
+    pub fn has_sent(&self, code: MessageType) -> bool {
+        self.find(&code, &self.view).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptocurrency_kit::crypto::EMPTY_HASH;
+
+    fn subject_msg(code: MessageType, view: View, digest: Hash) -> GossipMessage {
+        GossipMessage::new(code, Subject { view, digest }.into_bytes(), None)
+    }
+
+    #[test]
+    fn t_wal_check() {
+        let view = View::new(10, 1);
+        let mut wal = ConsensusWal::new(view);
+        let prepare = subject_msg(MessageType::Prepare, view, EMPTY_HASH);
+        assert!(wal.check(&prepare).unwrap().is_none());
+        wal.record(prepare.clone());
+        assert!(wal.has_sent(MessageType::Prepare));
+        assert!(!wal.has_sent(MessageType::Commit));
+
+        // same message
+        assert!(wal.check(&prepare).unwrap().is_some());
+        // conflicting digest
+        let conflict = subject_msg(MessageType::Prepare, view, 1_u64.hash());
+        assert!(wal.check(&conflict).is_err());
+        // another round is fine
+        let next = subject_msg(MessageType::Prepare, View::new(10, 2), 1_u64.hash());
+        assert!(wal.check(&next).unwrap().is_none());
+
+        let buf = wal.clone().into_bytes();
+        let wal = ConsensusWal::from_bytes(Cow::from(buf));
+        assert_eq!(wal.sent.len(), 1);
+        assert_eq!(wal.view, view);
+    }
+}
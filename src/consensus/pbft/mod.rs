@@ -1 +1,3 @@
-pub mod core;
\ No newline at end of file
+pub mod core;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
@@ -0,0 +1,156 @@
+//! The virtual clock, the events of the simulation are queued by their time and run in order.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::consensus::pbft::core::runner::CoreMessage;
+use crate::consensus::pbft::core::timer::{CoreTimer, TimerKind};
+use crate::types::Height;
+
+pub(crate) enum Event {
+    /// a consensus message arrives at the node
+    Deliver { from: usize, to: usize, payload: Vec<u8> },
+    /// a timer of the core fires
+    Timer { node: usize, kind: TimerKind, msg: CoreMessage },
+    /// the miner of the node proposes the block of the height
+    Propose { node: usize, height: Height },
+    /// the nodes behind fetch the blocks from the peers they reach
+    Sync,
+}
+
+type EventKey = (Duration, u64);
+
+pub(crate) struct Clock {
+    base: Instant,
+    now: Duration,
+    // breaks the ties of the events at the same time, the first scheduled runs first
+    seq: u64,
+    events: BTreeMap<EventKey, Event>,
+    timers: HashMap<(usize, TimerKind), EventKey>,
+}
+
+impl Clock {
+    pub(crate) fn new() -> Self {
+        Clock {
+            base: Instant::now(),
+            now: Duration::from_millis(0),
+            seq: 0,
+            events: BTreeMap::new(),
+            timers: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn now(&self) -> Duration {
+        self.now
+    }
+
+    pub(crate) fn instant(&self) -> Instant {
+        self.base + self.now
+    }
+
+    pub(crate) fn schedule(&mut self, delay: Duration, event: Event) {
+        self.seq += 1;
+        let key = (self.now + delay, self.seq);
+        if let Event::Timer { node, kind, .. } = &event {
+            let (node, kind) = (*node, *kind);
+            self.cancel(node, kind);
+            self.timers.insert((node, kind), key);
+        }
+        self.events.insert(key, event);
+    }
+
+    pub(crate) fn cancel(&mut self, node: usize, kind: TimerKind) {
+        if let Some(key) = self.timers.remove(&(node, kind)) {
+            self.events.remove(&key);
+        }
+    }
+
+    /// Drops the timers of a crashed node
+    pub(crate) fn cancel_all(&mut self, node: usize) {
+        let kinds: Vec<TimerKind> = self
+            .timers
+            .keys()
+            .filter(|(timer_node, _)| *timer_node == node)
+            .map(|(_, kind)| *kind)
+            .collect();
+        for kind in kinds {
+            self.cancel(node, kind);
+        }
+    }
+
+    /// Takes the next event and moves the time to it
+    pub(crate) fn pop(&mut self) -> Option<Event> {
+        let key = *self.events.keys().next()?;
+        let event = self.events.remove(&key).unwrap();
+        if let Event::Timer { node, kind, .. } = &event {
+            self.timers.remove(&(*node, *kind));
+        }
+        self.now = key.0;
+        Some(event)
+    }
+
+    /// Moves the time forward with no event run
+    pub(crate) fn advance(&mut self, to: Duration) {
+        if to > self.now {
+            self.now = to;
+        }
+    }
+
+    pub(crate) fn next_time(&self) -> Option<Duration> {
+        self.events.keys().next().map(|key| key.0)
+    }
+}
+
+/// The timer of a simulated core, it queues the timeouts on the shared clock
+pub(crate) struct SimTimer {
+    node: usize,
+    clock: Arc<Mutex<Clock>>,
+}
+
+impl SimTimer {
+    pub(crate) fn new(node: usize, clock: Arc<Mutex<Clock>>) -> Self {
+        SimTimer { node, clock }
+    }
+}
+
+impl CoreTimer for SimTimer {
+    fn start(&mut self, kind: TimerKind, delay: Duration, msg: CoreMessage) {
+        self.clock.lock().schedule(delay, Event::Timer { node: self.node, kind, msg });
+    }
+
+    fn stop(&mut self, kind: TimerKind) {
+        self.clock.lock().cancel(self.node, kind);
+    }
+
+    fn now(&self) -> Instant {
+        self.clock.lock().instant()
+    }
+
+    // the simulation starts at the epoch, so a run does not depend on when it runs
+    fn timestamp_millis(&self) -> u64 {
+        self.clock.lock().now().as_millis() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_timer_replaced() {
+        let clock = Arc::new(Mutex::new(Clock::new()));
+        let mut timer = SimTimer::new(0, clock.clone());
+        timer.start(TimerKind::RoundChange, Duration::from_secs(3), CoreMessage::Op(crate::consensus::events::OpCMD::Ping));
+        timer.start(TimerKind::RoundChange, Duration::from_secs(1), CoreMessage::Op(crate::consensus::events::OpCMD::Ping));
+        clock.lock().schedule(Duration::from_secs(2), Event::Sync);
+
+        let mut clock = clock.lock();
+        assert!(matches!(clock.pop(), Some(Event::Timer { node: 0, kind: TimerKind::RoundChange, .. })));
+        assert_eq!(clock.now(), Duration::from_secs(1));
+        assert!(matches!(clock.pop(), Some(Event::Sync)));
+        assert!(clock.pop().is_none());
+    }
+}
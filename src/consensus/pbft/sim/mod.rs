@@ -0,0 +1,503 @@
+//! A deterministic simulation of a pbft network in one process.
+//!
+//! Every validator runs the real `CoreState` and `ImplBackend` on a chain of its own, the gossip goes
+//! through a simulated network with latency, losses, reordering and partitions, and the timers run on
+//! a virtual clock, so a run of minutes takes milliseconds and the same seed replays the same run.
+//! The chains are stored in in-memory databases, a restarted node keeps its chain and wal.
+
+mod clock;
+mod network;
+mod node;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use cryptocurrency_kit::crypto::Hash;
+use cryptocurrency_kit::ethkey::KeyPair;
+use cryptocurrency_kit::storage::values::StorageValue;
+use parking_lot::Mutex;
+use toml::value::Table;
+
+pub use self::network::NetworkConfig;
+use self::clock::{Clock, Event};
+use self::network::Network;
+use self::node::{sim_key_pair, SimNode};
+use crate::{
+    config::GenesisConfig,
+    consensus::events::{FinalCommittedEvent, MessageEvent, NewHeaderEvent},
+    consensus::pbft::core::{byzantine::ByzantineMode, core::CoreSnapshot, runner::CoreMessage},
+    consensus::types::Proposal,
+    protocol::GossipMessage,
+    types::evidence::Evidence,
+    types::Height,
+};
+
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub validators: usize,
+    /// seeds the keys of the validators and the network
+    pub seed: u64,
+    pub network: NetworkConfig,
+    pub block_period: Duration,
+    pub request_time: Duration,
+    /// how often the nodes behind fetch the blocks from the peers they reach
+    pub sync_interval: Duration,
+    /// the misbehaviours of the byzantine nodes
+    pub byzantine: BTreeMap<usize, Vec<ByzantineMode>>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            validators: 4,
+            seed: 0,
+            network: NetworkConfig::default(),
+            block_period: Duration::from_secs(1),
+            request_time: Duration::from_secs(3),
+            sync_interval: Duration::from_secs(2),
+            byzantine: BTreeMap::new(),
+        }
+    }
+}
+
+pub struct Simulation {
+    config: SimConfig,
+    clock: Arc<Mutex<Clock>>,
+    network: Network,
+    nodes: Vec<SimNode>,
+}
+
+impl Simulation {
+    pub fn new(config: SimConfig) -> Self {
+        let key_pairs: Vec<KeyPair> = (0..config.validators)
+            .map(|index| sim_key_pair(config.seed, index))
+            .collect();
+        let genesis = genesis_config(&key_pairs);
+        let clock = Arc::new(Mutex::new(Clock::new()));
+        let nodes = key_pairs
+            .into_iter()
+            .enumerate()
+            .map(|(index, key_pair)| SimNode::new(index, key_pair, &genesis, &config, clock.clone()))
+            .collect();
+        let mut simulation = Simulation {
+            network: Network::new(config.network.clone(), config.seed),
+            config,
+            clock,
+            nodes,
+        };
+        for index in 0..simulation.nodes.len() {
+            simulation.start_node(index);
+        }
+        let sync_interval = simulation.config.sync_interval;
+        simulation.clock.lock().schedule(sync_interval, Event::Sync);
+        simulation
+    }
+
+    /// The virtual time since the start
+    pub fn now(&self) -> Duration {
+        self.clock.lock().now()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn height(&self, node: usize) -> Height {
+        self.nodes[node].chain.get_last_height()
+    }
+
+    /// The lowest height of the nodes not crashed
+    pub fn min_height(&self) -> Height {
+        (0..self.nodes.len())
+            .filter(|node| !self.network.is_crashed(*node))
+            .map(|node| self.height(node))
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn block_hash(&self, node: usize, height: Height) -> Option<Hash> {
+        self.nodes[node].chain.get_block_hash_by_height(height)
+    }
+
+    /// The evidences of equivocations committed by the chain of the node
+    pub fn evidences(&self, node: usize) -> Vec<Evidence> {
+        let chain = &self.nodes[node].chain;
+        (1..=chain.get_last_height())
+            .filter_map(|height| chain.get_block_by_height(height))
+            .flat_map(|block| block.evidences().clone())
+            .collect()
+    }
+
+    pub fn snapshot(&self, node: usize) -> CoreSnapshot {
+        self.nodes[node].core.snapshot()
+    }
+
+    /// Changes the latency and the losses of the messages sent from now on
+    pub fn set_network(&mut self, config: NetworkConfig) {
+        self.network.set_config(config);
+    }
+
+    /// Splits the network, the messages between the groups and in flight across them are lost
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        self.network.partition(self.nodes.len(), groups);
+    }
+
+    pub fn heal(&mut self) {
+        self.network.heal();
+    }
+
+    /// Stops the node, its timers and the messages it has not handled are lost
+    pub fn crash(&mut self, node: usize) {
+        self.network.crash(node);
+        self.clock.lock().cancel_all(node);
+        self.nodes[node].discard();
+    }
+
+    /// Restarts the crashed node from its chain and wal
+    pub fn recover(&mut self, node: usize) {
+        self.network.recover(node);
+        let clock = self.clock.clone();
+        self.nodes[node].restart(node, clock);
+        self.start_node(node);
+    }
+
+    /// Runs the next event, false if there is none
+    pub fn step(&mut self) -> bool {
+        let event = self.clock.lock().pop();
+        let event = match event {
+            Some(event) => event,
+            None => return false,
+        };
+        match event {
+            Event::Deliver { from, to, payload } => {
+                if self.network.connected(from, to) {
+                    self.handle(to, CoreMessage::Message(MessageEvent { payload }));
+                }
+            }
+            Event::Timer { node, msg, .. } => {
+                if !self.network.is_crashed(node) {
+                    self.handle(node, msg);
+                }
+            }
+            Event::Propose { node, height } => {
+                if !self.network.is_crashed(node) && self.height(node) + 1 == height {
+                    let proposal = Proposal::new(self.nodes[node].next_block());
+                    self.handle(node, CoreMessage::NewHeader(NewHeaderEvent { proposal }));
+                }
+            }
+            Event::Sync => {
+                self.sync();
+                let sync_interval = self.config.sync_interval;
+                self.clock.lock().schedule(sync_interval, Event::Sync);
+            }
+        }
+        true
+    }
+
+    /// Runs the events of the next duration
+    pub fn run_for(&mut self, duration: Duration) {
+        let deadline = self.now() + duration;
+        self.run_until(deadline, |_| false);
+    }
+
+    /// Runs until the condition holds or the virtual time reaches the deadline, true if it holds
+    pub fn run_until<F: Fn(&Simulation) -> bool>(&mut self, deadline: Duration, done: F) -> bool {
+        loop {
+            if done(self) {
+                return true;
+            }
+            let next_time = self.clock.lock().next_time();
+            match next_time {
+                Some(time) if time <= deadline => {
+                    self.step();
+                }
+                _ => {
+                    self.clock.lock().advance(deadline);
+                    return done(self);
+                }
+            }
+        }
+    }
+
+    /// No two nodes have committed different blocks at a height
+    pub fn check_safety(&self) -> Result<(), String> {
+        let max_height = (0..self.nodes.len()).map(|node| self.height(node)).max().unwrap_or(0);
+        for height in 1..=max_height {
+            let mut committed: Option<(usize, Hash)> = None;
+            for node in 0..self.nodes.len() {
+                let hash = match self.block_hash(node, height) {
+                    Some(hash) => hash,
+                    None => continue,
+                };
+                match committed {
+                    Some((first, ref first_hash)) if *first_hash != hash => {
+                        return Err(format!(
+                            "conflicting blocks at height {}, node {}: {:?}, node {}: {:?}",
+                            height, first, first_hash, node, hash
+                        ));
+                    }
+                    Some(_) => {}
+                    None => committed = Some((node, hash)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn start_node(&mut self, index: usize) {
+        self.nodes[index].core.start();
+        let inbound = self.nodes[index].inbound();
+        self.handle_all(index, inbound);
+        self.after_commit(index);
+    }
+
+    fn handle(&mut self, index: usize, msg: CoreMessage) {
+        self.handle_all(index, vec![msg]);
+        self.after_commit(index);
+    }
+
+    // the messages sent to self are handled at once, before the next event
+    fn handle_all(&mut self, index: usize, messages: Vec<CoreMessage>) {
+        let mut pending: VecDeque<CoreMessage> = messages.into();
+        while let Some(msg) = pending.pop_front() {
+            let node = &mut self.nodes[index];
+            // the future blocks are fetched by the periodic sync
+            node.core.handle(msg);
+            pending.extend(node.inbound());
+        }
+        let outbound = self.nodes[index].outbound();
+        for msg in outbound {
+            self.send(index, msg);
+        }
+    }
+
+    fn send(&mut self, from: usize, msg: GossipMessage) {
+        let payload = msg.into_bytes();
+        for to in 0..self.nodes.len() {
+            if to == from {
+                continue;
+            }
+            if let Some(latency) = self.network.latency(from, to) {
+                let payload = payload.clone();
+                self.clock.lock().schedule(latency, Event::Deliver { from, to, payload });
+            }
+        }
+    }
+
+    // the miner builds the next block after the chain moves, the proposer is finalized as the seal does
+    fn after_commit(&mut self, index: usize) {
+        let node = &mut self.nodes[index];
+        let height = node.chain.get_last_height();
+        if node.proposing > height {
+            return;
+        }
+        node.proposing = height + 1;
+        let proposed = height > 0 && node.chain.get_last_block().header().proposer == node.key_pair.address();
+        let block_period = self.config.block_period;
+        self.clock
+            .lock()
+            .schedule(block_period, Event::Propose { node: index, height: height + 1 });
+        if proposed {
+            self.handle_all(index, vec![CoreMessage::FinalCommitted(FinalCommittedEvent {})]);
+        }
+    }
+
+    // every node behind fetches the blocks from the highest peer it reaches
+    fn sync(&mut self) {
+        for index in 0..self.nodes.len() {
+            let height = self.height(index);
+            let best = (0..self.nodes.len())
+                .filter(|peer| *peer != index && self.network.connected(index, *peer))
+                .max_by_key(|peer| (self.height(*peer), std::cmp::Reverse(*peer)));
+            let peer = match best {
+                Some(peer) if self.height(peer) > height => peer,
+                _ => continue,
+            };
+            for next in height + 1..=self.height(peer) {
+                let block = self.nodes[peer].chain.get_block_by_height(next).unwrap();
+                let node = &self.nodes[index];
+                if let Err(err) = node.chain.import_block(&block, &node.verifier) {
+                    warn!("Failed to sync the block, node: {}, height: {}, err: {}", index, next, err);
+                    break;
+                }
+            }
+            self.after_commit(index);
+        }
+    }
+}
+
+fn genesis_config(key_pairs: &[KeyPair]) -> GenesisConfig {
+    let validators: Vec<String> = key_pairs
+        .iter()
+        .map(|key_pair| format!("{:#x}", key_pair.address()))
+        .collect();
+    GenesisConfig {
+        proposer: validators[0].clone(),
+        validator: validators,
+        accounts: Table::new(),
+        epoch_time: "2018-09-09T09:09:09+08:00".parse().unwrap(),
+        gas_used: 0,
+        extra: "simulation".to_string(),
+        epoch: crate::core::governance::DEFAULT_EPOCH,
+        min_delegate_stake: crate::consensus::dpos::delegates::DEFAULT_MIN_STAKE,
+        padded_merkle_height: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lossy_network() -> NetworkConfig {
+        NetworkConfig {
+            drop_rate: 0.1,
+            reorder_rate: 0.2,
+            ..NetworkConfig::default()
+        }
+    }
+
+    #[test]
+    fn t_commit_blocks() {
+        let mut simulation = Simulation::new(SimConfig::default());
+        assert!(simulation.run_until(Duration::from_secs(60), |simulation| simulation.min_height() >= 5));
+        simulation.check_safety().unwrap();
+    }
+
+    #[test]
+    fn t_lossy_network() {
+        let config = SimConfig {
+            seed: 3,
+            network: lossy_network(),
+            ..SimConfig::default()
+        };
+        let mut simulation = Simulation::new(config);
+        assert!(simulation.run_until(Duration::from_secs(300), |simulation| simulation.min_height() >= 5));
+        simulation.check_safety().unwrap();
+    }
+
+    #[test]
+    fn t_partition() {
+        let mut simulation = Simulation::new(SimConfig::default());
+        assert!(simulation.run_until(Duration::from_secs(60), |simulation| simulation.min_height() >= 2));
+
+        // no side has a quorum of 3
+        simulation.partition(&[&[0, 1], &[2, 3]]);
+        simulation.run_for(Duration::from_secs(5));
+        let stalled = (0..4).map(|node| simulation.height(node)).max().unwrap();
+        simulation.run_for(Duration::from_secs(30));
+        assert_eq!(stalled, (0..4).map(|node| simulation.height(node)).max().unwrap());
+        simulation.check_safety().unwrap();
+
+        simulation.heal();
+        let deadline = simulation.now() + Duration::from_secs(120);
+        assert!(simulation.run_until(deadline, |simulation| simulation.min_height() >= stalled + 3));
+        simulation.check_safety().unwrap();
+    }
+
+    #[test]
+    fn t_crash_fault() {
+        let mut simulation = Simulation::new(SimConfig::default());
+        assert!(simulation.run_until(Duration::from_secs(60), |simulation| simulation.min_height() >= 2));
+
+        // 3 of 4 validators are a quorum
+        simulation.crash(3);
+        let height = simulation.min_height();
+        let deadline = simulation.now() + Duration::from_secs(120);
+        assert!(simulation.run_until(deadline, |simulation| simulation.min_height() >= height + 3));
+
+        simulation.recover(3);
+        let height = (0..3).map(|node| simulation.height(node)).max().unwrap();
+        let deadline = simulation.now() + Duration::from_secs(120);
+        assert!(simulation.run_until(deadline, |simulation| simulation.height(3) >= height + 2));
+        simulation.check_safety().unwrap();
+    }
+
+    #[test]
+    fn t_deterministic() {
+        let run = || {
+            let config = SimConfig {
+                seed: 11,
+                network: lossy_network(),
+                ..SimConfig::default()
+            };
+            let mut simulation = Simulation::new(config);
+            simulation.run_for(Duration::from_secs(30));
+            let height = simulation.height(0);
+            let hashes: Vec<Option<Hash>> = (1..=height).map(|height| simulation.block_hash(0, height)).collect();
+            (height, hashes, simulation.snapshot(0).view)
+        };
+        let first = run();
+        assert!(first.0 > 0);
+        assert_eq!(first, run());
+    }
+
+    // node 0 misbehaves, the 3 honest validators are a quorum
+    fn run_byzantine(modes: Vec<ByzantineMode>) -> Simulation {
+        let mut byzantine = BTreeMap::new();
+        byzantine.insert(0, modes);
+        let config = SimConfig {
+            byzantine,
+            ..SimConfig::default()
+        };
+        let mut simulation = Simulation::new(config);
+        let honest = |simulation: &Simulation| (1..4).all(|node| simulation.height(node) >= 5);
+        assert!(simulation.run_until(Duration::from_secs(300), honest));
+        simulation.check_safety().unwrap();
+        simulation
+    }
+
+    // the honest validators catch node 0 and commit the evidence
+    fn run_equivocation(modes: Vec<ByzantineMode>) {
+        let mut simulation = run_byzantine(modes);
+        let offender = simulation.nodes[0].key_pair.address();
+        let committed = |simulation: &Simulation| (1..4).all(|node| !simulation.evidences(node).is_empty());
+        assert!(simulation.run_until(Duration::from_secs(300), committed));
+        for node in 1..4 {
+            for evidence in simulation.evidences(node) {
+                assert_eq!(evidence.offender().unwrap(), offender);
+            }
+        }
+        simulation.check_safety().unwrap();
+    }
+
+    #[test]
+    fn t_byzantine_equivocate_preprepare() {
+        run_byzantine(vec![ByzantineMode::EquivocatePreprepare]);
+    }
+
+    #[test]
+    fn t_byzantine_conflicting_votes() {
+        run_byzantine(vec![ByzantineMode::ConflictingVotes]);
+    }
+
+    #[test]
+    fn t_evidence_conflicting_votes() {
+        run_equivocation(vec![ByzantineMode::ConflictingVotes]);
+    }
+
+    #[test]
+    fn t_evidence_equivocate_preprepare() {
+        run_equivocation(vec![ByzantineMode::EquivocatePreprepare]);
+    }
+
+    #[test]
+    fn t_byzantine_withhold_votes() {
+        run_byzantine(vec![ByzantineMode::WithholdVotes]);
+    }
+
+    #[test]
+    fn t_byzantine_delay() {
+        run_byzantine(vec![ByzantineMode::Delay { millis: 2000 }]);
+    }
+
+    #[test]
+    fn t_byzantine_invalid_proposal() {
+        run_byzantine(vec![ByzantineMode::InvalidProposal]);
+    }
+
+    #[test]
+    fn t_byzantine_spam_round_change() {
+        run_byzantine(vec![ByzantineMode::SpamRoundChange { rounds: 3 }]);
+    }
+}
@@ -0,0 +1,134 @@
+//! The simulated network between the nodes, every message gets a seeded random latency or is lost.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    pub min_latency: Duration,
+    pub max_latency: Duration,
+    /// the probability that a message is lost
+    pub drop_rate: f64,
+    /// the probability that a message is held back by `reorder_delay`, so later ones overtake it
+    pub reorder_rate: f64,
+    pub reorder_delay: Duration,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            min_latency: Duration::from_millis(10),
+            max_latency: Duration::from_millis(50),
+            drop_rate: 0.0,
+            reorder_rate: 0.0,
+            reorder_delay: Duration::from_millis(300),
+        }
+    }
+}
+
+pub(crate) struct Network {
+    config: NetworkConfig,
+    rng: StdRng,
+    // the partition of every node, all nodes are in one if it is none
+    partitions: Option<Vec<usize>>,
+    crashed: HashSet<usize>,
+}
+
+impl Network {
+    pub(crate) fn new(config: NetworkConfig, seed: u64) -> Self {
+        let mut bytes = [0u8; 32];
+        bytes[..8].copy_from_slice(&seed.to_le_bytes());
+        Network {
+            config,
+            rng: StdRng::from_seed(bytes),
+            partitions: None,
+            crashed: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn set_config(&mut self, config: NetworkConfig) {
+        self.config = config;
+    }
+
+    /// Splits the nodes, a node not in the groups is isolated
+    pub(crate) fn partition(&mut self, nodes: usize, groups: &[&[usize]]) {
+        let mut partitions: Vec<usize> = (0..nodes).map(|node| groups.len() + node).collect();
+        for (index, group) in groups.iter().enumerate() {
+            for node in group.iter() {
+                partitions[*node] = index;
+            }
+        }
+        self.partitions = Some(partitions);
+    }
+
+    pub(crate) fn heal(&mut self) {
+        self.partitions = None;
+    }
+
+    pub(crate) fn crash(&mut self, node: usize) {
+        self.crashed.insert(node);
+    }
+
+    pub(crate) fn recover(&mut self, node: usize) {
+        self.crashed.remove(&node);
+    }
+
+    pub(crate) fn is_crashed(&self, node: usize) -> bool {
+        self.crashed.contains(&node)
+    }
+
+    pub(crate) fn connected(&self, from: usize, to: usize) -> bool {
+        if self.is_crashed(from) || self.is_crashed(to) {
+            return false;
+        }
+        self.partitions
+            .as_ref()
+            .map_or(true, |partitions| partitions[from] == partitions[to])
+    }
+
+    /// The latency of a message sent now, none if it is lost
+    pub(crate) fn latency(&mut self, from: usize, to: usize) -> Option<Duration> {
+        if !self.connected(from, to) {
+            return None;
+        }
+        // draw every number for every message, so a fault does not shift the rest of the run
+        let dropped = self.rng.gen::<f64>() < self.config.drop_rate;
+        let reordered = self.rng.gen::<f64>() < self.config.reorder_rate;
+        let min = self.config.min_latency.as_millis() as u64;
+        let max = self.config.max_latency.as_millis() as u64;
+        let mut latency = Duration::from_millis(self.rng.gen_range(min, max + 1));
+        if dropped {
+            return None;
+        }
+        if reordered {
+            latency += self.config.reorder_delay;
+        }
+        Some(latency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_partition() {
+        let mut network = Network::new(NetworkConfig::default(), 1);
+        network.partition(4, &[&[0, 1], &[2]]);
+        assert!(network.connected(0, 1));
+        assert!(!network.connected(1, 2));
+        // node 3 is in no group
+        assert!(!network.connected(3, 0));
+        assert!(network.latency(2, 0).is_none());
+
+        network.heal();
+        network.crash(3);
+        assert!(network.connected(1, 2));
+        assert!(!network.connected(3, 0));
+        let latency = network.latency(0, 2).unwrap();
+        assert!(latency >= Duration::from_millis(10) && latency <= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,159 @@
+//! A validator of the simulation, the real core and backend on a chain of its own.
+
+use std::sync::Arc;
+
+use crossbeam::channel::{Receiver, Sender};
+use cryptocurrency_kit::ethkey::{KeyPair, Secret};
+use lru_time_cache::LruCache;
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+use super::clock::{Clock, SimTimer};
+use super::SimConfig;
+use crate::{
+    common,
+    config::{Config, GenesisConfig},
+    consensus::backend::new_impl_backend,
+    consensus::consensus::{Engine, HeaderVerifier},
+    consensus::pbft::core::{core::CoreState, runner::{CoreHandle, CoreMessage}},
+    minner::build_block,
+    core::{chain::Chain, ledger::{LastMeta, Ledger}, transaction_pool::TransactionPool, tx_pool::SafeTxPool},
+    protocol::GossipMessage,
+    store::schema::Schema,
+    subscriber::events::{BroadcastEvent, BroadcastEventBus, MAX_MAILBOX_CAPACITY},
+    types::block::Block,
+    types::{Height, Validator},
+};
+
+pub(crate) struct SimNode {
+    pub(crate) key_pair: KeyPair,
+    pub(crate) chain: Arc<Chain>,
+    pub(crate) core: CoreState,
+    pub(crate) verifier: HeaderVerifier,
+    // no transaction is submitted yet, the blocks carry the coinbase only
+    tx_pool: RwLock<SafeTxPool>,
+    // the height the miner proposes next
+    pub(crate) proposing: Height,
+    core_tx: Sender<CoreMessage>,
+    core_rx: Receiver<CoreMessage>,
+    broadcast_bus: BroadcastEventBus,
+    outbound: broadcast::Receiver<BroadcastEvent>,
+}
+
+impl SimNode {
+    pub(crate) fn new(
+        index: usize,
+        key_pair: KeyPair,
+        genesis: &GenesisConfig,
+        config: &SimConfig,
+        clock: Arc<Mutex<Clock>>,
+    ) -> Self {
+        let chain = open_chain(index, genesis, config);
+        let (core_tx, core_rx) = crossbeam::channel::unbounded();
+        let broadcast_bus = BroadcastEventBus::new(MAX_MAILBOX_CAPACITY);
+        let outbound = broadcast_bus.subscribe();
+        let verifier = new_impl_backend(key_pair.clone(), chain.clone(), broadcast_bus.clone()).header_verifier();
+        let core = new_core(index, &key_pair, &chain, &broadcast_bus, &core_tx, clock);
+        SimNode {
+            key_pair,
+            chain,
+            core,
+            verifier,
+            tx_pool: RwLock::new(Box::new(TransactionPool::default()) as SafeTxPool),
+            proposing: 0,
+            core_tx,
+            core_rx,
+            broadcast_bus,
+            outbound,
+        }
+    }
+
+    /// Replaces the core by a new one on the chain and the wal, as a restarted node
+    pub(crate) fn restart(&mut self, index: usize, clock: Arc<Mutex<Clock>>) {
+        self.discard();
+        self.core = new_core(index, &self.key_pair, &self.chain, &self.broadcast_bus, &self.core_tx, clock);
+        self.proposing = 0;
+    }
+
+    /// The messages the core sent to itself
+    pub(crate) fn inbound(&self) -> Vec<CoreMessage> {
+        self.core_rx.try_iter().collect()
+    }
+
+    /// The consensus messages the core gossiped to the validators
+    pub(crate) fn outbound(&mut self) -> Vec<GossipMessage> {
+        let mut messages = vec![];
+        loop {
+            match self.outbound.try_recv() {
+                Ok(BroadcastEvent::Consensus(msg)) => messages.push(msg),
+                Ok(_) => {}
+                Err(TryRecvError::Lagged(count)) => warn!("Lost {} messages of the simulated node", count),
+                Err(_) => break,
+            }
+        }
+        messages
+    }
+
+    /// Drops the messages not handled or sent yet, they are lost with a crashed node
+    pub(crate) fn discard(&mut self) {
+        self.inbound();
+        self.outbound();
+    }
+
+    /// Builds the next block by the miner, one block period after the last block of the simulated clock
+    pub(crate) fn next_block(&self) -> Block {
+        let time = self.chain.get_last_block().header().time + self.chain.config.block_period.as_secs();
+        build_block(&self.chain, &self.key_pair, &self.tx_pool, time)
+    }
+}
+
+/// The key of the validator, derived from the seed so the proposers are the same in every run
+pub(crate) fn sim_key_pair(seed: u64, index: usize) -> KeyPair {
+    let mut secret = [0u8; 32];
+    secret[..8].copy_from_slice(&seed.to_be_bytes());
+    secret[24..].copy_from_slice(&(index as u64 + 1).to_be_bytes());
+    KeyPair::from_secret(Secret::from(secret)).unwrap()
+}
+
+fn open_chain(index: usize, genesis: &GenesisConfig, config: &SimConfig) -> Arc<Chain> {
+    let validators = genesis
+        .validator
+        .iter()
+        .map(|validator| Validator::new(common::string_to_address(validator).unwrap()))
+        .collect();
+    let ledger = Ledger::new(
+        LastMeta::new_zero(),
+        LruCache::with_capacity(1 << 10),
+        LruCache::with_capacity(1 << 10),
+        validators,
+        Schema::new_in_memory(),
+    );
+    let chain_config = Config {
+        block_period: config.block_period,
+        request_time: config.request_time,
+        genesis: Some(genesis.clone()),
+        byzantine: config.byzantine.get(&index).cloned().unwrap_or_default(),
+        ..Config::default()
+    };
+    let mut chain = Chain::new(chain_config, Arc::new(RwLock::new(ledger)));
+    chain.store_genesis_block().unwrap();
+    Arc::new(chain)
+}
+
+fn new_core(
+    index: usize,
+    key_pair: &KeyPair,
+    chain: &Arc<Chain>,
+    broadcast_bus: &BroadcastEventBus,
+    core_tx: &Sender<CoreMessage>,
+    clock: Arc<Mutex<Clock>>,
+) -> CoreState {
+    let mut backend = new_impl_backend(key_pair.clone(), chain.clone(), broadcast_bus.clone());
+    backend.set_core_handle(CoreHandle::new(core_tx.clone()));
+    CoreState::new(
+        chain.clone(),
+        Box::new(backend),
+        key_pair.clone(),
+        Box::new(SimTimer::new(index, clock)),
+    )
+}
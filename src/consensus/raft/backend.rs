@@ -0,0 +1,181 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crossbeam::channel::{Receiver, RecvTimeoutError};
+use cryptocurrency_kit::ethkey::{verify_address, Address, Message};
+
+use super::runner::RaftHandle;
+use crate::{
+    consensus::config::Config,
+    consensus::consensus::{Engine, HeaderVerifier, SealVerifier},
+    consensus::error::{EngineError, EngineResult},
+    consensus::types::Proposal,
+    core::chain::Chain,
+    types::block::{Block, Header},
+};
+
+pub fn new_raft_backend(chain: Arc<Chain>, handle: RaftHandle, commit_rx: Receiver<Block>) -> RaftBackend {
+    let config = Config {
+        request_time: chain.config.request_time.as_millis() as u64,
+        block_period: chain.config.block_period.as_secs(),
+        chain_id: chain.config.chain_id,
+    };
+    RaftBackend {
+        handle: Some(handle),
+        commit_rx,
+        chain,
+        started: false,
+        config,
+    }
+}
+
+/// Engine side of raft, the block is sealed once it is committed by the raft log,
+/// its only vote is the leader's signature.
+#[derive(Clone)]
+pub struct RaftBackend {
+    handle: Option<RaftHandle>,
+    // committed blocks from the raft core
+    commit_rx: Receiver<Block>,
+    chain: Arc<Chain>,
+    started: bool,
+    config: Config,
+}
+
+impl Engine for RaftBackend {
+    fn start(&mut self) -> Result<(), String> {
+        if self.started {
+            panic!("Engine start only once");
+        }
+        self.started = true;
+        info!("Raft engine start successfully");
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), String> {
+        if let Some(ref h) = self.handle {
+            h.send_stop();
+        }
+        self.handle = None;
+        self.started = false;
+        Ok(())
+    }
+
+    fn author(&self, header: &Header) -> Result<Address, String> {
+        Ok(header.proposer)
+    }
+
+    fn verify_header(&self, header: &Header, seal: bool) -> EngineResult {
+        if header.height == 0 {
+            return Err(EngineError::InvalidHeight);
+        }
+        let parent_header = {
+            self.chain
+                .get_header_by_height(header.height - 1)
+                .ok_or(EngineError::UnknownAncestor(header.height, header.height - 1))?
+        };
+        if parent_header.block_hash() != header.prev_hash {
+            return Err(EngineError::Unknown(
+                format!("parent hash({:?}) != heaer.prev hash({:?})", parent_header.block_hash(), header.prev_hash),
+            ));
+        }
+        if header.time < parent_header.time + self.config.block_period {
+            return Err(EngineError::InvalidTimestamp);
+        }
+        if seal {
+            self.verify_seal(header)?;
+        }
+        Ok(())
+    }
+
+    // only the leader proposes, the leader must be a member and the seal is its signature of the block hash
+    fn verify_seal(&self, header: &Header) -> EngineResult {
+        let validators = self.chain.get_validators(header.height);
+        if !validators.iter().any(|validator| *validator.address() == header.proposer) {
+            return Err(EngineError::Unauthorized);
+        }
+        let votes = header.votes.as_ref().ok_or(EngineError::LackVotes(1, 0))?;
+        if votes.len() != 1 {
+            return Err(EngineError::LackVotes(1, votes.len()));
+        }
+        let message = Message::from_slice(header.block_hash().as_ref());
+        match verify_address(&header.proposer, &votes.votes()[0], &message) {
+            Ok(true) => Ok(()),
+            _ => Err(EngineError::InvalidSignature),
+        }
+    }
+
+    fn new_chain_header(&mut self, proposal: &Proposal) -> EngineResult {
+        if !self.started {
+            return Err(EngineError::EngineNotStarted);
+        }
+        let handle = self.handle.as_ref().ok_or(EngineError::EngineNotStarted)?;
+        handle.send_propose(proposal.block().clone());
+        Ok(())
+    }
+
+    fn prepare(&mut self, header: &mut Header) -> Result<(), String> {
+        self.chain
+            .get_header_by_height(header.height - 1)
+            .ok_or("not found parent block for the header".to_string())?;
+        header.votes = None;
+        Ok(())
+    }
+
+    fn finalize(&mut self, _header: &Header) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn header_verifier(&self) -> HeaderVerifier {
+        let backend = self.clone();
+        Arc::new(move |header: &Header| backend.verify_header(header, true))
+    }
+
+    fn seal_verifier(&self) -> SealVerifier {
+        let backend = self.clone();
+        Arc::new(move |header: &Header| backend.verify_seal(header))
+    }
+
+    fn seal(&mut self, new_block: &mut Block, abort: Receiver<()>) -> EngineResult {
+        if !self.started {
+            return Err(EngineError::EngineNotStarted);
+        }
+
+        let header = new_block.mut_header();
+        let delay_ms = {
+            let now_ms = chrono::Local::now().timestamp_millis() as u64;
+            let target_ms = header.time * 1000;
+            target_ms.saturating_sub(now_ms)
+        };
+        info!(
+            "⛏️⛏️⛏👷️ Minnig next block, hash:{:?}, height:{:?}, delay: {}ms",
+            header.block_hash().short(), header.height, delay_ms);
+        ::std::thread::sleep(Duration::from_millis(delay_ms));
+
+        self.prepare(header).map_err(EngineError::Unknown)?;
+        self.new_chain_header(&Proposal(new_block.clone()))?;
+        let new_hash = new_block.hash();
+        let new_height = new_block.height();
+
+        loop {
+            if abort.try_recv().is_ok() {
+                trace!("seal abort, height={}", new_height);
+                return Err(EngineError::Interrupt);
+            }
+            match self.commit_rx.recv_timeout(Duration::from_secs(1)) {
+                Ok(block) => {
+                    if block.hash() == new_hash {
+                        // take the leader's seal
+                        new_block.mut_header().votes = block.votes().cloned();
+                        self.finalize(new_block.header()).map_err(EngineError::Unknown)?;
+                        break Ok(());
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(_) => {
+                    trace!("seal commit_rx closed, height={}", new_height);
+                    return Err(EngineError::Interrupt);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,641 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crossbeam::channel::{Receiver as CrossbeamReceiver, Sender as CrossbeamSender};
+use cryptocurrency_kit::crypto::Hash;
+use cryptocurrency_kit::ethkey::{Address, KeyPair};
+use cryptocurrency_kit::storage::values::StorageValue;
+use tokio::sync::mpsc;
+
+use super::{
+    runner::{RaftCoreMessage, RaftHandle},
+    types::{
+        log_up_to_date, quorum, AppendEntries, AppendResponse, Entry, HardState, RaftMessage,
+        RaftMessageType, RequestVote, Role, Term, Vote,
+    },
+};
+use crate::{
+    consensus::config::Config,
+    consensus::events::OpCMD,
+    core::chain::Chain,
+    error::ChainError,
+    subscriber::events::{BroadcastEvent, BroadcastEventBus},
+    types::block::Block,
+    types::Height,
+};
+
+// the max entries carried by a AppendEntries message
+const MAX_APPEND_ENTRIES: usize = 20;
+
+/// Raft core - run loop only
+pub struct RaftCore;
+
+pub struct RaftState {
+    pub config: Config,
+    address: Address,
+    keypair: KeyPair,
+    pub role: Role,
+    hard_state: HardState,
+    leader: Option<Address>,
+    members: Vec<Address>,
+    // uncommitted entries, the committed log is the chain itself
+    log: Vec<Entry>,
+    votes: HashSet<Address>,
+    next_height: HashMap<Address, Height>,
+    match_height: HashMap<Address, Height>,
+    chain: Arc<Chain>,
+    broadcast_bus: BroadcastEventBus,
+    commit_tx: CrossbeamSender<Block>,
+
+    handle: RaftHandle,
+    election_timer_handle: Option<tokio::task::JoinHandle<()>>,
+    heartbeat_timer_handle: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl RaftState {
+    fn last_committed(&self) -> Height {
+        self.chain.get_last_height()
+    }
+
+    fn last_height(&self) -> Height {
+        self.log
+            .last()
+            .map_or(self.last_committed(), |entry| entry.block.height())
+    }
+
+    fn last_term(&self) -> Term {
+        self.log
+            .last()
+            .map_or(self.hard_state.commit_term, |entry| entry.term)
+    }
+
+    fn entry_at(&self, height: Height) -> Option<Entry> {
+        if height <= self.last_committed() {
+            return self.chain.get_block_by_height(height).map(|block| Entry {
+                term: self.hard_state.commit_term,
+                block,
+            });
+        }
+        self.log
+            .iter()
+            .find(|entry| entry.block.height() == height)
+            .cloned()
+    }
+
+    fn hash_at(&self, height: Height) -> Option<Hash> {
+        if height <= self.last_committed() {
+            return self.chain.get_block_hash_by_height(height);
+        }
+        self.log
+            .iter()
+            .find(|entry| entry.block.height() == height)
+            .map(|entry| entry.block.hash())
+    }
+
+    // drop the entries which has been written into the chain by other way, eg: block sync
+    fn compact_log(&mut self) {
+        let committed = self.last_committed();
+        self.log.retain(|entry| entry.block.height() > committed);
+    }
+
+    fn persist(&self) {
+        let ledger = self.chain.get_ledger().write();
+        let mut hard_state = ledger.get_schema().raft_hard_state();
+        hard_state.set(self.hard_state.clone());
+    }
+
+    // the entries from the height are appended or truncated, they must be flushed before acking the leader.
+    // the stored entries committed meanwhile are dropped in the same write.
+    fn persist_log(&self, from: Height) {
+        let committed = self.last_committed();
+        let ledger = self.chain.get_ledger().write();
+        let schema = ledger.get_schema();
+        let raft_log = schema.raft_log();
+        let mut batch = schema.batch();
+        raft_log
+            .keys()
+            .filter(|height| *height <= committed || *height >= from)
+            .for_each(|height| raft_log.remove_in(&mut batch, &height));
+        self.log
+            .iter()
+            .filter(|entry| entry.block.height() >= from)
+            .for_each(|entry| raft_log.put_in(&mut batch, &entry.block.height(), entry.clone()));
+        schema.write_batch(batch);
+    }
+
+    fn reset_election_timer(&mut self) {
+        if let Some(h) = self.election_timer_handle.take() {
+            h.abort();
+        }
+        let handle = self.handle.clone();
+        // randomized in [request_time, 2 * request_time) to split the votes
+        let base = self.config.request_time.max(1);
+        let timeout_ms = base + rand::random::<u64>() % base;
+        self.election_timer_handle = Some(tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(timeout_ms)).await;
+            handle.send_election_timeout();
+        }));
+    }
+
+    fn reset_heartbeat_timer(&mut self) {
+        if let Some(h) = self.heartbeat_timer_handle.take() {
+            h.abort();
+        }
+        let handle = self.handle.clone();
+        let interval_ms = (self.config.request_time / 5).max(50);
+        self.heartbeat_timer_handle = Some(tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(interval_ms)).await;
+            handle.send_heartbeat();
+        }));
+    }
+
+    fn stop_timer(&mut self) {
+        if let Some(h) = self.election_timer_handle.take() {
+            h.abort();
+        }
+        if let Some(h) = self.heartbeat_timer_handle.take() {
+            h.abort();
+        }
+    }
+
+    fn send(&mut self, code: RaftMessageType, to: Option<Address>, payload: Vec<u8>) {
+        let mut msg = RaftMessage::new(code, self.hard_state.term, to, payload);
+        msg.address = self.address;
+        msg.set_sign(self.keypair.secret());
+        self.broadcast_bus.send(BroadcastEvent::Raft(msg));
+    }
+
+    fn peers(&self) -> Vec<Address> {
+        self.members
+            .iter()
+            .filter(|address| **address != self.address)
+            .cloned()
+            .collect()
+    }
+
+    fn become_follower(&mut self, term: Term, leader: Option<Address>) {
+        if term > self.hard_state.term {
+            self.hard_state.term = term;
+            self.hard_state.voted_for = None;
+            self.persist();
+        }
+        if self.role != Role::Follower {
+            debug!("Step down to follower, term: {}", term);
+        }
+        self.role = Role::Follower;
+        self.leader = leader;
+        if let Some(h) = self.heartbeat_timer_handle.take() {
+            h.abort();
+        }
+        self.reset_election_timer();
+    }
+
+    fn become_leader(&mut self) {
+        info!(
+            "Become leader, term: {}, height: {}",
+            self.hard_state.term,
+            self.last_height()
+        );
+        self.role = Role::Leader;
+        self.leader = Some(self.address);
+        if let Some(h) = self.election_timer_handle.take() {
+            h.abort();
+        }
+        let next = self.last_height() + 1;
+        self.next_height.clear();
+        self.match_height.clear();
+        for peer in self.peers() {
+            self.next_height.insert(peer, next);
+            self.match_height.insert(peer, 0);
+        }
+        self.broadcast_append();
+        self.reset_heartbeat_timer();
+    }
+
+    fn handle_election_timeout(&mut self) {
+        if self.role == Role::Leader {
+            return;
+        }
+        // only members can be elected
+        if !self.members.contains(&self.address) {
+            self.reset_election_timer();
+            return;
+        }
+        self.compact_log();
+        self.role = Role::Candidate;
+        self.leader = None;
+        self.hard_state.term += 1;
+        self.hard_state.voted_for = Some(self.address);
+        self.persist();
+        self.votes.clear();
+        self.votes.insert(self.address);
+        info!("Start election, term: {}", self.hard_state.term);
+
+        self.reset_election_timer();
+        if self.votes.len() >= quorum(self.members.len()) {
+            self.become_leader();
+            return;
+        }
+        let request = RequestVote {
+            last_height: self.last_height(),
+            last_term: self.last_term(),
+        };
+        self.send(RaftMessageType::RequestVote, None, request.into_bytes());
+    }
+
+    fn handle_heartbeat(&mut self) {
+        if self.role != Role::Leader {
+            return;
+        }
+        self.compact_log();
+        self.broadcast_append();
+        self.reset_heartbeat_timer();
+    }
+
+    fn handle_propose(&mut self, mut block: Block) {
+        if self.role != Role::Leader {
+            debug!("Im's not leader, drop the proposal, height: {}", block.height());
+            return;
+        }
+        self.compact_log();
+        let height = block.height();
+        if height != self.last_height() + 1
+            || self.hash_at(height - 1) != Some(block.header().prev_hash)
+        {
+            debug!("Proposal is not follow the last entry, height: {}", height);
+            return;
+        }
+        if block.header().proposer != self.address {
+            debug!("Proposal is not proposed by me, height: {}", height);
+            return;
+        }
+        // the seal is the leader's signature of the block hash
+        match block.hash().sign(self.keypair.secret()) {
+            Ok(signature) => block.add_votes(vec![signature]),
+            Err(err) => {
+                error!("Failed to sign the proposal, height: {}, err: {:?}", height, err);
+                return;
+            }
+        }
+        debug!("Append a new entry, hash: {}, height: {}", block.hash().short(), height);
+        let term = self.hard_state.term;
+        self.log.push(Entry { term, block });
+        self.persist_log(height);
+        self.advance_commit();
+        self.broadcast_append();
+    }
+
+    fn handle_message(&mut self, payload: &[u8]) -> Result<(), String> {
+        let mut msg: RaftMessage = RaftMessage::from_bytes(Cow::from(payload));
+        let address = msg.address()?;
+        if address == self.address {
+            return Ok(());
+        }
+        if !self.members.contains(&address) {
+            return Err("Unauthorized address".to_string());
+        }
+        if let Some(to) = msg.to {
+            if to != self.address {
+                return Ok(());
+            }
+        }
+        trace!("Message from {}", msg.trace());
+
+        self.compact_log();
+        if msg.term > self.hard_state.term {
+            let leader = if msg.code == RaftMessageType::AppendEntries {
+                Some(address)
+            } else {
+                None
+            };
+            self.become_follower(msg.term, leader);
+        }
+        match msg.code {
+            RaftMessageType::RequestVote => self.handle_request_vote(&msg),
+            RaftMessageType::Vote => self.handle_vote(&msg),
+            RaftMessageType::AppendEntries => self.handle_append_entries(&msg),
+            RaftMessageType::AppendResponse => self.handle_append_response(&msg),
+        }
+        Ok(())
+    }
+
+    fn handle_request_vote(&mut self, msg: &RaftMessage) {
+        let request: RequestVote = RequestVote::from_bytes(Cow::from(&msg.msg));
+        let granted = msg.term == self.hard_state.term
+            && self
+                .hard_state
+                .voted_for
+                .map_or(true, |voted_for| voted_for == msg.address)
+            && log_up_to_date(
+                request.last_term,
+                request.last_height,
+                self.last_term(),
+                self.last_height(),
+            );
+        if granted {
+            self.hard_state.voted_for = Some(msg.address);
+            self.persist();
+            self.reset_election_timer();
+        }
+        debug!(
+            "Vote for {:?}, term: {}, granted: {}",
+            msg.address, msg.term, granted
+        );
+        self.send(
+            RaftMessageType::Vote,
+            Some(msg.address),
+            Vote { granted }.into_bytes(),
+        );
+    }
+
+    fn handle_vote(&mut self, msg: &RaftMessage) {
+        if self.role != Role::Candidate || msg.term != self.hard_state.term {
+            return;
+        }
+        let vote: Vote = Vote::from_bytes(Cow::from(&msg.msg));
+        if vote.granted {
+            self.votes.insert(msg.address);
+            if self.votes.len() >= quorum(self.members.len()) {
+                self.become_leader();
+            }
+        }
+    }
+
+    fn handle_append_entries(&mut self, msg: &RaftMessage) {
+        if msg.term < self.hard_state.term {
+            let response = AppendResponse {
+                success: false,
+                match_height: self.last_height(),
+            };
+            self.send(RaftMessageType::AppendResponse, Some(msg.address), response.into_bytes());
+            return;
+        }
+        if self.role != Role::Follower || self.leader != Some(msg.address) {
+            self.become_follower(msg.term, Some(msg.address));
+        } else {
+            self.reset_election_timer();
+        }
+
+        let append: AppendEntries = AppendEntries::from_bytes(Cow::from(&msg.msg));
+        if self.hash_at(append.prev_height) != Some(append.prev_hash) {
+            // hint the leader where to retry from
+            let response = AppendResponse {
+                success: false,
+                match_height: self.last_height().min(append.prev_height.saturating_sub(1)),
+            };
+            self.send(RaftMessageType::AppendResponse, Some(msg.address), response.into_bytes());
+            return;
+        }
+
+        let committed = self.last_committed();
+        let mut match_height = append.prev_height;
+        // the first height appended or truncated
+        let mut changed: Option<Height> = None;
+        for entry in append.entries {
+            let height = entry.block.height();
+            if height != match_height + 1 || self.hash_at(match_height) != Some(entry.block.header().prev_hash) {
+                break;
+            }
+            if height > committed {
+                match self.log.iter().position(|e| e.block.height() == height) {
+                    Some(pos) if self.log[pos].term == entry.term && self.log[pos].block.hash() == entry.block.hash() => {}
+                    Some(pos) => {
+                        // conflict, delete the existing entry and all that follow it
+                        self.log.truncate(pos);
+                        self.log.push(entry);
+                        changed = changed.or(Some(height));
+                    }
+                    None => {
+                        self.log.push(entry);
+                        changed = changed.or(Some(height));
+                    }
+                }
+            } else if self.hash_at(height) != Some(entry.block.hash()) {
+                break;
+            }
+            match_height = height;
+        }
+
+        if let Some(from) = changed {
+            self.persist_log(from);
+        }
+        self.commit_to(append.leader_commit.min(match_height));
+        let response = AppendResponse {
+            success: true,
+            match_height,
+        };
+        self.send(RaftMessageType::AppendResponse, Some(msg.address), response.into_bytes());
+    }
+
+    fn handle_append_response(&mut self, msg: &RaftMessage) {
+        if self.role != Role::Leader || msg.term != self.hard_state.term {
+            return;
+        }
+        let response: AppendResponse = AppendResponse::from_bytes(Cow::from(&msg.msg));
+        // the hint is always smaller than the last sent height, so the retry terminates
+        self.next_height.insert(msg.address, response.match_height + 1);
+        if response.success {
+            let match_height = self.match_height.entry(msg.address).or_insert(0);
+            if response.match_height > *match_height {
+                *match_height = response.match_height;
+            }
+            self.advance_commit();
+            if response.match_height < self.last_height() {
+                self.send_append(msg.address);
+            }
+        } else {
+            self.send_append(msg.address);
+        }
+    }
+
+    fn broadcast_append(&mut self) {
+        for peer in self.peers() {
+            self.send_append(peer);
+        }
+    }
+
+    fn send_append(&mut self, peer: Address) {
+        let last_height = self.last_height();
+        let next = self
+            .next_height
+            .get(&peer)
+            .cloned()
+            .unwrap_or(last_height + 1)
+            .max(1);
+        let prev_height = next - 1;
+        let prev_hash = match self.hash_at(prev_height) {
+            Some(prev_hash) => prev_hash,
+            None => return,
+        };
+        let entries: Vec<Entry> = (next..=last_height)
+            .take(MAX_APPEND_ENTRIES)
+            .filter_map(|height| self.entry_at(height))
+            .collect();
+        let append = AppendEntries {
+            prev_height,
+            prev_hash,
+            entries,
+            leader_commit: self.last_committed(),
+        };
+        self.send(RaftMessageType::AppendEntries, Some(peer), append.into_bytes());
+    }
+
+    // only the entries of current term are committed by counting replicas (raft §5.4.2)
+    fn advance_commit(&mut self) {
+        let quorum = quorum(self.members.len());
+        let committed = self.last_committed();
+        let mut commit_to = committed;
+        for entry in &self.log {
+            let height = entry.block.height();
+            if entry.term != self.hard_state.term {
+                continue;
+            }
+            let replicated = 1 + self
+                .match_height
+                .values()
+                .filter(|match_height| **match_height >= height)
+                .count();
+            if replicated >= quorum {
+                commit_to = height;
+            }
+        }
+        if commit_to > committed {
+            self.commit_to(commit_to);
+        }
+    }
+
+    fn commit_to(&mut self, height: Height) {
+        while self.last_committed() < height {
+            let next = self.last_committed() + 1;
+            let entry = match self.log.iter().position(|e| e.block.height() == next) {
+                Some(pos) => self.log.remove(pos),
+                None => break,
+            };
+            match self.chain.insert_block(&entry.block) {
+                Ok(()) | Err(ChainError::Exists(_)) => {}
+                Err(err) => {
+                    error!("Failed to commit entry, height: {}, err: {:?}", next, err);
+                    self.log.insert(0, entry);
+                    break;
+                }
+            }
+            debug!(
+                "Commit entry, hash: {}, height: {}, term: {}",
+                entry.block.hash().short(),
+                next,
+                entry.term
+            );
+            if entry.term > self.hard_state.commit_term {
+                self.hard_state.commit_term = entry.term;
+                self.persist();
+            }
+            let _ = self.commit_tx.try_send(entry.block);
+        }
+    }
+
+    fn handle_op_cmd(&mut self, msg: OpCMD) -> bool {
+        match msg {
+            OpCMD::Stop => {
+                self.stop_timer();
+                true
+            }
+            OpCMD::Ping => {
+                debug!("Recive a test message");
+                false
+            }
+            op @ OpCMD::Pause | op @ OpCMD::Resume | op @ OpCMD::RoundChange => {
+                debug!("{:?} is not supported by raft", op);
+                false
+            }
+        }
+    }
+}
+
+impl RaftCore {
+    /// Async run loop, same shape as pbft's Core::run.
+    pub async fn run(
+        chain: Arc<Chain>,
+        broadcast_bus: BroadcastEventBus,
+        key_pair: KeyPair,
+        commit_tx: CrossbeamSender<Block>,
+        core_rx: CrossbeamReceiver<RaftCoreMessage>,
+        handle: RaftHandle,
+    ) {
+        let (bridge_tx, mut rx) = mpsc::unbounded_channel();
+        tokio::task::spawn_blocking(move || {
+            while let Ok(msg) = core_rx.recv() {
+                if bridge_tx.send(msg).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let last_height = chain.get_last_height();
+        let members: Vec<Address> = chain
+            .get_validators(last_height + 1)
+            .iter()
+            .map(|validator| *validator.address())
+            .collect();
+        assert_ne!(members.len(), 0, "members'size should be more than zero");
+        let hard_state = chain
+            .get_ledger()
+            .read()
+            .get_schema()
+            .raft_hard_state()
+            .get()
+            .unwrap_or_default();
+        let mut log: Vec<Entry> = chain.get_ledger().read().get_schema().raft_log().values().collect();
+        log.sort_by_key(|entry| entry.block.height());
+        let config = Config {
+            request_time: chain.config.request_time.as_millis() as u64,
+            block_period: chain.config.block_period.as_secs(),
+            chain_id: chain.config.chain_id,
+        };
+
+        let mut state = RaftState {
+            config,
+            address: key_pair.address(),
+            keypair: key_pair,
+            role: Role::Follower,
+            hard_state,
+            leader: None,
+            members,
+            log,
+            votes: HashSet::new(),
+            next_height: HashMap::new(),
+            match_height: HashMap::new(),
+            chain,
+            broadcast_bus,
+            commit_tx,
+            handle,
+            election_timer_handle: None,
+            heartbeat_timer_handle: None,
+        };
+        state.compact_log();
+        state.reset_election_timer();
+
+        info!("raft run loop started, term: {}", state.hard_state.term);
+
+        while let Some(msg) = rx.recv().await {
+            match msg {
+                RaftCoreMessage::Message(payload) => {
+                    if let Err(ref e) = state.handle_message(&payload) {
+                        debug!("handle raft message err: {:?}", e);
+                    }
+                }
+                RaftCoreMessage::Propose(block) => state.handle_propose(block),
+                RaftCoreMessage::ElectionTimeout => state.handle_election_timeout(),
+                RaftCoreMessage::Heartbeat => state.handle_heartbeat(),
+                RaftCoreMessage::Op(op) => {
+                    if state.handle_op_cmd(op) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        info!("raft run loop stopped");
+    }
+}
@@ -0,0 +1,4 @@
+pub mod types;
+pub mod runner;
+pub mod core;
+pub mod backend;
@@ -0,0 +1,54 @@
+//! Raft message handle - the counterpart of pbft's CoreHandle
+
+use crossbeam::channel::Sender;
+
+use crate::consensus::consensus::ConsensusHandle;
+use crate::consensus::events::OpCMD;
+use crate::types::block::Block;
+
+/// Messages that can be sent to the raft core
+#[derive(Debug)]
+pub enum RaftCoreMessage {
+    Message(Vec<u8>),
+    Propose(Block),
+    ElectionTimeout,
+    Heartbeat,
+    Op(OpCMD),
+}
+
+#[derive(Clone)]
+pub struct RaftHandle {
+    tx: Sender<RaftCoreMessage>,
+}
+
+impl RaftHandle {
+    pub fn new(tx: Sender<RaftCoreMessage>) -> Self {
+        Self { tx }
+    }
+
+    pub fn send_message(&self, payload: Vec<u8>) {
+        let _ = self.tx.try_send(RaftCoreMessage::Message(payload));
+    }
+
+    pub fn send_propose(&self, block: Block) {
+        let _ = self.tx.try_send(RaftCoreMessage::Propose(block));
+    }
+
+    pub fn send_election_timeout(&self) {
+        let _ = self.tx.try_send(RaftCoreMessage::ElectionTimeout);
+    }
+
+    pub fn send_heartbeat(&self) {
+        let _ = self.tx.try_send(RaftCoreMessage::Heartbeat);
+    }
+
+    pub fn send_stop(&self) {
+        let _ = self.tx.try_send(RaftCoreMessage::Op(OpCMD::Stop));
+    }
+}
+
+impl ConsensusHandle for RaftHandle {
+    fn send_message(&self, payload: Vec<u8>) {
+        RaftHandle::send_message(self, payload)
+    }
+}
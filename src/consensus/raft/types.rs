@@ -0,0 +1,196 @@
+use cryptocurrency_kit::crypto::{hash, CryptoHash, Hash};
+use cryptocurrency_kit::ethkey::{public_to_address, recover_bytes};
+use cryptocurrency_kit::ethkey::{Address, Secret, Signature};
+use cryptocurrency_kit::storage::values::StorageValue;
+
+use std::borrow::Cow;
+
+use crate::{
+    types::block::Block,
+    types::{Height, EMPTY_ADDRESS},
+};
+
+pub type Term = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RaftMessageType {
+    RequestVote,
+    Vote,
+    AppendEntries,
+    AppendResponse,
+}
+
+/// Persistent raft state, it must be flushed before answering any rpc
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct HardState {
+    pub term: Term,
+    pub voted_for: Option<Address>,
+    // term of the last committed entry, the committed log lives in the chain
+    pub commit_term: Term,
+}
+
+implement_cryptohash_traits! {HardState}
+implement_storagevalue_traits! {HardState}
+
+/// A log entry, the log index is the block height
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Entry {
+    pub term: Term,
+    pub block: Block,
+}
+
+implement_cryptohash_traits! {Entry}
+implement_storagevalue_traits! {Entry}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RequestVote {
+    pub last_height: Height,
+    pub last_term: Term,
+}
+
+implement_cryptohash_traits! {RequestVote}
+implement_storagevalue_traits! {RequestVote}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Vote {
+    pub granted: bool,
+}
+
+implement_cryptohash_traits! {Vote}
+implement_storagevalue_traits! {Vote}
+
+/// Log consistency is checked by block hash instead of term, the blocks are hash chained
+/// so two logs holding the same hash at the same height are identical up to that height.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppendEntries {
+    pub prev_height: Height,
+    pub prev_hash: Hash,
+    pub entries: Vec<Entry>,
+    pub leader_commit: Height,
+}
+
+implement_cryptohash_traits! {AppendEntries}
+implement_storagevalue_traits! {AppendEntries}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppendResponse {
+    pub success: bool,
+    pub match_height: Height,
+}
+
+implement_cryptohash_traits! {AppendResponse}
+implement_storagevalue_traits! {AppendResponse}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RaftMessage {
+    pub code: RaftMessageType,
+    pub term: Term,
+    // None means the message is for every member
+    pub to: Option<Address>,
+    pub msg: Vec<u8>,
+    #[serde(default)]
+    pub signature: Option<Signature>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub address: Address,
+}
+
+implement_cryptohash_traits! {RaftMessage}
+implement_storagevalue_traits! {RaftMessage}
+
+impl RaftMessage {
+    pub fn new(code: RaftMessageType, term: Term, to: Option<Address>, msg: Vec<u8>) -> Self {
+        RaftMessage {
+            code,
+            term,
+            to,
+            msg,
+            signature: None,
+            address: *EMPTY_ADDRESS,
+        }
+    }
+
+    pub fn set_sign(&mut self, secret: &Secret) {
+        let hash = CryptoHash::hash(&self.sign_payload());
+        self.signature = Some(hash.sign(secret).unwrap());
+    }
+
+    pub fn address(&mut self) -> Result<Address, String> {
+        let result = match self.signature {
+            Some(ref signature) => {
+                let bytes = self.sign_payload();
+                recover_bytes(signature, &bytes)
+                    .map(|ref public_key| public_to_address(public_key))
+                    .map_err(|_| "failed to recover public key from signature".to_string())
+            }
+            None => Err("invalid signature".to_string()),
+        };
+
+        self.address = result?;
+        Ok(self.address)
+    }
+
+    pub(crate) fn sign_payload(&self) -> Vec<u8> {
+        let mut msg = self.clone();
+        msg.signature = None;
+        msg.into_bytes()
+    }
+
+    pub(crate) fn trace(&self) -> String {
+        format!("code:{:?}, term:{}, address:{:?}", self.code, self.term, self.address)
+    }
+}
+
+/// The number of members that make up a majority
+pub fn quorum(members: usize) -> usize {
+    members / 2 + 1
+}
+
+/// Election restriction (raft §5.4.1), a voter only grants its vote when the candidate's log
+/// is at least as up-to-date as its own.
+pub fn log_up_to_date(last_term: Term, last_height: Height, local_term: Term, local_height: Height) -> bool {
+    last_term > local_term || (last_term == local_term && last_height >= local_height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptocurrency_kit::ethkey::{Generator, Random};
+
+    #[test]
+    fn t_message_sign() {
+        let key_pair = Random.generate().unwrap();
+        let vote = RequestVote { last_height: 10, last_term: 2 };
+        let mut msg = RaftMessage::new(RaftMessageType::RequestVote, 3, None, vote.into_bytes());
+        msg.set_sign(key_pair.secret());
+
+        let mut msg = RaftMessage::from_bytes(Cow::from(msg.into_bytes()));
+        assert_eq!(msg.address().unwrap(), key_pair.address());
+        let vote = RequestVote::from_bytes(Cow::from(&msg.msg));
+        assert_eq!(vote.last_height, 10);
+        assert_eq!(vote.last_term, 2);
+    }
+
+    #[test]
+    fn t_quorum() {
+        assert_eq!(quorum(1), 1);
+        assert_eq!(quorum(2), 2);
+        assert_eq!(quorum(3), 2);
+        assert_eq!(quorum(4), 3);
+        assert_eq!(quorum(5), 3);
+    }
+
+    #[test]
+    fn t_log_up_to_date() {
+        assert!(log_up_to_date(2, 1, 1, 10));
+        assert!(log_up_to_date(2, 10, 2, 10));
+        assert!(!log_up_to_date(2, 9, 2, 10));
+        assert!(!log_up_to_date(1, 100, 2, 10));
+    }
+}
@@ -6,7 +6,10 @@ use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 
-use crate::types::{Height, block::Block};
+use crate::{
+    protocol::GossipMessage,
+    types::{Height, block::Block},
+};
 
 pub type Round = u64;
 
@@ -144,6 +147,9 @@ impl Display for Subject {
 pub struct PrePrepare {
     pub view: View,
     pub proposal: Proposal,
+    // the round change messages which justify a preprepare of round > 0
+    #[serde(default)]
+    pub justification: Vec<GossipMessage>,
 }
 
 implement_cryptohash_traits! {PrePrepare}
@@ -151,10 +157,35 @@ implement_storagevalue_traits! {PrePrepare}
 
 impl PrePrepare {
     pub fn new(view: View, proposal: Proposal) -> Self {
-        PrePrepare { view, proposal }
+        PrePrepare { view, proposal, justification: vec![] }
     }
 }
 
+/// Proves that the proposal was prepared in `view`, it holds more than 2/3 signed
+/// Prepare (or Commit) messages of the view.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PreparedCertificate {
+    pub view: View,
+    pub proposal: Proposal,
+    pub prepares: Vec<GossipMessage>,
+}
+
+implement_cryptohash_traits! {PreparedCertificate}
+implement_storagevalue_traits! {PreparedCertificate}
+
+/// RoundChange payload, it is a superset of `Subject` so it also decodes as a `Subject`.
+/// The digest is the hash of the prepared proposal or EMPTY_HASH.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RoundChange {
+    pub view: View,
+    pub digest: Hash,
+    #[serde(default)]
+    pub prepared: Option<PreparedCertificate>,
+}
+
+implement_cryptohash_traits! {RoundChange}
+implement_storagevalue_traits! {RoundChange}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1,18 +1,31 @@
+use std::collections::{BTreeMap, HashSet};
 use std::sync::Arc;
 use std::time::Instant;
 
 use parking_lot::RwLock;
+use kvdb_rocksdb::Database;
 use cryptocurrency_kit::ethkey::Address;
-use cryptocurrency_kit::crypto::Hash;
+use cryptocurrency_kit::crypto::{CryptoHash, Hash};
 
 use crate::{
     config::Config,
+    consensus::consensus::HeaderVerifier,
+    consensus::error::EngineError,
+    consensus::types::View,
     error::{ChainError, ChainResult},
-    types::{Height, Validators, Validator, transaction::Transaction, block::Block, block::Header},
+    metrics,
+    store::proof_map_index::MapProof,
+    store::schema::TxLocation,
+    types::{Height, Validators, Validator, account::Account, transaction::{transactions_root, Transaction, TransactionProof}, block::{Block, Header, ValidatorVote}, evidence::{evidence_root, Evidence}},
     subscriber::events::{ChainEvent, ChainEventBus},
 };
 use super::genesis::store_genesis_block;
+use super::governance::{is_epoch_boundary, is_valid_vote, tally_votes, DEFAULT_EPOCH};
 use super::ledger::Ledger;
+use super::state::{execute_block, pack_transactions, Executed, Packed};
+
+/// The pending evidences kept at most, the ones of the oldest views are dropped past it
+pub const MAX_PENDING_EVIDENCES: usize = 1024;
 
 pub struct Chain {
     ledger: Arc<RwLock<Ledger>>,
@@ -20,23 +33,28 @@ pub struct Chain {
     genesis: Option<Block>,
     lock: RwLock<()>,
     sync_limiter: RwLock<Instant>,
+    // the local validator's proposals, candidate => authorize
+    proposals: RwLock<BTreeMap<Address, bool>>,
     pub config: Config,
 }
 
 impl Chain {
     pub fn new(config: Config, ledger: Arc<RwLock<Ledger>>) -> Self {
+        metrics::CHAIN_HEIGHT.set(*ledger.read().get_last_block_height() as i64);
         Chain {
             ledger,
             chain_event_bus: ChainEventBus::new(1024),
             lock: RwLock::new(()),
             config,
             sync_limiter: RwLock::new(Instant::now()),
+            proposals: RwLock::new(BTreeMap::new()),
             genesis: None,
         }
     }
 
     pub fn insert_block(&self, block: &Block) -> ChainResult {
         self.lock.write();
+        let start = Instant::now();
         {
             let mut ledger = self.ledger.write();
             if ledger.get_block_by_height(block.height()).is_some() {
@@ -45,16 +63,175 @@ impl Chain {
             let last_height = ledger.get_last_block_height();
             if last_height + 1 < block.height() {
                 self.post_event(ChainEvent::SyncBlock(last_height + 1));
-                return Err(ChainError::Unknown("Not found ancestor".to_owned()));
+                return Err(ChainError::UnknownAncestor(block.height()));
             }
 
-            ledger.add_block(block);
+            let executed = check_state(&ledger, block)?;
+            ledger.commit_block(block, executed.accounts);
+            self.update_validators(&mut ledger, block.height());
         }
+        metrics::CHAIN_BLOCK_INSERT_SECONDS.observe(start.elapsed().as_secs_f64());
+        metrics::CHAIN_HEIGHT.set(block.height() as i64);
         self.chain_event_bus.send(ChainEvent::NewBlock(block.clone()));
         self.chain_event_bus.send(ChainEvent::NewHeader(block.header().clone()));
         Ok(())
     }
 
+    /// Verifies the block received from network and inserts it,
+    /// the state root is checked while the transactions are executed on insert.
+    pub fn import_block(&self, block: &Block, verify_header: &HeaderVerifier) -> ChainResult {
+        self.verify_block(block, verify_header)?;
+        self.insert_block(block)
+    }
+
+    /// Checks the parent linkage, the engine rules (proposer and seals) and the transactions
+    pub fn verify_block(&self, block: &Block, verify_header: &HeaderVerifier) -> ChainResult {
+        let header = block.header();
+        if let Some(block_hash) = self.get_block_hash_by_height(block.height()) {
+            return Err(ChainError::Exists(block_hash));
+        }
+        if block.height() == 0 {
+            return Err(ChainError::InvalidHeader("genesis block".to_string()));
+        }
+        let parent = match self.get_header_by_height(block.height() - 1) {
+            Some(parent) => parent,
+            None => {
+                self.post_event(ChainEvent::SyncBlock(self.get_last_height() + 1));
+                return Err(ChainError::UnknownAncestor(block.height()));
+            }
+        };
+        if header.prev_hash != parent.block_hash() {
+            return Err(ChainError::InvalidPrevHash(parent.block_hash(), header.prev_hash));
+        }
+        verify_header(header).map_err(|err| match err {
+            EngineError::LackVotes(expect, got) => ChainError::LackVotes(expect, got),
+            EngineError::InvalidSignature => ChainError::InvalidSeal(format!("{}", err)),
+            EngineError::Unauthorized => ChainError::InvalidProposer(header.proposer),
+            err => ChainError::InvalidHeader(format!("{}", err)),
+        })?;
+
+        let transactions = block.transactions();
+        if let Some(transaction) = transactions.iter().find(|transaction| !transaction.verify_sign(self.config.chain_id)) {
+            return Err(ChainError::InvalidTransaction(transaction.hash()));
+        }
+        let tx_hash = self.transactions_root(header.height, transactions.clone());
+        if tx_hash != header.tx_hash {
+            return Err(ChainError::InvalidTransactionRoot(header.tx_hash, tx_hash));
+        }
+        self.verify_evidences(block)
+    }
+
+    /// Checks the evidences of the block against `Header::evidence_hash`, every one must be
+    /// valid and committed once
+    pub fn verify_evidences(&self, block: &Block) -> ChainResult {
+        let evidences = block.evidences();
+        let evidence_hash = evidence_root(evidences);
+        if evidence_hash != block.header().evidence_hash {
+            return Err(ChainError::InvalidEvidence(format!(
+                "root mismatch, expect: {:?}, got: {:?}",
+                block.header().evidence_hash,
+                evidence_hash
+            )));
+        }
+        let committed = self.ledger.read().get_schema().committed_evidences();
+        let mut included = HashSet::new();
+        for evidence in evidences {
+            self.verify_evidence(evidence, block.height())?;
+            let hash = evidence.hash();
+            if !included.insert(hash) || committed.contains(&hash) {
+                return Err(ChainError::InvalidEvidence(format!("{:?} is committed twice", hash)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the evidence of an equivocation until a block includes it, it is gossiped once.
+    /// Returns false if the evidence is known or older than all of a full pool.
+    pub fn add_evidence(&self, evidence: Evidence) -> Result<bool, ChainError> {
+        let view = self.verify_evidence(&evidence, self.get_last_height() + 1)?;
+        let hash = evidence.hash();
+        {
+            let ledger = self.ledger.write();
+            let schema = ledger.get_schema();
+            let mut pending = schema.evidences();
+            if pending.contains(&hash) || schema.committed_evidences().contains(&hash) {
+                return Ok(false);
+            }
+            let pending_views: Vec<(Hash, View)> = pending
+                .iter()
+                .filter_map(|(hash, evidence)| evidence.view().ok().map(|view| (hash, view)))
+                .collect();
+            if pending_views.len() >= MAX_PENDING_EVIDENCES {
+                let oldest = pending_views
+                    .into_iter()
+                    .min_by_key(|(_, view)| (view.height, view.round))
+                    .filter(|(_, oldest_view)| (oldest_view.height, oldest_view.round) < (view.height, view.round));
+                match oldest {
+                    Some((oldest, _)) => pending.remove(&oldest),
+                    None => return Ok(false),
+                }
+            }
+            pending.put(&hash, evidence.clone());
+        }
+        self.post_event(ChainEvent::Evidence(evidence));
+        Ok(true)
+    }
+
+    /// Returns the evidences not included in a block yet
+    pub fn pending_evidences(&self, limit: usize) -> Vec<Evidence> {
+        self.ledger.read().get_schema().evidences().values().take(limit).collect()
+    }
+
+    /// Returns the height of the block that includes the evidence
+    pub fn get_evidence_height(&self, hash: &Hash) -> Option<Height> {
+        self.ledger.read().get_schema().committed_evidences().get(hash)
+    }
+
+    // the offender must be a validator of the view, and the view no later than the block
+    fn verify_evidence(&self, evidence: &Evidence, height: Height) -> Result<View, ChainError> {
+        let (offender, view) = evidence.verify().map_err(ChainError::InvalidEvidence)?;
+        if view.height > height {
+            return Err(ChainError::InvalidEvidence(format!("future view, height: {}", view.height)));
+        }
+        if self.get_validators(view.height).iter().all(|validator| *validator.address() != offender) {
+            return Err(ChainError::InvalidEvidence(format!("{:?} is not a validator", offender)));
+        }
+        Ok(view)
+    }
+
+    /// Re-executes the transactions of the next block and checks its state root and gas used
+    pub fn verify_state(&self, block: &Block) -> Result<Executed, ChainError> {
+        let ledger = self.ledger.read();
+        let last_height = *ledger.get_last_block_height();
+        if block.height() != last_height + 1 {
+            return Err(ChainError::UnknownAncestor(block.height()));
+        }
+        check_state(&ledger, block)
+    }
+
+    /// Executes the transactions on the last state, it is used to fill the header of a new block
+    pub fn execute_transactions(&self, header: &Header, transactions: &[Transaction]) -> Result<Executed, ChainError> {
+        let ledger = self.ledger.read();
+        execute_block(&ledger, header, transactions).map_err(ChainError::State)
+    }
+
+    /// Packs the pending transactions that can be executed on the last state into a new block
+    pub fn pack_transactions(&self, header: &Header, coinbase: Transaction, pending: Vec<Transaction>) -> Result<Packed, ChainError> {
+        let ledger = self.ledger.read();
+        pack_transactions(&ledger, header, coinbase, pending).map_err(ChainError::State)
+    }
+
+    pub fn get_account(&self, address: &Address) -> Account {
+        self.ledger.read().get_account(address).unwrap_or_default()
+    }
+
+    /// Returns the last header with the account and its proof against `Header::root`
+    pub fn get_account_proof(&self, address: &Address) -> (Header, Option<Account>, MapProof) {
+        let ledger = self.ledger.read();
+        let (account, proof) = ledger.get_account_proof(address);
+        (ledger.get_last_block_header().clone(), account, proof)
+    }
+
     pub fn insert_block_mock(block: &Block, ledger: Arc<RwLock<Ledger>>) -> ChainResult {
         info!("Ready insert a new block, hash: {}, height: {}", block.hash().short(), block.height());
         {
@@ -72,6 +249,11 @@ impl Chain {
         &self.ledger
     }
 
+    /// The rocksdb of the ledger, none for the in-memory database
+    pub fn get_database(&self) -> Option<Arc<Database>> {
+        self.ledger.read().get_schema().database().cloned()
+    }
+
     pub fn get_last_height(&self) -> Height {
         *self.ledger.read().get_last_block_height()
     }
@@ -96,6 +278,34 @@ impl Chain {
         self.ledger.read().get_transactions()
     }
 
+    /// Returns at most `limit` blocks from the height
+    pub fn get_blocks(&self, from: Height, limit: u64) -> Vec<Block> {
+        let last_height = self.get_last_height();
+        (from..=last_height)
+            .take(limit as usize)
+            .filter_map(|height| self.get_block_by_height(height))
+            .collect()
+    }
+
+    /// Returns the committed transaction with its location
+    pub fn get_transaction(&self, tx_hash: &Hash) -> Option<(Transaction, TxLocation)> {
+        let ledger = self.ledger.read();
+        let transaction = ledger.get_transaction(tx_hash)?;
+        let location = ledger.get_transaction_location(tx_hash)?;
+        Some((transaction, location))
+    }
+
+    /// Returns the merkle audit path of the transaction to the `tx_hash` of its block, none for the blocks
+    /// below `padded_merkle_height`, their root has no audit path
+    pub fn get_transaction_proof(&self, tx_hash: &Hash) -> Option<TransactionProof> {
+        let location = self.ledger.read().get_transaction_location(tx_hash)?;
+        if location.block_height < self.padded_merkle_height() {
+            return None;
+        }
+        let block = self.get_block_by_height(location.block_height)?;
+        TransactionProof::new(&block, location.position_in_block as usize)
+    }
+
     pub fn get_block_hash_by_height(&self, height: Height) -> Option<Hash> {
         self.ledger.read().get_block_hash_by_height(height)
     }
@@ -108,15 +318,78 @@ impl Chain {
         *self.ledger.read().get_last_block_hash()
     }
 
-    pub fn add_validators(&self, _height: Height, validators: Vec<Address>) -> ChainResult {
+    pub fn add_validators(&self, height: Height, validators: Vec<Address>) -> ChainResult {
         let validators = validators.iter().map(|address| Validator::new(*address)).collect();
-        self.ledger.write().add_validators(validators);
+        self.ledger.write().add_validators(height, validators);
         Ok(())
     }
 
+    /// Returns the validators who seal the block of the height
     pub fn get_validators(&self, height: Height) -> Validators {
-        let ledger = self.ledger.write();
-        ledger.get_validators(height).clone()
+        let ledger = self.ledger.read();
+        ledger.get_validators(height)
+    }
+
+    pub fn epoch(&self) -> Height {
+        self.config.genesis.as_ref().map_or(DEFAULT_EPOCH, |genesis| genesis.epoch)
+    }
+
+    /// The first height whose `tx_hash` pads every odd level of the merkle tree
+    pub fn padded_merkle_height(&self) -> Height {
+        self.config.genesis.as_ref().map_or(0, |genesis| genesis.padded_merkle_height)
+    }
+
+    /// The `tx_hash` of the block at `height`
+    pub fn transactions_root(&self, height: Height, transactions: Vec<Transaction>) -> Hash {
+        transactions_root(transactions, height, self.padded_merkle_height())
+    }
+
+    /// Votes to add(`authorize`) or remove the validator in the blocks sealed by local node
+    pub fn propose_validator(&self, address: Address, authorize: bool) {
+        self.proposals.write().insert(address, authorize);
+    }
+
+    /// Returns true if there was a proposal for the address
+    pub fn discard_proposal(&self, address: &Address) -> bool {
+        self.proposals.write().remove(address).is_some()
+    }
+
+    pub fn proposals(&self) -> Vec<ValidatorVote> {
+        self.proposals
+            .read()
+            .iter()
+            .map(|(address, authorize)| ValidatorVote { address: *address, authorize: *authorize })
+            .collect()
+    }
+
+    /// Returns a vote for the block of the height, the proposals that make no change are skipped
+    pub fn next_vote(&self, height: Height) -> Option<ValidatorVote> {
+        let validators: Vec<Address> = self.get_validators(height).iter().map(|validator| *validator.address()).collect();
+        self.proposals
+            .read()
+            .iter()
+            .map(|(address, authorize)| ValidatorVote { address: *address, authorize: *authorize })
+            .find(|vote| is_valid_vote(&validators, vote))
+    }
+
+    // persists the validators of the next height, the votes of the epoch are applied at its boundary
+    fn update_validators(&self, ledger: &mut Ledger, height: Height) {
+        let validators = ledger.get_validators(height);
+        let epoch = self.epoch();
+        if !is_epoch_boundary(height, epoch) {
+            ledger.add_validators(height + 1, validators);
+            return;
+        }
+        let addresses: Vec<Address> = validators.iter().map(|validator| *validator.address()).collect();
+        let votes: Vec<(Address, ValidatorVote)> = (height + 1 - epoch..=height)
+            .filter_map(|height| ledger.get_header_by_height(height))
+            .filter_map(|header| header.vote.map(|vote| (header.proposer, vote)))
+            .collect();
+        let next: Validators = tally_votes(&addresses, &votes).into_iter().map(Validator::new).collect();
+        if next != validators {
+            info!("Validator set changed at epoch boundary, height: {}, validators: {}", height, next.len());
+        }
+        ledger.add_validators(height + 1, next);
     }
 
     pub fn get_genesis(&self) -> &Block {
@@ -155,6 +428,18 @@ impl Chain {
     }
 }
 
+fn check_state(ledger: &Ledger, block: &Block) -> Result<Executed, ChainError> {
+    let header = block.header();
+    let executed = execute_block(ledger, header, block.transactions()).map_err(ChainError::State)?;
+    if executed.root != header.root {
+        return Err(ChainError::InvalidStateRoot(header.root, executed.root));
+    }
+    if executed.gas_used != header.gas_used {
+        return Err(ChainError::InvalidGasUsed(header.gas_used, executed.gas_used));
+    }
+    Ok(executed)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -212,4 +497,46 @@ mod test {
 
         println!("last_block {:?}", ledger.get_last_block());
     }
+
+    #[test]
+    fn t_verify_block() {
+        let database = Database::open(&crate::store::schema::database_config(), &random_dir()).unwrap();
+        let schema = Schema::new(Arc::new(database));
+        let mut ledger = Ledger::new(
+            LastMeta::new_zero(),
+            LruCache::with_capacity(1 << 10),
+            LruCache::with_capacity(1 << 10),
+            vec![],
+            schema,
+        );
+        let genesis = Block::new(Header::new_mock(EMPTY_HASH, Address::from(10), EMPTY_HASH, 0, 100, None), vec![]);
+        ledger.add_genesis_block(&genesis);
+        ledger.reload_meta();
+        let chain = Chain::new(Config::default(), Arc::new(RwLock::new(ledger)));
+
+        let accept: HeaderVerifier = Arc::new(|_: &Header| Ok(()));
+        let lack_votes: HeaderVerifier = Arc::new(|_: &Header| Err(EngineError::LackVotes(3, 1)));
+        let new_block = |prev_hash: Hash, tx_hash: Hash| {
+            Block::new(Header::new_mock(prev_hash, Address::from(10), tx_hash, 1, 200, None), vec![])
+        };
+
+        let block = new_block(genesis.hash(), EMPTY_HASH);
+        assert!(chain.verify_block(&block, &accept).is_ok());
+        match chain.verify_block(&block, &lack_votes) {
+            Err(ChainError::LackVotes(3, 1)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match chain.verify_block(&new_block(EMPTY_HASH, EMPTY_HASH), &accept) {
+            Err(ChainError::InvalidPrevHash(_, _)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match chain.verify_block(&new_block(genesis.hash(), genesis.hash()), &accept) {
+            Err(ChainError::InvalidTransactionRoot(_, _)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match chain.verify_block(&genesis, &accept) {
+            Err(ChainError::Exists(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
@@ -1,12 +1,15 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::str::FromStr;
 
 use parking_lot::RwLock;
 
 use cryptocurrency_kit::crypto::EMPTY_HASH;
+use cryptocurrency_kit::ethkey::Address;
 
 use crate::{
     types::Timestamp,
+    types::account::Account,
     types::block::{Block, Header},
     types::{Validator, Validators},
     config::GenesisConfig,
@@ -14,6 +17,7 @@ use crate::{
 };
 use super::{
     ledger::Ledger,
+    state::State,
 };
 
 pub(crate) fn store_genesis_block(genesis_config: &GenesisConfig, ledger: Arc<RwLock<Ledger>>) -> Result<(), String> {
@@ -31,9 +35,16 @@ pub(crate) fn store_genesis_block(genesis_config: &GenesisConfig, ledger: Arc<Rw
         }).map(|address| {
             Validator::new(address)
         }).collect();
-        ledger.add_validators(validators);
+        ledger.add_validators(0, validators);
     }
 
+    // add accounts
+    let root = {
+        let accounts = genesis_accounts(genesis_config)?;
+        ledger.add_accounts(accounts);
+        State::new(&ledger).root()
+    };
+
     // TODO Add more xin
     {
         let proposer = common::string_to_address(&genesis_config.proposer)?;
@@ -43,7 +54,7 @@ pub(crate) fn store_genesis_block(genesis_config: &GenesisConfig, ledger: Arc<Rw
         }.map_err(|err: ParseError| err.to_string())?;
 
         let extra = genesis_config.extra.as_bytes().to_vec();
-        let header = Header::new(EMPTY_HASH, proposer, EMPTY_HASH, EMPTY_HASH, EMPTY_HASH,
+        let header = Header::new(EMPTY_HASH, proposer, root, EMPTY_HASH, EMPTY_HASH,
                                      0, 0, 0, genesis_config.gas_used + 10, genesis_config.gas_used,
                                      epoch_time.timestamp() as Timestamp, None, Some(extra));
         let block = Block::new(header, vec![]);
@@ -53,6 +64,18 @@ pub(crate) fn store_genesis_block(genesis_config: &GenesisConfig, ledger: Arc<Rw
     Ok(())
 }
 
+/// Parses the `[genesis.accounts]` table, address => balance
+fn genesis_accounts(genesis_config: &GenesisConfig) -> Result<BTreeMap<Address, Account>, String> {
+    genesis_config.accounts.iter().map(|(address, balance)| {
+        let address = common::string_to_address(address)?;
+        let balance = balance
+            .as_integer()
+            .filter(|balance| *balance >= 0)
+            .ok_or_else(|| format!("Invalid genesis balance of {:?}", address))?;
+        Ok((address, Account::new(balance as u64, 0)))
+    }).collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
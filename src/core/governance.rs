@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use cryptocurrency_kit::ethkey::Address;
+
+use crate::types::{block::ValidatorVote, Height};
+
+/// Blocks between two validator set changes
+pub const DEFAULT_EPOCH: Height = 1000;
+
+/// The votes are tallied when the block of the height is committed,
+/// the new validator set works from the next height.
+pub fn is_epoch_boundary(height: Height, epoch: Height) -> bool {
+    height > 0 && epoch > 0 && height % epoch == 0
+}
+
+/// A vote is valid if it changes the validator set: add a stranger or remove a validator.
+pub fn is_valid_vote(validators: &[Address], vote: &ValidatorVote) -> bool {
+    validators.contains(&vote.address) != vote.authorize
+}
+
+/// Tallies the (voter, vote) pairs of an epoch, the latest vote of a voter for a candidate wins.
+/// A change passes with the votes of more than half of the validators, the last validator is never removed.
+pub fn tally_votes(validators: &[Address], votes: &[(Address, ValidatorVote)]) -> Vec<Address> {
+    let mut ballots: HashMap<(Address, Address), bool> = HashMap::new();
+    votes
+        .iter()
+        .filter(|(voter, vote)| validators.contains(voter) && is_valid_vote(validators, vote))
+        .for_each(|(voter, vote)| {
+            ballots.insert((*voter, vote.address), vote.authorize);
+        });
+
+    let mut tally: HashMap<(Address, bool), usize> = HashMap::new();
+    ballots.iter().for_each(|((_, candidate), authorize)| {
+        *tally.entry((*candidate, *authorize)).or_insert(0) += 1;
+    });
+
+    let mut passed: Vec<(Address, bool)> = tally
+        .into_iter()
+        .filter(|(_, count)| *count > validators.len() / 2)
+        .map(|(change, _)| change)
+        .collect();
+    passed.sort();
+
+    let mut next = validators.to_vec();
+    for (candidate, authorize) in passed {
+        if authorize {
+            next.push(candidate);
+        } else if next.len() > 1 {
+            next.retain(|address| *address != candidate);
+        }
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vote(address: u64, authorize: bool) -> ValidatorVote {
+        ValidatorVote { address: Address::from(address), authorize }
+    }
+
+    #[test]
+    fn t_tally_votes() {
+        let validators: Vec<Address> = (1..=4).map(Address::from).collect();
+        assert!(is_epoch_boundary(20, 10));
+        assert!(!is_epoch_boundary(0, 10));
+        assert!(!is_valid_vote(&validators, &vote(1, true)));
+        assert!(!is_valid_vote(&validators, &vote(5, false)));
+
+        // 2 of 4 is not enough
+        let votes = vec![(Address::from(1), vote(5, true)), (Address::from(2), vote(5, true))];
+        assert_eq!(tally_votes(&validators, &votes), validators);
+
+        // duplicated votes count once, strangers' votes are ignored
+        let votes = vec![
+            (Address::from(1), vote(5, true)),
+            (Address::from(1), vote(5, true)),
+            (Address::from(2), vote(5, true)),
+            (Address::from(9), vote(5, true)),
+        ];
+        assert_eq!(tally_votes(&validators, &votes), validators);
+
+        let votes = vec![
+            (Address::from(1), vote(5, true)),
+            (Address::from(2), vote(5, true)),
+            (Address::from(3), vote(5, true)),
+            (Address::from(1), vote(4, false)),
+            (Address::from(2), vote(4, false)),
+            (Address::from(3), vote(4, false)),
+        ];
+        let next = tally_votes(&validators, &votes);
+        assert!(next.contains(&Address::from(5)));
+        assert!(!next.contains(&Address::from(4)));
+        assert_eq!(next.len(), 4);
+    }
+}
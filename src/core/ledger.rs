@@ -1,12 +1,18 @@
+use std::collections::BTreeMap;
+
 use cryptocurrency_kit::crypto::{CryptoHash, Hash};
+use cryptocurrency_kit::ethkey::Address;
 use lru_time_cache::LruCache;
 use parking_lot::RwLock;
 use chrono::{DateTime, NaiveDateTime, Utc};
 
 use crate::{
-    store::schema::Schema,
+    store::schema::{Schema, TxLocation},
+    store::proof_map_index::MapProof,
     types::block::{Block, Header},
     types::transaction::Transaction,
+    types::account::Account,
+    types::evidence::Evidences,
     types::{Height, Validator, ValidatorArray, HashesEntry},
 };
 
@@ -69,6 +75,10 @@ impl Ledger {
         self.schema.transaction().get(tx_hash)
     }
 
+    pub fn get_transaction_location(&self, tx_hash: &Hash) -> Option<TxLocation> {
+        self.schema.transaction_locations().get(tx_hash)
+    }
+
     pub fn get_genesis_block(&mut self) -> Option<&Block> {
         if self.genesis.is_some() {
             return self.genesis.as_ref();
@@ -120,13 +130,7 @@ impl Ledger {
         match block {
             Some(block) => Some(block.clone()),
             None => {
-                let result = self.schema.headers().get(block_hash).map(|header| {
-                    let transaction_entry = self.schema.transaction_hashes().get(block_hash).unwrap();
-                    let transactions: Vec<Transaction> = transaction_entry.0.iter().map(|hash| {
-                        self.schema.transaction().get(hash).unwrap()
-                    }).collect();
-                    Block::new(header, transactions)
-                });
+                let result = self.schema.headers().get(block_hash).map(|header| self.load_block(block_hash, header));
 
                 if let Some(block) = result {
                     cache.insert(*block_hash, block.clone());
@@ -148,8 +152,18 @@ impl Ledger {
         transactions
     }
 
-    //  FIXME store it into schema
-    pub fn get_validators(&self, _height: Height) -> &Vec<Validator> { &self.validators }
+    /// Returns the validators who seal the block of the height,
+    /// the latest set is used for a height that has not been reached.
+    pub fn get_validators(&self, height: Height) -> Vec<Validator> {
+        let validators = self.schema.validators();
+        // no set is stored beyond the next block
+        let height = height.min(self.get_last_block_height().saturating_add(1));
+        (0..=height)
+            .rev()
+            .find_map(|height| validators.get(&height))
+            .map(|val_array| val_array.addresses().iter().map(|address| Validator::new(*address)).collect())
+            .unwrap_or_else(|| self.validators.clone())
+    }
 
     pub fn get_block_by_height(&self, height: Height) -> Option<Block> {
         if let Some(block_hash) = self.schema.block_hash_by_height(height) {
@@ -157,17 +171,24 @@ impl Ledger {
                 return Some(block.clone());
             }
 
-            return self.schema.headers().get(&block_hash).map(|header| {
-                let transaction_entry = self.schema.transaction_hashes().get(&block_hash).unwrap();
-                let transactions: Vec<Transaction> = transaction_entry.0.iter().map(|block_hash| {
-                    self.schema.transaction().get(block_hash).unwrap()
-                }).collect();
-                Block::new(header, transactions)
-            });
+            return self.schema.headers().get(&block_hash).map(|header| self.load_block(&block_hash, header));
         }
         None
     }
 
+    // reads the transactions and the evidences of the stored header
+    fn load_block(&self, block_hash: &Hash, header: Header) -> Block {
+        let transaction_entry = self.schema.transaction_hashes().get(block_hash).unwrap();
+        let transactions: Vec<Transaction> = transaction_entry.0.iter().map(|hash| {
+            self.schema.transaction().get(hash).unwrap()
+        }).collect();
+        let mut block = Block::new(header, transactions);
+        if let Some(evidences) = self.schema.block_evidences().get(block_hash) {
+            block.set_evidences(evidences.0);
+        }
+        block
+    }
+
     pub fn get_header_by_height(&self, height: Height) -> Option<Header> {
         if let Some(block_hash) = self.schema.block_hash_by_height(height) {
             if let Some(header) = self.header_cache.write().get(&block_hash) {
@@ -190,39 +211,69 @@ impl Ledger {
     }
 
     pub fn add_block(&mut self, block: &Block) {
+        self.commit_block(block, BTreeMap::new());
+    }
+
+    /// Persists the block and the accounts changed by it in one write,
+    /// so a crash never leaves a block whose state root does not match the state.
+    pub fn commit_block(&mut self, block: &Block, accounts: BTreeMap<Address, Account>) {
         let header = block.header();
         let hash = header.block_hash();
         if self.meta.header.height >= header.height && block.height() != 0 {
             return;
         }
 
+        let mut batch = self.schema.batch();
         // persists
         {
 //            debug!("Write header");
-            let mut header_db = self.schema.headers();
-            header_db.put(&hash, header.clone());
+            let header_db = self.schema.headers();
+            header_db.put_in(&mut batch, &hash, header.clone());
         }
 
         // transactions
         {
             let mut tx_hashes = HashesEntry(vec![]);
-            let mut tx_db = self.schema.transaction();
+            let tx_db = self.schema.transaction();
+            let location_db = self.schema.transaction_locations();
 //            debug!("Write transaction");
-            for transaction in block.transactions() {
+            for (position, transaction) in block.transactions().iter().enumerate() {
                 let tx_hash = transaction.hash();
-                tx_db.put(&tx_hash, transaction.clone());
+                tx_db.put_in(&mut batch, &tx_hash, transaction.clone());
+                location_db.put_in(&mut batch, &tx_hash, TxLocation { block_height: block.height(), position_in_block: position as u64 });
                 tx_hashes.0.push(tx_hash);
             }
 
-            let mut tx_hashes_db = self.schema.transaction_hashes();
-            tx_hashes_db.put(&hash, tx_hashes);
+            let tx_hashes_db = self.schema.transaction_hashes();
+            tx_hashes_db.put_in(&mut batch, &hash, tx_hashes);
+        }
+
+        // evidences, they are no longer pending once committed
+        if !block.evidences().is_empty() {
+            let pending_db = self.schema.evidences();
+            let committed_db = self.schema.committed_evidences();
+            for evidence in block.evidences() {
+                let evidence_hash = evidence.hash();
+                pending_db.remove_in(&mut batch, &evidence_hash);
+                committed_db.put_in(&mut batch, &evidence_hash, block.height());
+            }
+            let block_evidences_db = self.schema.block_evidences();
+            block_evidences_db.put_in(&mut batch, &hash, Evidences(block.evidences().clone()));
+        }
+
+        // the state after the block
+        if !accounts.is_empty() {
+            self.schema
+                .accounts()
+                .apply_in(&mut batch, accounts.iter().map(|(address, account)| (address, Some(account))));
         }
 
         // height
+        self.schema.block_hashes_by_height().push_in(&mut batch, hash);
+        self.schema.write_batch(batch);
         {
-            let mut height_db = self.schema.block_hashes_by_height();
+            let height_db = self.schema.block_hashes_by_height();
 //            debug!("Write height, hash:{:?}, height:{:?}", hash.short(), block.height());
-            height_db.push(hash);
             assert_eq!(height_db.last().unwrap(), hash);
             assert_eq!(height_db.len(), block.height() + 1);
         }
@@ -246,12 +297,41 @@ impl Ledger {
         info!("📝 Insert new block, hash:{:?}, height:{}, utime:{}, proposer:{:?}", hash.short(), header.height, dt.to_rfc3339(), header.proposer);
     }
 
-    pub fn add_validators(&mut self, validators: Vec<Validator>) {
+    /// Persists the validators who seal the blocks from the height
+    pub fn add_validators(&mut self, height: Height, validators: Vec<Validator>) {
         let val_array = ValidatorArray::from(validators.clone());
-        let mut validators_entry = self.schema.validators();
-        validators_entry.set(val_array);
-        // cache it
-        self.validators = validators;
+        let mut validators_index = self.schema.validators();
+        validators_index.put(&height, val_array);
+        // cache the latest one
+        if height >= *self.get_last_block_height() {
+            self.validators = validators;
+        }
+    }
+
+    pub fn get_account(&self, address: &Address) -> Option<Account> {
+        self.schema.accounts().get(address)
+    }
+
+    /// Returns the account and its proof against the state root of the last block
+    pub fn get_account_proof(&self, address: &Address) -> (Option<Account>, MapProof) {
+        let accounts = self.schema.accounts();
+        (accounts.get(address), accounts.get_proof(address))
+    }
+
+    /// Returns the state root after the accounts are changed
+    pub fn state_root_with(&self, accounts: &BTreeMap<Address, Account>) -> Hash {
+        self.schema
+            .accounts()
+            .root_hash_with(accounts.iter().map(|(address, account)| (address, Some(account))))
+    }
+
+    /// Persists the accounts changed by a block, see `commit_block`
+    pub fn add_accounts(&mut self, accounts: BTreeMap<Address, Account>) {
+        let mut batch = self.schema.batch();
+        self.schema
+            .accounts()
+            .apply_in(&mut batch, accounts.iter().map(|(address, account)| (address, Some(account))));
+        self.schema.write_batch(batch);
     }
 
     pub fn reload_meta(&mut self) {
@@ -3,4 +3,6 @@ pub mod genesis;
 pub mod transaction_pool;
 pub mod tx_pool;
 pub mod chain;
+pub mod governance;
+pub mod state;
 pub mod actor;
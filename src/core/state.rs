@@ -0,0 +1,390 @@
+//! Account state: the transactions of a block move the balances and bump the nonces of accounts,
+//! the state root of all accounts is committed by the block header.
+
+use std::collections::BTreeMap;
+
+use cryptocurrency_kit::crypto::{CryptoHash, Hash};
+use cryptocurrency_kit::ethkey::Address;
+
+use crate::{
+    consensus::dpos::types::DelegateOp,
+    error::StateError,
+    types::{account::Account, block::Header, transaction::Transaction, Gas, Height},
+};
+use super::ledger::Ledger;
+
+/// Gas paid by every transaction
+pub const TX_GAS: Gas = 21_000;
+/// Gas paid for every byte of the payload
+pub const TX_DATA_GAS: Gas = 68;
+pub const BLOCK_GAS_LIMIT: Gas = 10_000_000;
+/// Minted to the proposer by the coinbase transaction
+pub const BLOCK_REWARD: u64 = 5_000;
+
+pub fn intrinsic_gas(transaction: &Transaction) -> Gas {
+    TX_GAS + transaction.payload().len() as Gas * TX_DATA_GAS
+}
+
+/// The coinbase is the first transaction of a block and pays no gas
+pub fn is_coinbase(index: usize, transaction: &Transaction) -> bool {
+    index == 0 && transaction.gas() == 0
+}
+
+/// The coinbase carries the height of its block instead of the proposer's nonce, so it can not be replayed
+pub fn coinbase_payload(height: Height) -> Vec<u8> {
+    Vec::from(format!("coinbase {}", height))
+}
+
+/// The result of executing the transactions of a block on its parent state
+#[derive(Debug, Clone)]
+pub struct Executed {
+    pub root: Hash,
+    pub gas_used: Gas,
+    /// the changed accounts
+    pub accounts: BTreeMap<Address, Account>,
+}
+
+/// The accounts changed by the executing block over the persisted state
+pub struct State<'a> {
+    ledger: &'a Ledger,
+    dirty: BTreeMap<Address, Account>,
+}
+
+impl<'a> State<'a> {
+    pub fn new(ledger: &'a Ledger) -> Self {
+        State { ledger, dirty: BTreeMap::new() }
+    }
+
+    pub fn account(&self, address: &Address) -> Account {
+        self.dirty
+            .get(address)
+            .cloned()
+            .or_else(|| self.ledger.get_account(address))
+            .unwrap_or_default()
+    }
+
+    fn put_account(&mut self, address: Address, account: Account) {
+        self.dirty.insert(address, account);
+    }
+
+    /// Mints the block reward to the proposer, the nonce of the proposer is untouched
+    pub fn apply_coinbase(&mut self, transaction: &Transaction, header: &Header) -> Result<(), StateError> {
+        let tx_hash = transaction.hash();
+        let sender = transaction.sender().ok_or(StateError::InvalidSignature(tx_hash))?;
+        let proposer = &header.proposer;
+        if sender != *proposer
+            || transaction.to() != Some(proposer)
+            || transaction.amount() != BLOCK_REWARD
+            || transaction.payload() != coinbase_payload(header.height).as_slice()
+        {
+            return Err(StateError::InvalidCoinbase(tx_hash));
+        }
+        let mut account = self.account(proposer);
+        account.balance = account.balance.checked_add(BLOCK_REWARD).ok_or(StateError::Overflow(tx_hash))?;
+        self.put_account(sender, account);
+        Ok(())
+    }
+
+    /// Transfers the amount and charges the intrinsic gas to the proposer, returns the gas used.
+    /// The sender must afford the whole gas limit. The amount of a delegate vote is locked on the sender
+    /// instead, the last locked stake is released by the next vote or unvote.
+    pub fn apply_transaction(&mut self, transaction: &Transaction, proposer: &Address) -> Result<Gas, StateError> {
+        let tx_hash = transaction.hash();
+        let sender = transaction.sender().ok_or(StateError::InvalidSignature(tx_hash))?;
+        let recipient = *transaction.to().ok_or(StateError::MissingRecipient(tx_hash))?;
+        let gas_used = intrinsic_gas(transaction);
+        if transaction.gas() < gas_used {
+            return Err(StateError::IntrinsicGas(tx_hash, gas_used, transaction.gas()));
+        }
+
+        let mut from = self.account(&sender);
+        if from.nonce != transaction.nonce() {
+            return Err(StateError::InvalidNonce(sender, from.nonce, transaction.nonce()));
+        }
+        let max_cost = transaction
+            .gas()
+            .checked_mul(transaction.gas_price())
+            .and_then(|fee| fee.checked_add(transaction.amount()))
+            .ok_or(StateError::Overflow(tx_hash))?;
+        if from.balance < max_cost {
+            return Err(StateError::InsufficientBalance(sender, max_cost, from.balance));
+        }
+        let fee = gas_used * transaction.gas_price();
+        from.balance -= transaction.amount() + fee;
+        from.nonce += 1;
+        let op = DelegateOp::from_payload(transaction.payload());
+        let voting = matches!(op, Some(DelegateOp::Vote(_)));
+        if voting || op == Some(DelegateOp::Unvote) {
+            from.balance = from.balance.checked_add(from.locked).ok_or(StateError::Overflow(tx_hash))?;
+            from.locked = 0;
+        }
+        if voting {
+            from.locked = transaction.amount();
+        }
+        self.put_account(sender, from);
+
+        if !voting {
+            let mut to = self.account(&recipient);
+            to.balance = to.balance.checked_add(transaction.amount()).ok_or(StateError::Overflow(tx_hash))?;
+            self.put_account(recipient, to);
+        }
+
+        let mut coinbase = self.account(proposer);
+        coinbase.balance = coinbase.balance.checked_add(fee).ok_or(StateError::Overflow(tx_hash))?;
+        self.put_account(*proposer, coinbase);
+        Ok(gas_used)
+    }
+
+    /// The root of the sparse merkle tree of all accounts, `EMPTY_HASH` if there is none
+    pub fn root(&self) -> Hash {
+        self.ledger.state_root_with(&self.dirty)
+    }
+
+    pub fn into_changes(self) -> BTreeMap<Address, Account> {
+        self.dirty
+    }
+}
+
+/// Executes the transactions of the block on the last state of the ledger
+pub fn execute_block(ledger: &Ledger, header: &Header, transactions: &[Transaction]) -> Result<Executed, StateError> {
+    if header.gas_limit > BLOCK_GAS_LIMIT {
+        return Err(StateError::GasLimitReached(BLOCK_GAS_LIMIT, header.gas_limit));
+    }
+    let mut state = State::new(ledger);
+    let mut gas_used: Gas = 0;
+    for (index, transaction) in transactions.iter().enumerate() {
+        if is_coinbase(index, transaction) {
+            state.apply_coinbase(transaction, header)?;
+            continue;
+        }
+        let gas = intrinsic_gas(transaction);
+        if gas_used + gas > header.gas_limit {
+            return Err(StateError::GasLimitReached(header.gas_limit, gas_used + gas));
+        }
+        gas_used += state.apply_transaction(transaction, &header.proposer)?;
+    }
+    Ok(Executed {
+        root: state.root(),
+        gas_used,
+        accounts: state.into_changes(),
+    })
+}
+
+/// The transactions packed into a new block and the result of executing them
+#[derive(Debug, Clone)]
+pub struct Packed {
+    pub transactions: Vec<Transaction>,
+    pub executed: Executed,
+    /// the pending transactions that can never be executed, e.g. a stale nonce
+    pub dropped: Vec<Hash>,
+}
+
+/// Packs the coinbase and the pending transactions that can be executed in order until the gas limit,
+/// the higher gas price comes first. A transaction waiting for a lower nonce is retried after the others.
+pub fn pack_transactions(
+    ledger: &Ledger,
+    header: &Header,
+    coinbase: Transaction,
+    mut pending: Vec<Transaction>,
+) -> Result<Packed, StateError> {
+    let mut state = State::new(ledger);
+    state.apply_coinbase(&coinbase, header)?;
+    let mut transactions = vec![coinbase];
+    let mut dropped = vec![];
+    let mut gas_used: Gas = 0;
+
+    pending.sort_by(|a, b| b.gas_price().cmp(&a.gas_price()).then(a.nonce().cmp(&b.nonce())));
+    loop {
+        let packed = transactions.len();
+        pending.retain(|transaction| {
+            if gas_used + intrinsic_gas(transaction) > header.gas_limit {
+                return true;
+            }
+            let checkpoint = state.dirty.clone();
+            match state.apply_transaction(transaction, &header.proposer) {
+                Ok(gas) => {
+                    gas_used += gas;
+                    transactions.push(transaction.clone());
+                    false
+                }
+                // a future nonce
+                Err(StateError::InvalidNonce(_, expect, got)) if got > expect => {
+                    state.dirty = checkpoint;
+                    true
+                }
+                Err(err) => {
+                    debug!("Drop the pending transaction, err: {}", err);
+                    state.dirty = checkpoint;
+                    dropped.push(transaction.hash());
+                    false
+                }
+            }
+        });
+        if transactions.len() == packed {
+            break;
+        }
+    }
+    Ok(Packed {
+        executed: Executed {
+            root: state.root(),
+            gas_used,
+            accounts: state.dirty,
+        },
+        transactions,
+        dropped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use cryptocurrency_kit::crypto::EMPTY_HASH;
+    use cryptocurrency_kit::ethkey::{Generator, KeyPair, Random};
+    use kvdb_rocksdb::Database;
+    use lru_time_cache::LruCache;
+
+    use super::*;
+    use crate::common::random_dir;
+    use crate::core::ledger::LastMeta;
+    use crate::store::schema::Schema;
+
+    fn new_ledger() -> Ledger {
+        let database = Database::open(&crate::store::schema::database_config(), &random_dir()).unwrap();
+        Ledger::new(
+            LastMeta::new_zero(),
+            LruCache::with_capacity(1 << 10),
+            LruCache::with_capacity(1 << 10),
+            vec![],
+            Schema::new(Arc::new(database)),
+        )
+    }
+
+    fn coinbase(proposer: &KeyPair, height: Height) -> Transaction {
+        let mut transaction = Transaction::new(0, proposer.address(), BLOCK_REWARD, 0, 0, coinbase_payload(height));
+        transaction.sign(0, proposer.secret());
+        transaction
+    }
+
+    fn transfer(key_pair: &KeyPair, nonce: u64, to: Address, amount: u64) -> Transaction {
+        let mut transaction = Transaction::new(nonce, to, amount, TX_GAS, 1, vec![]);
+        transaction.sign(0, key_pair.secret());
+        transaction
+    }
+
+    #[test]
+    fn t_execute_block() {
+        let (proposer, alice) = (Random.generate().unwrap(), Random.generate().unwrap());
+        let bob = Address::from(100);
+        let mut ledger = new_ledger();
+        assert_eq!(State::new(&ledger).root(), EMPTY_HASH);
+        ledger.add_accounts(vec![(alice.address(), Account::new(100_000, 0))].into_iter().collect());
+        let genesis_root = State::new(&ledger).root();
+
+        let mut header = Header::new_mock(EMPTY_HASH, proposer.address(), EMPTY_HASH, 1, 100, None);
+        header.gas_limit = BLOCK_GAS_LIMIT;
+        let transactions = vec![coinbase(&proposer, 1), transfer(&alice, 0, bob, 10), transfer(&alice, 1, bob, 20)];
+
+        let executed = execute_block(&ledger, &header, &transactions).unwrap();
+        assert_eq!(executed.gas_used, 2 * TX_GAS);
+        assert_eq!(executed.accounts[&alice.address()], Account::new(100_000 - 30 - 2 * TX_GAS, 2));
+        assert_eq!(executed.accounts[&bob], Account::new(30, 0));
+        assert_eq!(executed.accounts[&proposer.address()], Account::new(BLOCK_REWARD + 2 * TX_GAS, 0));
+        assert_ne!(executed.root, genesis_root);
+        // deterministic
+        assert_eq!(execute_block(&ledger, &header, &transactions).unwrap().root, executed.root);
+        // the coinbase of another height
+        match execute_block(&ledger, &header, &[coinbase(&proposer, 2)]) {
+            Err(StateError::InvalidCoinbase(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // nonce gap
+        match execute_block(&ledger, &header, &[transfer(&alice, 1, bob, 10)]) {
+            Err(StateError::InvalidNonce(_, 0, 1)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // can not afford the gas limit
+        match execute_block(&ledger, &header, &[transfer(&alice, 0, bob, 100_000)]) {
+            Err(StateError::InsufficientBalance(..)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        header.gas_limit = TX_GAS;
+        match execute_block(&ledger, &header, &transactions[1..]) {
+            Err(StateError::GasLimitReached(..)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        ledger.add_accounts(executed.accounts);
+        assert_eq!(State::new(&ledger).root(), executed.root);
+    }
+
+    #[test]
+    fn t_vote_locks_stake() {
+        let (proposer, alice) = (Random.generate().unwrap(), Random.generate().unwrap());
+        let delegate = Address::from(100);
+        let mut ledger = new_ledger();
+        ledger.add_accounts(vec![(alice.address(), Account::new(100_000, 0))].into_iter().collect());
+        let mut header = Header::new_mock(EMPTY_HASH, proposer.address(), EMPTY_HASH, 1, 100, None);
+        header.gas_limit = BLOCK_GAS_LIMIT;
+        let delegate_tx = |nonce: u64, amount: u64, op: DelegateOp| {
+            let payload = op.into_payload();
+            let gas = TX_GAS + payload.len() as Gas * TX_DATA_GAS;
+            let mut transaction = Transaction::new(nonce, alice.address(), amount, gas, 0, payload);
+            transaction.sign(0, alice.secret());
+            transaction
+        };
+
+        let vote = delegate_tx(0, 1_000, DelegateOp::Vote(delegate));
+        let executed = execute_block(&ledger, &header, &[vote]).unwrap();
+        let account = executed.accounts[&alice.address()];
+        assert_eq!((account.balance, account.locked), (99_000, 1_000));
+        ledger.add_accounts(executed.accounts);
+
+        // the locked stake can not be spent
+        match execute_block(&ledger, &header, &[transfer(&alice, 1, delegate, 99_500)]) {
+            Err(StateError::InsufficientBalance(..)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // moving the ballot releases the last stake
+        let executed = execute_block(&ledger, &header, &[delegate_tx(1, 500, DelegateOp::Vote(delegate))]).unwrap();
+        let account = executed.accounts[&alice.address()];
+        assert_eq!((account.balance, account.locked), (99_500, 500));
+        ledger.add_accounts(executed.accounts);
+
+        let executed = execute_block(&ledger, &header, &[delegate_tx(2, 0, DelegateOp::Unvote)]).unwrap();
+        let account = executed.accounts[&alice.address()];
+        assert_eq!((account.balance, account.locked), (100_000, 0));
+        assert!(!executed.accounts.contains_key(&delegate));
+    }
+
+    #[test]
+    fn t_pack_transactions() {
+        let (proposer, alice) = (Random.generate().unwrap(), Random.generate().unwrap());
+        let bob = Address::from(100);
+        let mut ledger = new_ledger();
+        let accounts = vec![(alice.address(), Account::new(100_000, 0)), (proposer.address(), Account::new(100_000, 0))];
+        ledger.add_accounts(accounts.into_iter().collect());
+
+        let mut header = Header::new_mock(EMPTY_HASH, proposer.address(), EMPTY_HASH, 1, 100, None);
+        header.gas_limit = BLOCK_GAS_LIMIT;
+        // out of nonce order, a future one and one can not be afforded
+        let pending = vec![
+            transfer(&alice, 1, bob, 20),
+            transfer(&alice, 0, bob, 10),
+            transfer(&proposer, 5, bob, 10),
+            transfer(&alice, 2, bob, 100_000),
+            transfer(&proposer, 0, bob, 10),
+        ];
+
+        let packed = pack_transactions(&ledger, &header, coinbase(&proposer, 1), pending.clone()).unwrap();
+        let nonces: Vec<u64> = packed.transactions.iter().skip(1).map(|tx| tx.nonce()).collect();
+        // the coinbase does not take the proposer's nonce
+        assert_eq!(nonces, vec![0, 0, 1]);
+        assert_eq!(packed.dropped, vec![pending[3].hash()]);
+        // the proposer's transaction of nonce 5 waits for the nonce
+        assert_eq!(packed.executed.gas_used, 3 * TX_GAS);
+        let executed = execute_block(&ledger, &header, &packed.transactions).unwrap();
+        assert_eq!(executed.root, packed.executed.root);
+    }
+}
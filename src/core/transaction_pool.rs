@@ -1,79 +1,275 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::mem;
 use std::sync::Arc;
 
-use ethereum_types::U256;
 use cryptocurrency_kit::{
     ethkey::Address,
-    crypto::Hash,
+    crypto::{CryptoHash, Hash},
+};
+use transaction_pool::{
+    scoring::{Change, Choice},
+    ErrorKind, Options, Pool, Readiness, Ready, Scoring, VerifiedTransaction,
 };
-use transaction_pool::VerifiedTransaction;
-
 
 use crate::{
-    types::Gas,
+    core::chain::Chain,
+    core::tx_pool::TxPool,
+    metrics,
     types::transaction::Transaction,
+    error::TxPoolError,
 };
 
-#[derive(Debug, Default, Clone)]
-pub struct TransactionBuilder {
-    nonce: U256,
-    gas_price: Gas,
-    gas: Gas,
-    sender: Address,
-    mem_usage: usize,
+pub const MAX_TXPOOL_SIZE: usize = 8192;
+pub const MAX_PER_SENDER: usize = 64;
+pub const MAX_MEM_USAGE: usize = 32 * 1024 * 1024;
+/// A transaction replaces the one of same nonce if its gas price is higher by the percent at least
+pub const PRICE_BUMP_PERCENT: u64 = 10;
+
+impl VerifiedTransaction for Transaction {
+    type Hash = Hash;
+    type Sender = Address;
+
+    fn hash(&self) -> &Hash {
+        self.get_hash().expect("the hash is set before the transaction enters the pool")
+    }
+
+    fn mem_usage(&self) -> usize {
+        mem::size_of::<Transaction>() + self.payload().len()
+    }
+
+    fn sender(&self) -> &Address {
+        self.get_sender().expect("the sender is recovered before the transaction enters the pool")
+    }
+}
+
+pub type SharedTransaction = Arc<Transaction>;
+
+/// The next nonce of the accounts, the pool reads it to tell the ready transactions from the future ones
+pub trait AccountNonce {
+    fn nonce(&self, address: &Address) -> u64;
 }
 
-impl TransactionBuilder {
-    pub fn tx(&self) -> Self {
-        self.clone()
+impl AccountNonce for Chain {
+    fn nonce(&self, address: &Address) -> u64 {
+        self.get_account(address).nonce
     }
+}
 
-    pub fn nonce<T: Into<U256>>(mut self, nonce: T) -> Self {
-        self.nonce = nonce.into();
-        self
+impl AccountNonce for HashMap<Address, u64> {
+    fn nonce(&self, address: &Address) -> u64 {
+        self.get(address).cloned().unwrap_or(0)
     }
+}
+
+/// The transactions of a sender are ordered by nonce, the senders are prioritized by gas price
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GasPriceScoring;
 
-    pub fn gas_price<T: Into<Gas>>(mut self, gas_price: T) -> Self {
-        self.gas_price = gas_price.into();
-        self
+impl Scoring<Transaction> for GasPriceScoring {
+    type Score = u64;
+    type Event = ();
+
+    fn compare(&self, old: &Transaction, other: &Transaction) -> cmp::Ordering {
+        old.nonce().cmp(&other.nonce())
     }
 
-    pub fn sender<T: Into<Address>>(mut self, sender: T) -> Self {
-        self.sender = sender.into();
-        self
+    fn choose(&self, old: &Transaction, new: &Transaction) -> Choice {
+        if old.nonce() != new.nonce() {
+            return Choice::InsertNew;
+        }
+        let min_price = old.gas_price().saturating_add(old.gas_price() * PRICE_BUMP_PERCENT / 100);
+        if new.gas_price() > old.gas_price() && new.gas_price() >= min_price {
+            Choice::ReplaceOld
+        } else {
+            Choice::RejectNew
+        }
     }
 
-    pub fn mem_usage(mut self, mem_usage: usize) -> Self {
-        self.mem_usage = mem_usage;
-        self
+    fn update_scores(&self, txs: &[transaction_pool::Transaction<Transaction>], scores: &mut [u64], _: Change) {
+        txs.iter()
+            .zip(scores.iter_mut())
+            .for_each(|(tx, score)| *score = tx.gas_price());
     }
 
-//    pub fn new(self) -> Transaction {
-//        let hash = self.nonce ^ (U256::from(100) * self.gas_price) ^ (U256::from(100_000) * U256::from(self.sender.low_u64()));
-////        Transaction::new()
-//    }
+    fn should_replace(&self, old: &Transaction, new: &Transaction) -> Choice {
+        if new.gas_price() > old.gas_price() {
+            Choice::ReplaceOld
+        } else {
+            Choice::RejectNew
+        }
+    }
 }
 
-impl VerifiedTransaction for Transaction {
-    type Hash = Hash;
-    type Sender = Address;
-    fn hash(&self) -> &Hash { self.get_hash().as_ref().unwrap() }
+/// A transaction is ready if its nonce follows the account nonce and the ready ones before it
+pub struct NonceReady<'a> {
+    state: &'a dyn AccountNonce,
+    nonces: HashMap<Address, u64>,
+}
 
-    /// TODO
-    fn mem_usage(&self) -> usize { 0 }
+impl<'a> NonceReady<'a> {
+    pub fn new(state: &'a dyn AccountNonce) -> Self {
+        NonceReady {
+            state,
+            nonces: HashMap::new(),
+        }
+    }
+}
 
-    fn sender(&self) -> &Address { self.to().unwrap() }
+impl<'a> Ready<Transaction> for NonceReady<'a> {
+    fn is_ready(&mut self, tx: &Transaction) -> Readiness {
+        let sender = *VerifiedTransaction::sender(tx);
+        let state = self.state;
+        let nonce = self.nonces.entry(sender).or_insert_with(|| state.nonce(&sender));
+        match tx.nonce().cmp(nonce) {
+            cmp::Ordering::Greater => Readiness::Future,
+            cmp::Ordering::Equal => {
+                *nonce += 1;
+                Readiness::Ready
+            }
+            cmp::Ordering::Less => Readiness::Stale,
+        }
+    }
 }
 
-pub type SharedTransaction =  Arc<Transaction>;
+/// The node's transaction pool over `transaction_pool::Pool`
+#[derive(Debug)]
+pub struct TransactionPool {
+    pool: Pool<Transaction, GasPriceScoring>,
+}
+
+impl Default for TransactionPool {
+    fn default() -> Self {
+        TransactionPool::new(Options {
+            max_count: MAX_TXPOOL_SIZE,
+            max_per_sender: MAX_PER_SENDER,
+            max_mem_usage: MAX_MEM_USAGE,
+        })
+    }
+}
+
+impl TransactionPool {
+    pub fn new(options: Options) -> Self {
+        TransactionPool {
+            pool: Pool::with_scoring(GasPriceScoring, options),
+        }
+    }
+
+    fn observe_size(&self) {
+        metrics::TXPOOL_SIZE.set(self.len() as i64);
+    }
+}
+
+impl TxPool for TransactionPool {
+    fn len(&self) -> usize {
+        self.pool.light_status().transaction_count
+    }
+
+    fn get_tx(&self, tx_hash: &Hash) -> Option<SharedTransaction> {
+        self.pool.find(tx_hash)
+    }
+
+    fn pending(&self, state: &dyn AccountNonce, n: usize) -> Vec<SharedTransaction> {
+        self.pool.pending(NonceReady::new(state)).take(n).collect()
+    }
+
+    fn add_tx(&mut self, mut transaction: Transaction) -> Result<u64, TxPoolError> {
+        if transaction.get_sender().is_none() {
+            let sender = transaction.sender().ok_or(TxPoolError::InvalidSignature)?;
+            transaction.set_sender(sender);
+        }
+        let tx_hash = CryptoHash::hash(&transaction);
+        transaction.set_hash(tx_hash);
+        match self.pool.import(transaction) {
+            Ok(_) => {}
+            Err(err) => match err.kind() {
+                ErrorKind::AlreadyImported(_) => {}
+                ErrorKind::TooCheapToEnter(_, _) => return Err(TxPoolError::TooCheapToEnter(tx_hash)),
+                ErrorKind::TooCheapToReplace(_, _) => return Err(TxPoolError::TooCheapToReplace(tx_hash)),
+                _ => return Err(TxPoolError::Rejected(format!("{}", err))),
+            },
+        }
+        self.observe_size();
+        Ok(self.len() as u64)
+    }
+
+    fn add_txs(&mut self, transactions: &Vec<Transaction>) -> Result<u64, TxPoolError> {
+        for transaction in transactions {
+            self.add_tx(transaction.clone())?;
+        }
+        Ok(self.len() as u64)
+    }
+
+    fn remove_txs(&mut self, tx_hashes: Vec<&Hash>) {
+        tx_hashes.into_iter().for_each(|tx_hash| {
+            self.pool.remove(tx_hash, true);
+        });
+        self.observe_size();
+    }
+
+    fn cull(&mut self, state: &dyn AccountNonce) -> usize {
+        let removed = self.pool.cull(None, NonceReady::new(state));
+        self.observe_size();
+        removed
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use transaction_pool::Pool;
-    
+    use cryptocurrency_kit::ethkey::{Generator, KeyPair, Random};
+
+    fn transaction(keypair: &KeyPair, nonce: u64, price: u64) -> Transaction {
+        let mut tx = Transaction::new(nonce, Address::from(100), 10, 21_000, price, vec![]);
+        tx.sign(1, keypair.secret());
+        tx
+    }
 
     #[test]
-    fn t_transaction_pool(){
+    fn t_transaction_pool() {
+        let (alice, bob) = (Random.generate().unwrap(), Random.generate().unwrap());
+        let mut tx_pool = TransactionPool::default();
+        let txs = vec![transaction(&alice, 0, 1), transaction(&alice, 1, 50), transaction(&bob, 0, 3)];
+        assert_eq!(tx_pool.add_txs(&txs).unwrap(), 3);
+        assert_eq!(tx_pool.add_tx(txs[0].clone()).unwrap(), 3);
+
+        // bob's comes first by gas price, alice's follow the nonce order
+        let mut state = HashMap::new();
+        let pending: Vec<(u64, u64)> = tx_pool.pending(&state, 10).iter().map(|tx| (tx.nonce(), tx.gas_price())).collect();
+        assert_eq!(pending, vec![(0, 3), (0, 1), (1, 50)]);
+        assert_eq!(tx_pool.pending(&state, 1).len(), 1);
 
+        // replacement by fee
+        assert!(tx_pool.add_tx(transaction(&alice, 1, 52)).is_err());
+        tx_pool.add_tx(transaction(&alice, 1, 60)).unwrap();
+        assert_eq!(tx_pool.len(), 3);
+        assert!(tx_pool.get_tx(&CryptoHash::hash(&txs[1])).is_none());
+
+        // a future nonce is not ready
+        state.insert(bob.address(), 1);
+        tx_pool.add_tx(transaction(&bob, 2, 100)).unwrap();
+        assert_eq!(tx_pool.pending(&state, 10).len(), 2);
+
+        // the stale ones are culled after bob's transaction is committed
+        assert_eq!(tx_pool.cull(&state), 1);
+        assert_eq!(tx_pool.len(), 3);
+
+        tx_pool.remove_txs(vec![&CryptoHash::hash(&txs[0])]);
+        assert_eq!(tx_pool.len(), 2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn t_sender_limit() {
+        let keypair = Random.generate().unwrap();
+        let mut tx_pool = TransactionPool::new(Options {
+            max_count: 16,
+            max_per_sender: 2,
+            max_mem_usage: MAX_MEM_USAGE,
+        });
+        (0..4).for_each(|nonce| {
+            let _ = tx_pool.add_tx(transaction(&keypair, nonce, 1));
+        });
+        assert_eq!(tx_pool.len(), 2);
+        assert!(tx_pool.add_tx(Transaction::new(0, Address::from(100), 10, 21_000, 1, vec![])).is_err());
+    }
+}
@@ -1,127 +1,59 @@
-use std::collections::BTreeMap;
+use std::sync::Arc;
 
-use priority_queue::PriorityQueue;
-use cryptocurrency_kit::crypto::Hash;
+use parking_lot::RwLock;
+use cryptocurrency_kit::crypto::{CryptoHash, Hash};
+use cryptocurrency_kit::ethkey::Address;
 
 use crate::{
+    core::chain::Chain,
+    core::state::intrinsic_gas,
+    core::transaction_pool::AccountNonce,
     types::transaction::Transaction,
     error::TxPoolError,
 };
 
-pub const MAX_TXPOOL_SIZE: u64 = 10_000_000;
-pub const MAX_SLOT_SIZE: u32 = 1_000;
-
 pub trait TxPool {
     fn len(&self) -> usize;
-    fn get_tx(&self, tx_hash: &Hash) -> Option<&Transaction>;
-    fn get_n_tx(&self, n: u64) -> Vec<&Transaction>;
+    fn get_tx(&self, tx_hash: &Hash) -> Option<Arc<Transaction>>;
+    /// The ready transactions by the account nonces, at most `n`, the higher gas price comes first
+    fn pending(&self, state: &dyn AccountNonce, n: usize) -> Vec<Arc<Transaction>>;
     fn add_tx(&mut self, transaction: Transaction) -> Result<u64, TxPoolError>;
     fn add_txs(&mut self, transactions: &Vec<Transaction>) -> Result<u64, TxPoolError>;
     fn remove_txs(&mut self, tx_hashes: Vec<&Hash>);
+    /// Removes the transactions whose nonces are used, returns the number of the removed
+    fn cull(&mut self, state: &dyn AccountNonce) -> usize;
 }
 
 pub type SafeTxPool = Box<dyn TxPool + Send + Sync>;
 
-pub struct BaseTxPool {
-    pq: PriorityQueue<Hash, u64>,
-    txs: Vec<BTreeMap<Hash, Transaction>>,
-}
-
-impl TxPool for BaseTxPool {
-    fn len(&self) -> usize {
-        self.txs.len()
+/// Checks the transaction submitted by a client or relayed by a peer before it enters the pool,
+/// returns the sender
+pub fn verify_transaction(chain: &Chain, transaction: &Transaction) -> Result<Address, TxPoolError> {
+    if !transaction.verify_sign(chain.config.chain_id) {
+        return Err(TxPoolError::InvalidSignature);
     }
-
-    fn get_tx(&self, tx_hash: &Hash) -> Option<&Transaction> {
-        self.txs[self.get_idx(tx_hash)].get(tx_hash)
+    let gas = intrinsic_gas(transaction);
+    if transaction.gas() < gas {
+        return Err(TxPoolError::IntrinsicGas(gas, transaction.gas()));
     }
-
-    fn get_n_tx(&self, n: u64) -> Vec<&Transaction> {
-        let mut txs = vec![];
-        let i: u64 = 0;
-        for (tx_hash, _) in self.pq.iter() {
-            if i >= n {
-                break;
-            }
-            let idx = self.get_idx(tx_hash);
-
-            let m = self.txs.get(idx).unwrap();
-            txs.push(m.get(tx_hash).unwrap());
-        }
-        txs
-    }
-
-    fn add_tx(&mut self, tx: Transaction) -> Result<u64, TxPoolError> {
-        let idx = self.get_idx(tx.get_hash().unwrap());
-        let v: &mut BTreeMap<_, _> = self.txs.get_mut(idx).unwrap();
-        if v.get(tx.get_hash().unwrap()).is_some() {
-            return Ok(self.pq.len() as u64);
-        }
-        v.insert(*tx.get_hash().unwrap(), tx.clone());
-        self.pq.push(*tx.get_hash().unwrap(), tx.amount());
-        Ok(self.pq.len() as u64)
-    }
-
-    fn add_txs(&mut self, txs: &Vec<Transaction>) -> Result<u64, TxPoolError> {
-        let mut start: u64 = 0;
-        for tx in txs {
-            self.add_tx(tx.clone())?;
-            start += 1;
-        }
-        Ok(start)
-    }
-
-    fn remove_txs(&mut self, tx_hashes: Vec<&Hash>) {
-        tx_hashes.iter().for_each(|tx_hash| {
-            let idx = self.get_idx(tx_hash);
-            let m: &mut BTreeMap<_, _> = self.txs.get_mut(idx).unwrap();
-            m.remove(tx_hash);
-        });
+    let sender = transaction.sender().ok_or(TxPoolError::InvalidSignature)?;
+    let nonce = chain.get_account(&sender).nonce;
+    if transaction.nonce() < nonce {
+        return Err(TxPoolError::StaleNonce(nonce, transaction.nonce()));
     }
+    Ok(sender)
 }
 
-impl Default for BaseTxPool {
-    fn default() -> Self {
-        Self::new()
-    }
+/// Verifies the transaction and adds it into the pool, returns false if it is in the pool already
+pub fn submit_transaction(chain: &Chain, tx_pool: &RwLock<SafeTxPool>, mut transaction: Transaction) -> Result<bool, TxPoolError> {
+    let sender = verify_transaction(chain, &transaction)?;
+    let tx_hash = transaction.hash();
+    transaction.set_hash(tx_hash);
+    transaction.set_sender(sender);
+    let mut tx_pool = tx_pool.write();
+    if tx_pool.get_tx(&tx_hash).is_some() {
+        return Ok(false);
+    }
+    tx_pool.add_tx(transaction)?;
+    Ok(true)
 }
-
-impl BaseTxPool {
-    pub fn new() -> Self {
-        let n = (MAX_TXPOOL_SIZE / u64::from(MAX_SLOT_SIZE)) as usize;
-        let mut tx_pool = BaseTxPool {
-            pq: PriorityQueue::new(),
-            txs: Vec::with_capacity(n),
-        };
-        (0..n).for_each(|_| {
-            tx_pool.txs.push(BTreeMap::new());
-        });
-        tx_pool
-    }
-    fn get_idx(&self, tx_hash: &Hash) -> usize {
-        use ethereum_types::U256;
-        let u = U256::from(tx_hash.as_ref());
-        (u % U256::from(self.txs.len())).as_u64() as usize
-    }
-}
-
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::sync::RwLock;
-
-    struct TxPoolActor {
-        tx_pool: Arc<RwLock<Box<TxPool>>>,
-    }
-
-    impl Actor for TxPoolActor {
-        type Context = Context<Self>;
-    }
-
-    #[test]
-    fn t_txpool() {
-//        let mut v = vec![];
-        (0..10_0000).for_each(|_idx| {})
-    }
-}
\ No newline at end of file
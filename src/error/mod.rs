@@ -1,10 +1,25 @@
 
 use cryptocurrency_kit::crypto::Hash;
+use cryptocurrency_kit::ethkey::Address;
+
+use crate::types::{Gas, Height};
 
 #[derive(Debug, Fail)]
 pub enum TxPoolError {
     #[fail(display = "More than max txpool limit, max:{}", _0)]
     MoreThanMaxSIZE(u64),
+    #[fail(display = "Invalid transaction signature or chain id")]
+    InvalidSignature,
+    #[fail(display = "Intrinsic gas too low, need: {}, got: {}", _0, _1)]
+    IntrinsicGas(u64, u64),
+    #[fail(display = "Stale nonce, expect: {}, got: {}", _0, _1)]
+    StaleNonce(u64, u64),
+    #[fail(display = "Too cheap to enter the full pool, ({:?})", _0)]
+    TooCheapToEnter(Hash),
+    #[fail(display = "Too cheap to replace the transaction of same nonce, ({:?})", _0)]
+    TooCheapToReplace(Hash),
+    #[fail(display = "Rejected by the pool, ({})", _0)]
+    Rejected(String),
 }
 
 #[derive(Debug, Fail)]
@@ -19,6 +34,54 @@ pub enum P2PError {
     InvalidMessage,
     #[fail(display = "Timeout")]
     Timeout,
+    #[fail(display = "Message too large, max: {}, got: {}", _0, _1)]
+    TooLarge(usize, usize),
+    #[fail(display = "Rate limited, ({})", _0)]
+    RateLimited(String),
+    #[fail(display = "The remote proved another node key, ({})", _0)]
+    Impersonation(String),
+    #[fail(display = "Not in the allowlist, ({})", _0)]
+    NotAllowed(String),
+    #[fail(display = "Protocol violation, ({})", _0)]
+    Violation(String),
+    #[fail(display = "The peer is banned, ({})", _0)]
+    Banned(String),
+    #[fail(display = "Too many {} peers, max: {}", _0, _1)]
+    TooManyPeers(String, u64),
+    #[fail(display = "Failed to handle the message, ({})", _0)]
+    Handle(String),
+}
+
+#[derive(Debug, Fail)]
+pub enum SyncError {
+    #[fail(display = "Discontinuous header, expect height: {}, got: {}", _0, _1)]
+    Discontinuous(u64, u64),
+    #[fail(display = "Header does not link to its parent, height: {}", _0)]
+    UnknownParent(u64),
+    #[fail(display = "Invalid seal, height: {}, ({})", _0, _1)]
+    InvalidSeal(u64, String),
+    #[fail(display = "Invalid block body, hash: {:?}", _0)]
+    InvalidBody(Hash),
+}
+
+#[derive(Debug, Fail)]
+pub enum StateError {
+    #[fail(display = "Invalid transaction signature, ({:?})", _0)]
+    InvalidSignature(Hash),
+    #[fail(display = "Transaction without recipient, ({:?})", _0)]
+    MissingRecipient(Hash),
+    #[fail(display = "Invalid nonce, account: {:?}, expect: {}, got: {}", _0, _1, _2)]
+    InvalidNonce(Address, u64, u64),
+    #[fail(display = "Insufficient balance, account: {:?}, need: {}, have: {}", _0, _1, _2)]
+    InsufficientBalance(Address, u64, u64),
+    #[fail(display = "Intrinsic gas too low, transaction: {:?}, need: {}, got: {}", _0, _1, _2)]
+    IntrinsicGas(Hash, Gas, Gas),
+    #[fail(display = "Block gas limit reached, limit: {}, need: {}", _0, _1)]
+    GasLimitReached(Gas, Gas),
+    #[fail(display = "Invalid coinbase transaction, ({:?})", _0)]
+    InvalidCoinbase(Hash),
+    #[fail(display = "Balance overflow, transaction: {:?}", _0)]
+    Overflow(Hash),
 }
 
 pub type ChainResult = Result<(), ChainError>;
@@ -27,6 +90,30 @@ pub type ChainResult = Result<(), ChainError>;
 pub enum ChainError {
     #[fail(display = "the block has exist, ({:?})", _0)]
     Exists(Hash),
+    #[fail(display = "Not found ancestor, height: {}", _0)]
+    UnknownAncestor(Height),
+    #[fail(display = "Invalid prev hash, expect: {:?}, got: {:?}", _0, _1)]
+    InvalidPrevHash(Hash, Hash),
+    #[fail(display = "Invalid proposer, ({:?})", _0)]
+    InvalidProposer(Address),
+    #[fail(display = "Lack votes, expect: {}, got: {}", _0, _1)]
+    LackVotes(usize, usize),
+    #[fail(display = "Invalid seal, ({})", _0)]
+    InvalidSeal(String),
+    #[fail(display = "Invalid transaction root, expect: {:?}, got: {:?}", _0, _1)]
+    InvalidTransactionRoot(Hash, Hash),
+    #[fail(display = "Invalid transaction signature, ({:?})", _0)]
+    InvalidTransaction(Hash),
+    #[fail(display = "Invalid header, ({})", _0)]
+    InvalidHeader(String),
+    #[fail(display = "Invalid evidence, ({})", _0)]
+    InvalidEvidence(String),
+    #[fail(display = "Invalid state root, expect: {:?}, got: {:?}", _0, _1)]
+    InvalidStateRoot(Hash, Hash),
+    #[fail(display = "Invalid gas used, expect: {}, got: {}", _0, _1)]
+    InvalidGasUsed(Gas, Gas),
+    #[fail(display = "Failed to execute the block, ({})", _0)]
+    State(StateError),
     #[fail(display = "An unknown error has occurred, ({})", _0)]
     Unknown(String),
 }
\ No newline at end of file
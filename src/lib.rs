@@ -33,8 +33,10 @@ pub mod pprof;
 #[macro_use]
 pub mod subscriber;
 pub mod minner;
+pub mod sync;
 pub mod cmd;
 pub mod config;
 pub mod logger;
 pub mod mocks;
-pub mod api;
\ No newline at end of file
+pub mod api;
+pub mod metrics;
\ No newline at end of file
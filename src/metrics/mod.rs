@@ -0,0 +1,192 @@
+//! Prometheus metrics of the node, they are exported in the text format by `GET /metrics`.
+
+use std::time::Duration;
+
+use kvdb_rocksdb::Database;
+use prometheus::{
+    exponential_buckets, CounterVec, Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, TextEncoder,
+};
+
+use crate::{
+    consensus::types::View,
+    p2p::protocol::{BoundType, P2PMsgCode},
+    protocol::{MessageType, State},
+};
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+lazy_static! {
+    pub static ref CONSENSUS_HEIGHT: IntGauge = register(IntGauge::new("consensus_height", "Height of the current consensus view").unwrap());
+    pub static ref CONSENSUS_ROUND: IntGauge = register(IntGauge::new("consensus_round", "Round of the current consensus view").unwrap());
+    pub static ref CONSENSUS_ROUND_CHANGES: IntCounter =
+        register(IntCounter::new("consensus_round_changes_total", "Rounds started after a round change").unwrap());
+    pub static ref CONSENSUS_STATE_SECONDS: CounterVec = register(
+        CounterVec::new(Opts::new("consensus_state_seconds_total", "Time spent in each consensus state"), &["state"]).unwrap()
+    );
+    pub static ref CONSENSUS_MESSAGES: IntCounterVec = register(
+        IntCounterVec::new(Opts::new("consensus_messages_total", "Consensus messages received from the validators"), &["type"]).unwrap()
+    );
+    pub static ref CONSENSUS_BACKLOG: IntGauge =
+        register(IntGauge::new("consensus_backlog_messages", "Future messages kept in the backlog").unwrap());
+    pub static ref CONSENSUS_COMMIT_SECONDS: Histogram = register(
+        Histogram::with_opts(
+            HistogramOpts::new("consensus_block_commit_seconds", "Time from the start of a height to the commit of its block")
+                .buckets(exponential_buckets(0.05, 2.0, 12).unwrap())
+        )
+        .unwrap()
+    );
+
+    pub static ref CHAIN_HEIGHT: IntGauge = register(IntGauge::new("chain_height", "Height of the last block").unwrap());
+    pub static ref CHAIN_BLOCK_INSERT_SECONDS: Histogram = register(
+        Histogram::with_opts(
+            HistogramOpts::new("chain_block_insert_seconds", "Time to execute and store a block")
+                .buckets(exponential_buckets(0.001, 2.0, 14).unwrap())
+        )
+        .unwrap()
+    );
+
+    pub static ref P2P_PEERS: IntGaugeVec =
+        register(IntGaugeVec::new(Opts::new("p2p_peers", "Connected peers"), &["bound"]).unwrap());
+    pub static ref P2P_BYTES: IntCounterVec = register(
+        IntCounterVec::new(Opts::new("p2p_bytes_total", "Bytes of the p2p messages"), &["direction", "code"]).unwrap()
+    );
+    pub static ref P2P_VIOLATIONS: IntCounter =
+        register(IntCounter::new("p2p_protocol_violations_total", "Malformed or refused messages of the peers").unwrap());
+
+    pub static ref TXPOOL_SIZE: IntGauge = register(IntGauge::new("txpool_transactions", "Transactions in the pool").unwrap());
+
+    pub static ref ROCKSDB_SST_FILES: IntGauge = register(IntGauge::new("rocksdb_sst_files", "Table files of the database").unwrap());
+    pub static ref ROCKSDB_SST_BYTES: IntGauge =
+        register(IntGauge::new("rocksdb_sst_bytes", "Total size of the table files").unwrap());
+    pub static ref ROCKSDB_WAL_BYTES: IntGauge =
+        register(IntGauge::new("rocksdb_wal_bytes", "Total size of the write-ahead logs").unwrap());
+    pub static ref ROCKSDB_BUFFERED_KEYS: IntGauge =
+        register(IntGauge::new("rocksdb_buffered_keys", "Keys written and not flushed yet").unwrap());
+}
+
+fn register<M: prometheus::core::Collector + Clone + 'static>(metric: M) -> M {
+    prometheus::register(Box::new(metric.clone())).expect("metric registered twice");
+    metric
+}
+
+pub fn state_label(state: &State) -> &'static str {
+    match state {
+        State::AcceptRequest => "accept_request",
+        State::PrePrepared => "preprepared",
+        State::Prepared => "prepared",
+        State::Committed => "committed",
+    }
+}
+
+pub fn message_label(code: &MessageType) -> &'static str {
+    match code {
+        MessageType::Preprepare => "preprepare",
+        MessageType::Prepare => "prepare",
+        MessageType::Commit => "commit",
+        MessageType::RoundChange => "round_change",
+    }
+}
+
+pub fn msg_code_label(code: &P2PMsgCode) -> &'static str {
+    match code {
+        P2PMsgCode::Ping => "ping",
+        P2PMsgCode::Handshake => "handshake",
+        P2PMsgCode::Transaction => "transaction",
+        P2PMsgCode::Block => "block",
+        P2PMsgCode::Consensus => "consensus",
+        P2PMsgCode::Sync => "sync",
+        P2PMsgCode::Evidence => "evidence",
+        P2PMsgCode::PeerExchange => "peer_exchange",
+    }
+}
+
+pub fn bound_label(bound_type: &BoundType) -> &'static str {
+    match bound_type {
+        BoundType::InBound => "inbound",
+        BoundType::OutBound => "outbound",
+    }
+}
+
+pub fn observe_view(view: &View) {
+    CONSENSUS_HEIGHT.set(view.height as i64);
+    CONSENSUS_ROUND.set(view.round as i64);
+}
+
+/// Adds the time spent in the state that is left
+pub fn observe_state(state: &State, elapsed: Duration) {
+    CONSENSUS_STATE_SECONDS
+        .with_label_values(&[state_label(state)])
+        .inc_by(elapsed.as_secs_f64());
+}
+
+pub fn observe_p2p_bytes(direction: &str, code: &P2PMsgCode, bytes: usize) {
+    P2P_BYTES
+        .with_label_values(&[direction, msg_code_label(code)])
+        .inc_by(bytes as u64);
+}
+
+/// The database has no statistics of its own, its files and the unflushed writes are read on scrape
+pub fn observe_database(db: &Database) {
+    match db.disk_usage() {
+        Ok(usage) => {
+            ROCKSDB_SST_FILES.set(usage.sst_files as i64);
+            ROCKSDB_SST_BYTES.set(usage.sst_bytes as i64);
+            ROCKSDB_WAL_BYTES.set(usage.wal_bytes as i64);
+        }
+        Err(err) => warn!("Failed to read the database usage, err: {}", err),
+    }
+    ROCKSDB_BUFFERED_KEYS.set(db.buffered_keys() as i64);
+}
+
+/// Registers the metrics not touched yet, so they are exported before the first update
+fn init() {
+    lazy_static::initialize(&CONSENSUS_HEIGHT);
+    lazy_static::initialize(&CONSENSUS_ROUND);
+    lazy_static::initialize(&CONSENSUS_ROUND_CHANGES);
+    lazy_static::initialize(&CONSENSUS_STATE_SECONDS);
+    lazy_static::initialize(&CONSENSUS_MESSAGES);
+    lazy_static::initialize(&CONSENSUS_BACKLOG);
+    lazy_static::initialize(&CONSENSUS_COMMIT_SECONDS);
+    lazy_static::initialize(&CHAIN_HEIGHT);
+    lazy_static::initialize(&CHAIN_BLOCK_INSERT_SECONDS);
+    lazy_static::initialize(&P2P_PEERS);
+    lazy_static::initialize(&P2P_BYTES);
+    lazy_static::initialize(&P2P_VIOLATIONS);
+    lazy_static::initialize(&TXPOOL_SIZE);
+    lazy_static::initialize(&ROCKSDB_SST_FILES);
+    lazy_static::initialize(&ROCKSDB_SST_BYTES);
+    lazy_static::initialize(&ROCKSDB_WAL_BYTES);
+    lazy_static::initialize(&ROCKSDB_BUFFERED_KEYS);
+}
+
+/// Encodes all metrics in the text format
+pub fn gather() -> String {
+    init();
+    let mut buffer = vec![];
+    if let Err(err) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        error!("Failed to encode metrics, err: {}", err);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_gather() {
+        observe_view(&View::new(10, 2));
+        observe_state(&State::Prepared, Duration::from_millis(1500));
+        observe_p2p_bytes("in", &P2PMsgCode::Consensus, 128);
+        P2P_PEERS.with_label_values(&[bound_label(&BoundType::InBound)]).set(3);
+
+        // the registry is shared by the tests, only the series are checked
+        let text = gather();
+        assert!(text.contains("consensus_height "));
+        assert!(text.contains("consensus_state_seconds_total{state=\"prepared\"}"));
+        assert!(text.contains("p2p_bytes_total{code=\"consensus\",direction=\"in\"}"));
+        assert!(text.contains("p2p_peers{bound=\"inbound\"}"));
+        assert!(text.contains("txpool_transactions "));
+    }
+}
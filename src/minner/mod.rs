@@ -2,28 +2,35 @@ use std::sync::Arc;
 
 use crossbeam::channel;
 use parking_lot::RwLock;
-use rand::random;
 use cryptocurrency_kit::ethkey::{Address, KeyPair};
-use cryptocurrency_kit::crypto::Hash;
+use cryptocurrency_kit::crypto::{Hash, EMPTY_HASH};
 
 use crate::{
     subscriber::events::ChainEvent,
     core::chain::Chain,
+    core::state::{coinbase_payload, BLOCK_GAS_LIMIT, BLOCK_REWARD},
     core::tx_pool::SafeTxPool,
     consensus::consensus::SafeEngine,
     types::block::{Block, Header},
-    types::transaction::{Transaction, merkle_root_transactions},
+    types::{Height, Timestamp},
+    types::evidence::evidence_root,
+    types::transaction::Transaction,
 };
 
+/// Max pending transactions tried for a new block
+pub const MAX_PACKED_CANDIDATES: usize = 4096;
+
+/// Max pending evidences included in a new block
+pub const MAX_BLOCK_EVIDENCES: usize = 16;
+
 /// Start the minner in a dedicated thread - subscribes to ChainEventBus and mines blocks
 pub fn start_minner(
     _config: &crate::config::Config,
     key_pair: KeyPair,
     chain: Arc<Chain>,
-    _txpool: Arc<RwLock<SafeTxPool>>,
+    txpool: Arc<RwLock<SafeTxPool>>,
     mut engine: SafeEngine,
 ) {
-    let minter = key_pair.address();
     let chain_bus = chain.chain_event_bus();
 
     std::thread::spawn(move || {
@@ -31,7 +38,12 @@ pub fn start_minner(
         chain.post_event(ChainEvent::SyncBlock(chain.get_last_height() + 1));
 
         loop {
-            let mut block = packet_next_block(minter, &key_pair, &chain);
+            // the transactions committed by the last block leave the pool
+            let culled = txpool.write().cull(&*chain);
+            if culled > 0 {
+                debug!("Cull the committed transactions from pool, count: {}", culled);
+            }
+            let mut block = build_block(&chain, &key_pair, &txpool, next_time(&chain));
             let mint_height = block.height();
 
             let (abort_tx, abort_rx) = channel::bounded(1);
@@ -59,43 +71,63 @@ pub fn start_minner(
     });
 }
 
-fn packet_next_block(minter: Address, key_pair: &KeyPair, chain: &Chain) -> Block {
-    let (next_time, pre_header) = next_block(chain);
-    let coinbase = coinbase_transaction(minter, key_pair, chain);
+/// Builds the block of the key on the last block with the pending transactions of the pool,
+/// the ones that can never be executed leave the pool
+pub fn build_block(chain: &Chain, key_pair: &KeyPair, txpool: &RwLock<SafeTxPool>, time: Timestamp) -> Block {
+    let minter = key_pair.address();
+    let pre_header = chain.get_last_block().header().clone();
+    let coinbase = coinbase_transaction(minter, key_pair, chain, pre_header.height + 1);
+    let pending: Vec<Transaction> = txpool
+        .read()
+        .pending(chain, MAX_PACKED_CANDIDATES)
+        .into_iter()
+        .map(|transaction| (*transaction).clone())
+        .collect();
 
     let pre_hash: Hash = pre_header.block_hash();
-    let tx_hash = merkle_root_transactions(vec![coinbase.clone()]);
     let extra = Vec::from("Coinse base");
 
     let mut header = Header::new_mock(
         pre_hash,
         minter,
-        tx_hash,
+        EMPTY_HASH,
         pre_header.height + 1,
-        next_time,
+        time,
         Some(extra),
     );
+    header.vote = chain.next_vote(header.height);
+    header.gas_limit = BLOCK_GAS_LIMIT;
+    let transactions = match chain.pack_transactions(&header, coinbase.clone(), pending) {
+        Ok(packed) => {
+            txpool.write().remove_txs(packed.dropped.iter().collect());
+            header.root = packed.executed.root;
+            header.gas_used = packed.executed.gas_used;
+            packed.transactions
+        }
+        Err(err) => {
+            error!("Failed to execute the transactions of the next block, err: {}", err);
+            vec![coinbase]
+        }
+    };
+    header.tx_hash = chain.transactions_root(header.height, transactions.clone());
+    let evidences = chain.pending_evidences(MAX_BLOCK_EVIDENCES);
+    header.evidence_hash = evidence_root(&evidences);
     header.cache_hash(None);
-    Block::new(header, vec![coinbase])
+    let mut block = Block::new(header, transactions);
+    block.set_evidences(evidences);
+    block
 }
 
-fn coinbase_transaction(minter: Address, key_pair: &KeyPair, chain: &Chain) -> Transaction {
-    let nonce: u64 = random();
-    let to = minter;
-    let amount = random::<u64>();
-    let gas_limit = random::<u64>();
-    let gas_price = 1_u64;
-    let payload = Vec::from(chrono::Local::now().to_string());
-
-    let mut transaction = Transaction::new(nonce, to, amount, gas_limit, gas_price, payload);
+/// Mints the block reward to the minter
+fn coinbase_transaction(minter: Address, key_pair: &KeyPair, chain: &Chain, height: Height) -> Transaction {
+    let mut transaction = Transaction::new(0, minter, BLOCK_REWARD, 0, 0, coinbase_payload(height));
     transaction.sign(chain.config.chain_id, key_pair.secret());
     transaction
 }
 
-fn next_block(chain: &Chain) -> (u64, Header) {
-    let pre_block = chain.get_last_block();
-    let pre_header = pre_block.header();
-    let pre_timestamp = pre_header.time;
+// the block period after the last block, or now if it has passed
+fn next_time(chain: &Chain) -> Timestamp {
+    let pre_timestamp = chain.get_last_block().header().time;
     let next_timestamp = pre_timestamp + chain.config.block_period.as_secs();
     let now_timestamp = chrono::Local::now().timestamp() as u64;
     trace!(
@@ -104,8 +136,5 @@ fn next_block(chain: &Chain) -> (u64, Header) {
         pre_timestamp,
         next_timestamp
     );
-    if now_timestamp > next_timestamp {
-        return (now_timestamp, pre_header.clone());
-    }
-    (next_timestamp, pre_header.clone())
+    now_timestamp.max(next_timestamp)
 }
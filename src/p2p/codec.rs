@@ -1,4 +1,3 @@
-use std::borrow::Cow;
 use std::io;
 
 use byteorder::{BigEndian, ByteOrder};
@@ -7,11 +6,40 @@ use cryptocurrency_kit::storage::values::StorageValue;
 use tokio_util::codec::{Decoder, Encoder};
 
 use super::protocol::*;
+use super::tx_gossip::MAX_TX_BATCH_BYTES;
+use crate::error::P2PError;
+use crate::metrics;
 
 pub const MAX_MSG_SIZE: u32 = 1 << 10;
 pub const MSG_SIZE: u32 = 4; // byte
+/// The message code follows the size, so the frame is bounded by its code before it is buffered
+pub const CODE_SIZE: u32 = 1; // byte
+/// The largest payload of the blocks, the sync and the evidence messages
+pub const MAX_PAYLOAD_SIZE: usize = 16 << 20;
+/// The buffer grows by at most this for a partial frame, a large frame is buffered as it arrives
+pub const MAX_RESERVE_SIZE: usize = 64 << 10;
 
-// |msg_size: 4bytes| msg encode |
+/// The largest payload of the message code
+pub fn max_payload_size(code: &P2PMsgCode) -> usize {
+    match code {
+        P2PMsgCode::Ping | P2PMsgCode::Handshake => MAX_MSG_SIZE as usize,
+        P2PMsgCode::PeerExchange => 64 * MAX_MSG_SIZE as usize,
+        P2PMsgCode::Transaction => MAX_TX_BATCH_BYTES,
+        P2PMsgCode::Consensus => MAX_PAYLOAD_SIZE / 2,
+        P2PMsgCode::Block | P2PMsgCode::Sync | P2PMsgCode::Evidence => MAX_PAYLOAD_SIZE,
+    }
+}
+
+/// A payload byte takes up to 4 bytes in the json of a frame, e.g. "255,"
+pub fn max_frame_size(code: &P2PMsgCode) -> usize {
+    4 * max_payload_size(code) + MAX_MSG_SIZE as usize
+}
+
+fn invalid_data(err: P2PError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+// |msg_size: 4bytes| code: 1byte | msg encode |
 pub struct MsgPacketCodec;
 
 impl Decoder for MsgPacketCodec {
@@ -19,22 +47,38 @@ impl Decoder for MsgPacketCodec {
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let size = {
-            if src.len() < MSG_SIZE as usize {
-                // continue read
-                return Ok(None);
-            }
-            BigEndian::read_u32(src.as_ref())
-        };
-
-        if src.len() >= (size + MSG_SIZE) as usize {
-            src.split_to(MSG_SIZE as usize);
-            let buf = src.split_to(size as usize);
-            let raw_message: RawMessage = RawMessage::from_bytes(Cow::from(buf.to_vec()));
-            Ok(Some(raw_message))
-        } else {
-            Ok(None)
+        let prefix_size = (MSG_SIZE + CODE_SIZE) as usize;
+        if src.len() < prefix_size {
+            // continue read
+            return Ok(None);
+        }
+        let size = BigEndian::read_u32(src.as_ref()) as usize;
+        let code = P2PMsgCode::from_u8(src[MSG_SIZE as usize])
+            .ok_or_else(|| invalid_data(P2PError::Violation(format!("unknown message code {}", src[MSG_SIZE as usize]))))?;
+        // the frame is refused before it is buffered
+        let max_frame = max_frame_size(&code);
+        if size > max_frame {
+            return Err(invalid_data(P2PError::TooLarge(max_frame, size)));
+        }
+
+        let frame_size = size + prefix_size;
+        if src.len() < frame_size {
+            src.reserve((frame_size - src.len()).min(MAX_RESERVE_SIZE));
+            return Ok(None);
+        }
+        src.split_to(prefix_size);
+        let buf = src.split_to(size);
+        let raw_message: RawMessage =
+            serde_json::from_slice(&buf).map_err(|err| invalid_data(P2PError::Violation(err.to_string())))?;
+        if raw_message.header().code != code {
+            return Err(invalid_data(P2PError::Violation("the message code differs from the prefix".to_string())));
         }
+        let max_size = max_payload_size(&code);
+        if raw_message.payload().len() > max_size {
+            return Err(invalid_data(P2PError::TooLarge(max_size, raw_message.payload().len())));
+        }
+        metrics::observe_p2p_bytes("in", &code, frame_size);
+        Ok(Some(raw_message))
     }
 }
 
@@ -42,14 +86,69 @@ impl Encoder<RawMessage> for MsgPacketCodec {
     type Error = io::Error;
 
     fn encode(&mut self, msg: RawMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let code = msg.header().code.clone();
         let msg = msg.into_bytes();
         let size = msg.len() as u32;
-        dst.reserve((size + MSG_SIZE) as usize);
+        let frame_size = (size + MSG_SIZE + CODE_SIZE) as usize;
+        metrics::observe_p2p_bytes("out", &code, frame_size);
+        dst.reserve(frame_size);
         let mut buf = [0u8; 4];
         BigEndian::write_u32(&mut buf, size);
         dst.extend_from_slice(&buf);
+        dst.extend_from_slice(&[code.to_u8()]);
         dst.extend_from_slice(&msg);
         Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(code: P2PMsgCode, payload: Vec<u8>) -> BytesMut {
+        let mut dst = BytesMut::new();
+        MsgPacketCodec.encode(RawMessage::new(Header::new(code, 10, 0, None), payload), &mut dst).unwrap();
+        dst
+    }
+
+    #[test]
+    fn t_decode() {
+        let mut src = frame(P2PMsgCode::Consensus, vec![1, 2, 3]);
+        let tail = src.split_off(10);
+        // a partial frame waits for the rest
+        assert!(MsgPacketCodec.decode(&mut src).unwrap().is_none());
+        src.unsplit(tail);
+        let msg = MsgPacketCodec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(msg.payload(), &vec![1, 2, 3]);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn t_decode_invalid() {
+        // the length is checked by the code before the frame arrives
+        let mut src = BytesMut::from(&[0xffu8, 0xff, 0xff, 0xff][..]);
+        assert!(MsgPacketCodec.decode(&mut src).unwrap().is_none());
+        src.extend_from_slice(&[P2PMsgCode::Block.to_u8()]);
+        assert_eq!(MsgPacketCodec.decode(&mut src).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let mut src = BytesMut::from(&[0u8, 1, 0, 0, P2PMsgCode::Ping.to_u8()][..]);
+        assert_eq!(MsgPacketCodec.decode(&mut src).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        // a large frame is not reserved at once
+        let mut src = BytesMut::from(&[0u8, 1, 0, 0, P2PMsgCode::Block.to_u8()][..]);
+        assert!(MsgPacketCodec.decode(&mut src).unwrap().is_none());
+        assert!(src.capacity() < 2 * MAX_RESERVE_SIZE);
+
+        let mut src = BytesMut::from(&[0u8, 0, 0, 3, 0xff, b'b', b'a', b'd'][..]);
+        assert_eq!(MsgPacketCodec.decode(&mut src).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let mut src = BytesMut::from(&[0u8, 0, 0, 3, P2PMsgCode::Ping.to_u8(), b'b', b'a', b'd'][..]);
+        assert_eq!(MsgPacketCodec.decode(&mut src).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // the prefix must tell the code of the message
+        let mut src = frame(P2PMsgCode::Consensus, vec![1, 2, 3]);
+        src[MSG_SIZE as usize] = P2PMsgCode::Ping.to_u8();
+        assert_eq!(MsgPacketCodec.decode(&mut src).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // a ping has no large payload
+        let mut src = frame(P2PMsgCode::Ping, vec![0; MAX_MSG_SIZE as usize + 1]);
+        assert_eq!(MsgPacketCodec.decode(&mut src).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
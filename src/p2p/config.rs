@@ -1,18 +1,25 @@
-#[derive(Default)]
+use libp2p::Multiaddr;
+
+/// The connection quotas of the peer manager, the reserved peers are not counted against them
+#[derive(Debug, Clone, Default)]
 pub struct Config {
     pub max_inbound: u64,
     pub max_outbound: u64,
     pub max_connection_size: u64,
     pub seal: bool,
+    // the addresses the peer exchange announces for the node
+    pub external_addresses: Vec<Multiaddr>,
 
 }
 
 impl Config {
-//    pub fn new(max_inbound: u64, max_outbound: u64, max_connection_size: u64) -> Self {
-//        Config {
-//            max_inbound,
-//            max_outbound,
-//            max_connection_size,
-//        }
-//    }
-}
\ No newline at end of file
+    pub fn new(max_inbound: u64, max_outbound: u64, max_connection_size: u64) -> Self {
+        Config {
+            max_inbound,
+            max_outbound,
+            max_connection_size,
+            seal: false,
+            external_addresses: Vec::new(),
+        }
+    }
+}
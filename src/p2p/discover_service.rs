@@ -2,7 +2,8 @@ use std::time::Duration;
 
 use futures::StreamExt;
 use libp2p::swarm::SwarmEvent;
-use libp2p::{Multiaddr, PeerId, SwarmBuilder};
+use libp2p::identity::Keypair;
+use libp2p::{Multiaddr, SwarmBuilder};
 
 use crate::subscriber::{P2PEvent, P2PEventBus};
 
@@ -18,20 +19,22 @@ impl DiscoverService {
     /// Run mDNS discovery in a background thread, sending P2PEvent::AddPeer to the bus
     pub fn run_discover_service(
         p2p_bus: P2PEventBus,
-        local_peer_id: PeerId,
+        node_key: Keypair,
         local_address: Multiaddr,
         ttl: Duration,
     ) {
         std::thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new().expect("runtime");
             rt.block_on(async move {
+                // the swarm has the node key, so the discovered ids are the ones the sessions authenticate
+                let local_peer_id = node_key.public().to_peer_id();
                 let mdns_config = libp2p::mdns::Config {
                     ttl,
                     query_interval: Duration::from_secs(60),
                     enable_ipv6: false,
                 };
 
-                let mut swarm = match SwarmBuilder::with_new_identity()
+                let mut swarm = match SwarmBuilder::with_existing_identity(node_key)
                     .with_tokio()
                     .with_tcp(
                         libp2p::tcp::Config::default(),
@@ -1,8 +1,12 @@
 pub mod config;
 pub mod discover_service;
 pub mod node;
+pub mod peer_exchange;
+pub mod peer_manager;
 pub mod server;
 pub mod session;
 pub mod codec;
 pub mod protocol;
+pub mod tx_gossip;
+pub mod transport;
 pub use crate::subscriber::*;
\ No newline at end of file
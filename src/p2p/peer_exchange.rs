@@ -0,0 +1,95 @@
+//! The peer exchange, the peers share the addresses they know, so the nodes find each other from a
+//! single seed where the mdns does not reach, e.g. across subnets or container networks.
+
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::time::Duration;
+
+use cryptocurrency_kit::crypto::{hash, CryptoHash, Hash};
+use cryptocurrency_kit::storage::values::StorageValue;
+use libp2p::{Multiaddr, PeerId};
+
+use crate::error::P2PError;
+
+/// How often the connected peers are asked for their peers
+pub const PEER_EXCHANGE_INTERVAL: Duration = Duration::from_secs(60);
+/// The peers in a reply, a larger reply is a violation
+pub const MAX_EXCHANGED_PEERS: usize = 64;
+/// The addresses of an exchanged peer
+pub const MAX_PEER_ADDRESSES: usize = 8;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PeerAddress {
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum PeerExchange {
+    /// asks for the known peers, with the listen addresses of the sender
+    Request(Vec<String>),
+    Peers(Vec<PeerAddress>),
+}
+
+implement_cryptohash_traits! {PeerExchange}
+implement_storagevalue_traits! {PeerExchange}
+
+impl PeerAddress {
+    pub fn new(peer_id: &PeerId, addresses: &[Multiaddr]) -> Self {
+        PeerAddress {
+            peer_id: peer_id.to_base58(),
+            addresses: addresses.iter().take(MAX_PEER_ADDRESSES).map(|addr| addr.to_string()).collect(),
+        }
+    }
+
+    pub fn parse(&self) -> Result<(PeerId, Vec<Multiaddr>), P2PError> {
+        let peer_id = PeerId::from_str(&self.peer_id).map_err(|err| P2PError::Violation(err.to_string()))?;
+        Ok((peer_id, parse_addresses(&self.addresses)?))
+    }
+}
+
+pub fn parse_addresses(addresses: &[String]) -> Result<Vec<Multiaddr>, P2PError> {
+    if addresses.len() > MAX_PEER_ADDRESSES {
+        return Err(P2PError::TooLarge(MAX_PEER_ADDRESSES, addresses.len()));
+    }
+    addresses
+        .iter()
+        .map(|addr| Multiaddr::from_str(addr).map_err(|err| P2PError::Violation(err.to_string())))
+        .collect()
+}
+
+impl PeerExchange {
+    /// Decodes the payload of a peer, it may come from anyone so it is decoded without panic
+    pub fn decode(payload: &[u8]) -> Result<Self, P2PError> {
+        let msg: PeerExchange = serde_json::from_slice(payload).map_err(|err| P2PError::Violation(err.to_string()))?;
+        if let PeerExchange::Peers(peers) = &msg {
+            if peers.len() > MAX_EXCHANGED_PEERS {
+                return Err(P2PError::TooLarge(MAX_EXCHANGED_PEERS, peers.len()));
+            }
+        }
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_peer_exchange() {
+        let peer_id = PeerId::random();
+        let addr = Multiaddr::from_str("/ip4/10.0.0.2/tcp/7960").unwrap();
+        let msg = PeerExchange::Peers(vec![PeerAddress::new(&peer_id, &[addr.clone()])]);
+        let decoded = PeerExchange::decode(&msg.clone().into_bytes()).unwrap();
+        assert_eq!(decoded, msg);
+        if let PeerExchange::Peers(peers) = decoded {
+            assert_eq!(peers[0].parse().unwrap(), (peer_id, vec![addr]));
+        }
+
+        assert!(PeerExchange::decode(b"[1, 2]").is_err());
+        let too_many = PeerExchange::Peers(vec![PeerAddress::new(&peer_id, &[]); MAX_EXCHANGED_PEERS + 1]);
+        assert!(PeerExchange::decode(&too_many.into_bytes()).is_err());
+        let invalid = PeerAddress { peer_id: peer_id.to_base58(), addresses: vec!["10.0.0.2:7960".to_string()] };
+        assert!(invalid.parse().is_err());
+    }
+}
@@ -0,0 +1,450 @@
+//! Decides which peers the server keeps: the inbound and outbound quotas, the bans, the redial of the
+//! bootstrap peers and the validators with a backoff, and the addresses remembered across restarts.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use cryptocurrency_kit::crypto::{hash, CryptoHash, Hash};
+use cryptocurrency_kit::ethkey::Address;
+use cryptocurrency_kit::storage::values::StorageValue;
+use libp2p::{Multiaddr, PeerId};
+
+use super::config::Config;
+use super::peer_exchange::MAX_PEER_ADDRESSES;
+use super::protocol::BoundType;
+use super::transport::peer_address;
+use crate::error::P2PError;
+use crate::store::schema::Schema;
+
+pub const MIN_REDIAL_BACKOFF: Duration = Duration::from_secs(1);
+pub const MAX_REDIAL_BACKOFF: Duration = Duration::from_secs(5 * 60);
+/// How long a peer dropped for its protocol violations may not connect
+pub const BAN_DURATION: Duration = Duration::from_secs(30 * 60);
+/// The least recently seen peers are forgotten beyond it
+pub const MAX_KNOWN_PEERS: usize = 1024;
+/// The failed dials before a peer learned from the others is no longer dialed
+pub const MAX_DISCOVERED_DIALS: u32 = 3;
+
+pub type ValidatorFn = dyn Fn(&Address) -> bool + Send + Sync;
+
+/// A known peer as it is persisted, the times are unix millis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerRecord {
+    pub addresses: Vec<String>,
+    pub last_seen: i64,
+    // 0 if the peer is not banned
+    pub banned_until: i64,
+}
+
+implement_cryptohash_traits! {PeerRecord}
+implement_storagevalue_traits! {PeerRecord}
+
+#[derive(Default)]
+struct KnownPeer {
+    addresses: Vec<Multiaddr>,
+    // the bootstrap peers, they are always redialed
+    reserved: bool,
+    // the validator address of the node key
+    address: Option<Address>,
+    connected: Option<BoundType>,
+    // the dials since the last connection
+    failures: u32,
+    next_dial: Option<Instant>,
+    last_seen: i64,
+    banned_until: i64,
+}
+
+/// The delay of the next dial after so many failed ones, it doubles up to `MAX_REDIAL_BACKOFF`
+pub fn backoff(failures: u32) -> Duration {
+    MIN_REDIAL_BACKOFF
+        .checked_mul(1 << failures.min(16))
+        .map_or(MAX_REDIAL_BACKOFF, |delay| delay.min(MAX_REDIAL_BACKOFF))
+}
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+pub struct PeerManager {
+    config: Config,
+    peers: HashMap<PeerId, KnownPeer>,
+    is_validator: Box<ValidatorFn>,
+    store: Option<Schema>,
+}
+
+impl PeerManager {
+    /// Loads the known peers of the store, each of them is dialed once again
+    pub fn new(config: Config, store: Option<Schema>, is_validator: Box<ValidatorFn>) -> Self {
+        let mut peers = HashMap::new();
+        if let Some(schema) = &store {
+            let now = Instant::now();
+            for (peer_id, record) in schema.peers().iter() {
+                let peer_id = match PeerId::from_str(&peer_id) {
+                    Ok(peer_id) => peer_id,
+                    Err(_) => continue,
+                };
+                let addresses: Vec<Multiaddr> = record.addresses.iter().filter_map(|addr| Multiaddr::from_str(addr).ok()).collect();
+                peers.insert(
+                    peer_id,
+                    KnownPeer {
+                        next_dial: if addresses.is_empty() { None } else { Some(now) },
+                        addresses,
+                        address: peer_address(&peer_id),
+                        last_seen: record.last_seen,
+                        banned_until: record.banned_until,
+                        ..KnownPeer::default()
+                    },
+                );
+            }
+            info!("Load {} known peers", peers.len());
+        }
+        PeerManager {
+            config,
+            peers,
+            is_validator,
+            store,
+        }
+    }
+
+    /// Remembers the addresses of the peer, a reserved peer is dialed until it connects
+    pub fn add_addresses(&mut self, peer_id: &PeerId, addresses: &[Multiaddr], reserved: bool) {
+        if !self.peers.contains_key(peer_id) {
+            self.evict();
+        }
+        let peer = self.entry(peer_id);
+        peer.reserved |= reserved;
+        // the newest addresses last, the oldest ones are forgotten past the limit
+        for addr in addresses {
+            peer.addresses.retain(|known| known != addr);
+            peer.addresses.push(addr.clone());
+        }
+        let excess = peer.addresses.len().saturating_sub(MAX_PEER_ADDRESSES);
+        peer.addresses.drain(..excess);
+        if self.is_reserved(peer_id) {
+            let peer = self.peers.get_mut(peer_id).unwrap();
+            if peer.connected.is_none() && peer.next_dial.is_none() {
+                peer.next_dial = Some(Instant::now());
+            }
+        }
+        self.persist(peer_id);
+    }
+
+    /// Remembers a peer learned from another one, it is dialed if the outbound quota allows.
+    /// The addresses of a bootstrap peer are configured, they are not replaced.
+    pub fn discover(&mut self, peer_id: &PeerId, addresses: &[Multiaddr]) {
+        if self.peers.get(peer_id).map_or(false, |peer| peer.reserved) {
+            return;
+        }
+        self.add_addresses(peer_id, addresses, false);
+        let banned = self.is_banned(peer_id);
+        let peer = self.entry(peer_id);
+        if !banned && peer.connected.is_none() && peer.next_dial.is_none() && peer.failures < MAX_DISCOVERED_DIALS {
+            peer.next_dial = Some(Instant::now());
+        }
+    }
+
+    /// The peers worth to share with the others, the connected and reserved ones first, then the recently seen ones.
+    /// A peer the node never connected to is not shared.
+    pub fn known_peers(&self, limit: usize) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let now = now_millis();
+        let mut known: Vec<(&PeerId, &KnownPeer)> = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| !peer.addresses.is_empty() && peer.banned_until <= now)
+            .filter(|(_, peer)| peer.reserved || peer.last_seen > 0)
+            .collect();
+        known.sort_by_key(|(_, peer)| std::cmp::Reverse((peer.connected.is_some() || peer.reserved, peer.last_seen)));
+        known.into_iter().take(limit).map(|(peer_id, peer)| (*peer_id, peer.addresses.clone())).collect()
+    }
+
+    /// The addresses announced to the peers, the listen address is used if there is none
+    pub fn external_addresses(&self) -> &[Multiaddr] {
+        &self.config.external_addresses
+    }
+
+    /// Forgets the addresses, e.g. the expired ones of the mdns
+    pub fn remove_addresses(&mut self, peer_id: &PeerId, addresses: &[Multiaddr]) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.addresses.retain(|addr| !addresses.contains(addr));
+            self.persist(peer_id);
+        }
+    }
+
+    /// The bootstrap peers and the validators, they are not limited by the quotas
+    pub fn is_reserved(&self, peer_id: &PeerId) -> bool {
+        let (reserved, address) = match self.peers.get(peer_id) {
+            Some(peer) => (peer.reserved, peer.address),
+            None => (false, peer_address(peer_id)),
+        };
+        reserved || address.map_or(false, |address| (self.is_validator)(&address))
+    }
+
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        self.peers.get(peer_id).map_or(false, |peer| peer.banned_until > now_millis())
+    }
+
+    /// Refuses the connections of the peer for the duration
+    pub fn ban(&mut self, peer_id: &PeerId, duration: Duration) {
+        let peer = self.entry(peer_id);
+        peer.banned_until = now_millis() + duration.as_millis() as i64;
+        peer.next_dial = None;
+        warn!("Ban the peer {} for {:?}", peer_id.to_base58(), duration);
+        self.persist(peer_id);
+    }
+
+    /// Whether the handshaked peer may stay connected
+    pub fn admit(&self, peer_id: &PeerId, bound_type: &BoundType) -> Result<(), P2PError> {
+        if self.is_banned(peer_id) {
+            return Err(P2PError::Banned(peer_id.to_base58()));
+        }
+        if self.is_reserved(peer_id) {
+            return Ok(());
+        }
+        let (inbound, outbound) = self.connections();
+        if inbound + outbound >= self.config.max_connection_size {
+            return Err(P2PError::TooManyPeers("connected".to_string(), self.config.max_connection_size));
+        }
+        match bound_type {
+            BoundType::InBound if inbound >= self.config.max_inbound => {
+                Err(P2PError::TooManyPeers("inbound".to_string(), self.config.max_inbound))
+            }
+            BoundType::OutBound if outbound >= self.config.max_outbound => {
+                Err(P2PError::TooManyPeers("outbound".to_string(), self.config.max_outbound))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether a new outbound connection to the peer is worth to dial
+    pub fn may_dial(&self, peer_id: &PeerId) -> bool {
+        if self.is_banned(peer_id) || self.peers.get(peer_id).map_or(false, |peer| peer.connected.is_some()) {
+            return false;
+        }
+        self.is_reserved(peer_id) || self.connections().1 < self.config.max_outbound
+    }
+
+    pub fn on_connected(&mut self, peer_id: &PeerId, bound_type: BoundType) {
+        let peer = self.entry(peer_id);
+        peer.connected = Some(bound_type);
+        peer.failures = 0;
+        peer.next_dial = None;
+        peer.last_seen = now_millis();
+        self.persist(peer_id);
+    }
+
+    /// A reserved peer is redialed after the backoff
+    pub fn on_disconnected(&mut self, peer_id: &PeerId, now: Instant) {
+        let reserved = self.is_reserved(peer_id);
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.connected = None;
+            peer.last_seen = now_millis();
+            if reserved && peer.banned_until <= peer.last_seen {
+                peer.next_dial = Some(now + backoff(peer.failures));
+            }
+        }
+    }
+
+    /// The peers to dial now, the next dial of each of them is scheduled in case this one fails
+    pub fn due_dials(&mut self, now: Instant) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let due: Vec<PeerId> = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| peer.connected.is_none() && !peer.addresses.is_empty())
+            .filter(|(_, peer)| peer.next_dial.map_or(false, |at| at <= now))
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        let mut dials = vec![];
+        for peer_id in due {
+            let (may_dial, reserved) = (self.may_dial(&peer_id), self.is_reserved(&peer_id));
+            let peer = self.peers.get_mut(&peer_id).unwrap();
+            peer.failures += 1;
+            // only the reserved peers are dialed until they connect
+            peer.next_dial = if reserved { Some(now + backoff(peer.failures)) } else { None };
+            if may_dial {
+                dials.push((peer_id, peer.addresses.clone()));
+            }
+        }
+        dials
+    }
+
+    fn entry(&mut self, peer_id: &PeerId) -> &mut KnownPeer {
+        self.peers.entry(*peer_id).or_insert_with(|| KnownPeer {
+            address: peer_address(peer_id),
+            ..KnownPeer::default()
+        })
+    }
+
+    fn connections(&self) -> (u64, u64) {
+        self.peers.values().fold((0, 0), |(inbound, outbound), peer| match peer.connected {
+            Some(BoundType::InBound) => (inbound + 1, outbound),
+            Some(BoundType::OutBound) => (inbound, outbound + 1),
+            None => (inbound, outbound),
+        })
+    }
+
+    /// Forgets the least recently seen peer if there are too many of them
+    fn evict(&mut self) {
+        if self.peers.len() < MAX_KNOWN_PEERS {
+            return;
+        }
+        let oldest = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| peer.connected.is_none() && !peer.reserved)
+            .min_by_key(|(_, peer)| peer.last_seen)
+            .map(|(peer_id, _)| *peer_id);
+        if let Some(peer_id) = oldest {
+            self.peers.remove(&peer_id);
+            if let Some(schema) = &self.store {
+                schema.peers().remove(&peer_id.to_base58());
+            }
+        }
+    }
+
+    fn persist(&self, peer_id: &PeerId) {
+        if let (Some(schema), Some(peer)) = (&self.store, self.peers.get(peer_id)) {
+            let record = PeerRecord {
+                addresses: peer.addresses.iter().map(|addr| addr.to_string()).collect(),
+                last_seen: peer.last_seen,
+                banned_until: peer.banned_until,
+            };
+            schema.peers().put(&peer_id.to_base58(), record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::random_dir;
+    use crate::p2p::transport::node_keypair;
+    use crate::store::schema::database_config;
+    use cryptocurrency_kit::ethkey::{KeyPair, Secret};
+    use kvdb_rocksdb::Database;
+    use std::sync::Arc;
+
+    fn manager(store: Option<Schema>) -> PeerManager {
+        PeerManager::new(Config::new(1, 1, 2), store, Box::new(|_| false))
+    }
+
+    fn addr(port: u16) -> Multiaddr {
+        Multiaddr::from_str(&format!("/ip4/127.0.0.1/tcp/{}", port)).unwrap()
+    }
+
+    #[test]
+    fn t_backoff() {
+        assert_eq!(backoff(0), MIN_REDIAL_BACKOFF);
+        assert_eq!(backoff(3), MIN_REDIAL_BACKOFF * 8);
+        assert_eq!(backoff(100), MAX_REDIAL_BACKOFF);
+    }
+
+    #[test]
+    fn t_quota_and_ban() {
+        let mut manager = manager(None);
+        let (first, second, reserved) = (PeerId::random(), PeerId::random(), PeerId::random());
+        assert!(manager.admit(&first, &BoundType::InBound).is_ok());
+        manager.on_connected(&first, BoundType::InBound);
+        assert!(manager.admit(&second, &BoundType::InBound).is_err());
+        assert!(manager.admit(&second, &BoundType::OutBound).is_ok());
+        // the bootstrap peers are not counted
+        manager.add_addresses(&reserved, &[addr(1)], true);
+        assert!(manager.admit(&reserved, &BoundType::InBound).is_ok());
+
+        manager.ban(&second, BAN_DURATION);
+        assert!(!manager.may_dial(&second));
+        match manager.admit(&second, &BoundType::OutBound) {
+            Err(P2PError::Banned(_)) => {}
+            _ => panic!("the banned peer is admitted"),
+        }
+    }
+
+    #[test]
+    fn t_redial() {
+        let mut manager = manager(None);
+        let (bootstrap, other) = (PeerId::random(), PeerId::random());
+        manager.add_addresses(&bootstrap, &[addr(1)], true);
+        manager.add_addresses(&other, &[addr(2)], false);
+
+        let now = Instant::now();
+        assert_eq!(manager.due_dials(now), vec![(bootstrap, vec![addr(1)])]);
+        // the failed dial is retried after the backoff
+        assert!(manager.due_dials(now).is_empty());
+        assert_eq!(manager.due_dials(now + backoff(1)).len(), 1);
+
+        manager.on_connected(&bootstrap, BoundType::OutBound);
+        assert!(manager.due_dials(now + MAX_REDIAL_BACKOFF).is_empty());
+        manager.on_disconnected(&bootstrap, now);
+        assert!(manager.due_dials(now).is_empty());
+        assert_eq!(manager.due_dials(now + backoff(0)).len(), 1);
+    }
+
+    #[test]
+    fn t_discover() {
+        let mut manager = manager(None);
+        let (seen, learned) = (PeerId::random(), PeerId::random());
+        manager.add_addresses(&seen, &[addr(1)], false);
+        manager.on_connected(&seen, BoundType::OutBound);
+        manager.discover(&learned, &[addr(2)]);
+        // only the peers the node connected to are shared
+        assert_eq!(manager.known_peers(10), vec![(seen, vec![addr(1)])]);
+
+        manager.on_disconnected(&seen, Instant::now());
+        let mut dials = manager.due_dials(Instant::now());
+        assert_eq!(dials.pop(), Some((learned, vec![addr(2)])));
+        // an unreachable peer is given up after some dials
+        for _ in 1..MAX_DISCOVERED_DIALS {
+            manager.discover(&learned, &[addr(2)]);
+            assert_eq!(manager.due_dials(Instant::now()).len(), 1);
+        }
+        manager.discover(&learned, &[addr(2)]);
+        assert!(manager.due_dials(Instant::now()).is_empty());
+    }
+
+    #[test]
+    fn t_address_limit() {
+        let mut manager = manager(None);
+        let (bootstrap, other) = (PeerId::random(), PeerId::random());
+        let addresses: Vec<Multiaddr> = (0..MAX_PEER_ADDRESSES as u16 + 2).map(addr).collect();
+        manager.discover(&other, &addresses);
+        // the newest ones are kept
+        assert_eq!(manager.entry(&other).addresses, addresses[2..].to_vec());
+        manager.discover(&other, &[addr(2)]);
+        assert_eq!(manager.entry(&other).addresses.last(), Some(&addr(2)));
+        assert_eq!(manager.entry(&other).addresses.len(), MAX_PEER_ADDRESSES);
+
+        // the configured addresses of a bootstrap peer are not replaced
+        manager.add_addresses(&bootstrap, &[addr(1)], true);
+        manager.discover(&bootstrap, &addresses);
+        assert_eq!(manager.entry(&bootstrap).addresses, vec![addr(1)]);
+    }
+
+    #[test]
+    fn t_validator_peer() {
+        let secret = Secret::from([9; 32]);
+        let validator = KeyPair::from_secret(secret.clone()).unwrap().address();
+        let peer_id = node_keypair(&secret).unwrap().public().to_peer_id();
+        let manager = PeerManager::new(Config::new(0, 0, 0), None, Box::new(move |address| *address == validator));
+        assert!(manager.is_reserved(&peer_id));
+        assert!(manager.admit(&peer_id, &BoundType::InBound).is_ok());
+        assert!(manager.admit(&PeerId::random(), &BoundType::InBound).is_err());
+    }
+
+    #[test]
+    fn t_persist() {
+        let db = Arc::new(Database::open(&database_config(), &random_dir()).unwrap());
+        let (known, banned) = (PeerId::random(), PeerId::random());
+        {
+            let mut manager = manager(Some(Schema::new(db.clone())));
+            manager.add_addresses(&known, &[addr(1)], false);
+            manager.on_connected(&known, BoundType::OutBound);
+            manager.ban(&banned, BAN_DURATION);
+        }
+
+        // the known peers are dialed again after a restart, the bans are kept
+        let mut manager = manager(Some(Schema::new(db)));
+        assert!(manager.is_banned(&banned));
+        assert_eq!(manager.due_dials(Instant::now()), vec![(known, vec![addr(1)])]);
+    }
+}
@@ -5,6 +5,8 @@ use libp2p::PeerId;
 use cryptocurrency_kit::crypto::{CryptoHash, Hash, hash};
 use cryptocurrency_kit::storage::values::StorageValue;
 
+use crate::error::P2PError;
+
 #[derive(Debug, Clone, Deserialize, Serialize, Eq, PartialEq)]
 pub enum P2PMsgCode {
     Ping,
@@ -13,11 +15,54 @@ pub enum P2PMsgCode {
     Block,
     Consensus,
     Sync,
+    Evidence,
+    PeerExchange,
 }
 
 implement_storagevalue_traits! {P2PMsgCode}
 implement_cryptohash_traits! {P2PMsgCode}
 
+impl P2PMsgCode {
+    /// The capability a peer needs to receive the message, none for the base protocol
+    pub fn capability(&self) -> Option<&'static str> {
+        match self {
+            P2PMsgCode::Transaction => Some(CAP_TX_GOSSIP),
+            P2PMsgCode::Sync => Some(CAP_SYNC_V2),
+            P2PMsgCode::Evidence => Some(CAP_EVIDENCE),
+            P2PMsgCode::PeerExchange => Some(CAP_PEER_EXCHANGE),
+            _ => None,
+        }
+    }
+
+    /// The code byte in the frame prefix
+    pub fn to_u8(&self) -> u8 {
+        match self {
+            P2PMsgCode::Ping => 0,
+            P2PMsgCode::Handshake => 1,
+            P2PMsgCode::Transaction => 2,
+            P2PMsgCode::Block => 3,
+            P2PMsgCode::Consensus => 4,
+            P2PMsgCode::Sync => 5,
+            P2PMsgCode::Evidence => 6,
+            P2PMsgCode::PeerExchange => 7,
+        }
+    }
+
+    pub fn from_u8(code: u8) -> Option<P2PMsgCode> {
+        match code {
+            0 => Some(P2PMsgCode::Ping),
+            1 => Some(P2PMsgCode::Handshake),
+            2 => Some(P2PMsgCode::Transaction),
+            3 => Some(P2PMsgCode::Block),
+            4 => Some(P2PMsgCode::Consensus),
+            5 => Some(P2PMsgCode::Sync),
+            6 => Some(P2PMsgCode::Evidence),
+            7 => Some(P2PMsgCode::PeerExchange),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum BoundType {
     InBound,
@@ -78,11 +123,47 @@ impl Header {
 
 pub type Payload = Vec<u8>;
 
+/// The version of the wire protocol, it changes when a payload changes incompatibly.
+/// The peers before the handshake carried it are version 0.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// the headers-first sync of `SyncMessage`
+pub const CAP_SYNC_V2: &str = "sync/2";
+/// the batched transaction gossip
+pub const CAP_TX_GOSSIP: &str = "tx_gossip";
+/// the equivocation evidences
+pub const CAP_EVIDENCE: &str = "evidence";
+/// the addresses of the known peers, see `peer_exchange`
+pub const CAP_PEER_EXCHANGE: &str = "peer_exchange";
+/// blocks sent as the header and the transaction hashes, no node serves them yet
+pub const CAP_COMPACT_BLOCKS: &str = "compact_blocks";
+
+/// The capabilities of the local node
+pub fn local_capabilities() -> Vec<String> {
+    vec![
+        CAP_SYNC_V2.to_string(),
+        CAP_TX_GOSSIP.to_string(),
+        CAP_EVIDENCE.to_string(),
+        CAP_PEER_EXCHANGE.to_string(),
+    ]
+}
+
+/// A version 0 peer expects the bare height for the sync and does not know the batched transactions,
+/// so it is given none of the capabilities
+fn legacy_capabilities() -> Vec<String> {
+    vec![]
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Handshake {
     version: String,
     peer_id: String,
     genesis: Hash,
+    // the capabilities are names, so a peer skips the ones it does not know
+    #[serde(default)]
+    protocol: u32,
+    #[serde(default)]
+    capabilities: Vec<String>,
 }
 
 implement_storagevalue_traits! {Handshake}
@@ -95,18 +176,56 @@ impl Handshake {
             version,
             peer_id,
             genesis,
+            protocol: PROTOCOL_VERSION,
+            capabilities: local_capabilities(),
         }
     }
 
+    pub fn protocol(&self) -> u32 {
+        self.protocol
+    }
+
+    /// The capabilities both sides support, the behaviour of the session follows them
+    pub fn negotiate(&self, local: &[String]) -> Vec<String> {
+        let remote = if self.protocol == 0 { legacy_capabilities() } else { self.capabilities.clone() };
+        local.iter().filter(|capability| remote.contains(capability)).cloned().collect()
+    }
+
     pub fn version(&self) -> &String {
         &self.version
     }
 
-    pub fn peer_id(&self) -> PeerId {
-        PeerId::from_str(&self.peer_id).unwrap()
+    pub fn peer_id(&self) -> Result<PeerId, P2PError> {
+        PeerId::from_str(&self.peer_id).map_err(|_| P2PError::HandShakeFailed)
     }
 
     pub fn genesis(&self) -> &Hash {
         &self.genesis
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_negotiate() {
+        let peer_id = PeerId::random();
+        let handshake = Handshake::new("0.1.1".to_string(), peer_id, Hash::zero());
+        assert_eq!(handshake.negotiate(&local_capabilities()), local_capabilities());
+
+        // the unknown capabilities of a newer peer are ignored
+        let mut newer = handshake.clone();
+        newer.protocol = PROTOCOL_VERSION + 1;
+        newer.capabilities = vec![CAP_TX_GOSSIP.to_string(), CAP_COMPACT_BLOCKS.to_string()];
+        assert_eq!(newer.negotiate(&local_capabilities()), vec![CAP_TX_GOSSIP.to_string()]);
+
+        // a peer of the first release announces nothing
+        let legacy = format!(r#"{{"version":"0.1.1","peer_id":"{}","genesis":{}}}"#,
+                             peer_id.to_base58(), serde_json::to_string(&Hash::zero()).unwrap());
+        let legacy: Handshake = serde_json::from_str(&legacy).unwrap();
+        assert_eq!(legacy.protocol(), 0);
+        assert!(legacy.negotiate(&local_capabilities()).is_empty());
+        assert_eq!(P2PMsgCode::Evidence.capability(), Some(CAP_EVIDENCE));
+        assert_eq!(P2PMsgCode::Consensus.capability(), None);
+    }
+}
@@ -2,10 +2,11 @@ use std::collections::HashMap;
 use std::net;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use cryptocurrency_kit::crypto::{CryptoHash, Hash};
 use cryptocurrency_kit::storage::values::StorageValue;
+use libp2p::identity::Keypair;
 use libp2p::multiaddr::Protocol;
 use libp2p::{Multiaddr, PeerId};
 use lru_time_cache::LruCache;
@@ -13,17 +14,30 @@ use parking_lot::RwLock;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::time::{interval, sleep};
 
-use super::protocol::{BoundType, RawMessage, Header as RawHeader, P2PMsgCode, Handshake};
+use super::peer_exchange::{parse_addresses, PeerAddress, PeerExchange, MAX_EXCHANGED_PEERS, PEER_EXCHANGE_INTERVAL};
+use super::peer_manager::{PeerManager, BAN_DURATION};
+use super::protocol::{local_capabilities, BoundType, RawMessage, Header as RawHeader, P2PMsgCode, Handshake};
 use super::session::{Session, SessionTx};
+use super::transport::{secure_inbound, secure_outbound, Allowlist};
+use super::tx_gossip::{TxGossip, TX_FLUSH_INTERVAL};
 use crate::{
     common::multiaddr_to_ipv4,
     error::P2PError,
+    metrics,
     subscriber::events::{BroadcastEvent, ChainEvent},
+    sync::messages::SyncMessage,
     types::block::Blocks,
+    types::transaction::Transactions,
 };
 
 pub const MAX_OUTBOUND_CONNECTION_MAILBOX: usize = 1 << 10;
 pub const MAX_INBOUND_CONNECTION_MAILBOX: usize = 1 << 9;
+/// A peer is dropped after so many protocol violations
+pub const MAX_PROTOCOL_VIOLATIONS: u32 = 10;
+/// How long a protocol violation of a peer is counted
+pub const VIOLATION_EXPIRY: Duration = Duration::from_secs(10 * 60);
+/// How often the peer manager is asked for the peers to redial
+pub const REDIAL_INTERVAL: Duration = Duration::from_secs(1);
 
 lazy_static::lazy_static! {
     pub static ref ZERO_PEER: PeerId =
@@ -31,7 +45,8 @@ lazy_static::lazy_static! {
 }
 
 pub type AuthorFn = dyn Fn(Handshake) -> bool + Send + Sync;
-pub type HandleMsgFn = dyn Fn(PeerId, RawMessage) -> Result<(), String> + Send + Sync;
+/// Handles a message of a peer, only a `P2PError::Violation` counts against the peer
+pub type HandleMsgFn = dyn Fn(PeerId, RawMessage) -> Result<(), P2PError> + Send + Sync;
 
 pub fn author_handshake(genesis: Hash) -> impl Fn(Handshake) -> bool {
     move |handshake: Handshake| handshake.genesis() == &genesis
@@ -61,45 +76,89 @@ pub enum ServerEvent {
     Disconnected(PeerId),
     Message(PeerId, RawMessage),
     Ping(PeerId),
+    /// the peer sent a malformed or refused message
+    Violation(PeerId, String),
     WithReply(Box<ServerEvent>, tokio::sync::oneshot::Sender<Result<PeerId, P2PError>>),
 }
 
+/// A connected peer, `last_seen` is the time of its last ping
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerInfo {
+    pub peer_id: String,
+    pub inbound: bool,
+    pub last_seen: chrono::DateTime<chrono::Utc>,
+    pub protocol: u32,
+    pub capabilities: Vec<String>,
+}
+
 struct ConnectInfo {
     connect_time: chrono::DateTime<chrono::Utc>,
     bound_type: BoundType,
     write_tx: SessionTx,
+    // the protocol version of the peer and the capabilities negotiated with it
+    protocol: u32,
+    capabilities: Vec<String>,
 }
 
 impl ConnectInfo {
-    fn new(connect_time: chrono::DateTime<chrono::Utc>, bound_type: BoundType, write_tx: SessionTx) -> Self {
+    fn new(
+        connect_time: chrono::DateTime<chrono::Utc>,
+        bound_type: BoundType,
+        write_tx: SessionTx,
+        protocol: u32,
+        capabilities: Vec<String>,
+    ) -> Self {
         ConnectInfo {
             connect_time,
             bound_type,
             write_tx,
+            protocol,
+            capabilities,
         }
     }
+
+    /// Whether the peer understands the message, the others are not sent to it
+    fn accepts(&self, code: &P2PMsgCode) -> bool {
+        code.capability()
+            .map_or(true, |capability| self.capabilities.iter().any(|negotiated| negotiated == capability))
+    }
 }
 
 #[derive(Clone)]
 pub struct TcpServer {
     node_info: (PeerId, Multiaddr),
+    // the node key, the noise handshake proves the local peer id with it
+    key: Keypair,
+    allowlist: Allowlist,
     peers: Arc<RwLock<HashMap<PeerId, ConnectInfo>>>,
     genesis: Hash,
     cache: Arc<RwLock<LruCache<Hash, bool>>>,
+    tx_gossip: Arc<RwLock<TxGossip>>,
+    // the recent protocol violations of every peer
+    violations: Arc<RwLock<LruCache<PeerId, u32>>>,
+    manager: Arc<RwLock<PeerManager>>,
+    // the peers answered recently, a peer is answered once per exchange
+    exchanges: Arc<RwLock<LruCache<PeerId, bool>>>,
+    // the peers asked for their peers, one reply is accepted per request
+    requests: Arc<RwLock<LruCache<PeerId, bool>>>,
     author_fn: Arc<AuthorFn>,
     handles: Arc<HandleMsgFn>,
     server_handle: TcpServerHandle,
 }
 
 impl TcpServer {
+    /// Listens on the address, the peer id is the one of the node key.
+    /// Only the peers in the allowlist may connect if it is not empty, the manager keeps the quotas.
     pub fn new(
-        peer_id: PeerId,
+        key: Keypair,
         mul_addr: Multiaddr,
-        _key: Option<()>,
+        allowlist: Allowlist,
+        manager: PeerManager,
         genesis: Hash,
         author: Box<dyn Fn(Handshake) -> bool + Send + Sync>,
-        handles: Box<dyn Fn(PeerId, RawMessage) -> Result<(), String> + Send + Sync>,
+        handles: Box<dyn Fn(PeerId, RawMessage) -> Result<(), P2PError> + Send + Sync>,
     ) -> (Self, TcpServerHandle) {
+        let peer_id = key.public().to_peer_id();
         let author = Arc::new(author);
         let handles = Arc::new(handles);
         let mut addr: String = String::new();
@@ -121,9 +180,16 @@ impl TcpServer {
 
         let server = TcpServer {
             node_info: (peer_id, mul_addr.clone()),
+            key,
+            allowlist,
             peers: Arc::new(RwLock::new(HashMap::new())),
             genesis,
             cache: Arc::new(RwLock::new(LruCache::with_expiry_duration_and_capacity(Duration::from_secs(5), 100_000))),
+            tx_gossip: Arc::new(RwLock::new(TxGossip::new())),
+            violations: Arc::new(RwLock::new(LruCache::with_expiry_duration(VIOLATION_EXPIRY))),
+            manager: Arc::new(RwLock::new(manager)),
+            exchanges: Arc::new(RwLock::new(LruCache::with_expiry_duration(PEER_EXCHANGE_INTERVAL / 2))),
+            requests: Arc::new(RwLock::new(LruCache::with_expiry_duration(PEER_EXCHANGE_INTERVAL))),
             author_fn: author,
             handles,
             server_handle: server_handle.clone(),
@@ -135,28 +201,52 @@ impl TcpServer {
         let author_fn = server.author_fn.clone();
         let handles = server.handles.clone();
         let cache = server.cache.clone();
+        let tx_gossip = server.tx_gossip.clone();
+        let violations = server.violations.clone();
+        let manager = server.manager.clone();
+        let exchanges = server.exchanges.clone();
+        let requests = server.requests.clone();
+        let key = server.key.clone();
+        let allowlist = server.allowlist.clone();
 
         std::thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new().expect("runtime");
             rt.block_on(async move {
                 let listener = TcpListener::bind(socket_addr).await.expect("bind");
                 let mut peer_cleanup = interval(Duration::from_secs(3));
+                let mut tx_flush = interval(TX_FLUSH_INTERVAL);
+                let mut redial = interval(REDIAL_INTERVAL);
+                let mut peer_exchange = interval(PEER_EXCHANGE_INTERVAL);
 
                 loop {
                     tokio::select! {
                         accept = listener.accept() => {
                         if let Ok((stream, _)) = accept {
-                            let (read, write) = stream.into_split();
-                            let (write_tx, write_rx) = tokio::sync::mpsc::unbounded_channel();
-                            let session = Session::new(
-                                *ZERO_PEER,
-                                node_info.0,
-                                server_handle_for_spawn.clone(),
-                                BoundType::InBound,
-                                genesis,
-                                write_tx,
-                            );
+                            let key = key.clone();
+                            let allowlist = allowlist.clone();
+                            let local_id = node_info.0;
+                            let server_handle = server_handle_for_spawn.clone();
                             tokio::spawn(async move {
+                                let (remote_id, read, write) = match secure_inbound(&key, stream).await {
+                                    Ok(secured) => secured,
+                                    Err(err) => {
+                                        debug!("Reject the inbound connection, err: {}", err);
+                                        return;
+                                    }
+                                };
+                                if !allowlist.allows(&remote_id) {
+                                    info!("Reject the inbound connection, err: {}", P2PError::NotAllowed(remote_id.to_base58()));
+                                    return;
+                                }
+                                let (write_tx, write_rx) = tokio::sync::mpsc::unbounded_channel();
+                                let session = Session::new(
+                                    remote_id,
+                                    local_id,
+                                    server_handle,
+                                    BoundType::InBound,
+                                    genesis,
+                                    write_tx,
+                                );
                                 session.run(read, write, write_rx).await;
                             });
                         }
@@ -165,11 +255,36 @@ impl TcpServer {
                         if let Some(ev) = event {
                             match ev {
                                 ServerEvent::WithReply(inner, reply) => {
-                                    let result = handle_server_event(&inner, &peers, &cache, &author_fn, &handles, &node_info, &genesis);
+                                    // the banned peers and the ones beyond the quotas are refused after the handshake
+                                    let admitted = match inner.as_ref() {
+                                        ServerEvent::Connected(remote_id, bound_type, _, _) => manager.read().admit(remote_id, bound_type),
+                                        _ => Ok(()),
+                                    };
+                                    let result = admitted.and_then(|_| handle_server_event(&inner, &peers, &cache, &tx_gossip, &author_fn, &handles, &node_info));
+                                    if let (Ok(peer_id), ServerEvent::Connected(_, bound_type, _, _)) = (&result, inner.as_ref()) {
+                                        manager.write().on_connected(peer_id, *bound_type);
+                                        request_peers(&peers, &manager, &requests, &node_info, peer_id);
+                                    }
                                     let _ = reply.send(result);
                                 }
+                                ServerEvent::Message(peer_id, raw_msg) if raw_msg.header().code == P2PMsgCode::PeerExchange => {
+                                    let result = check_accepted(&peers, &peer_id, &raw_msg.header().code)
+                                        .and_then(|_| handle_peer_exchange(&peers, &manager, &exchanges, &requests, &node_info, &peer_id, raw_msg.payload()));
+                                    if let Err(err) = result {
+                                        record_violation(&peers, &tx_gossip, &violations, &manager, &peer_id, &err);
+                                    }
+                                }
+                                ServerEvent::Message(peer_id, _) | ServerEvent::Violation(peer_id, _) => {
+                                    if let Err(err) = handle_server_event(&ev, &peers, &cache, &tx_gossip, &author_fn, &handles, &node_info) {
+                                        record_violation(&peers, &tx_gossip, &violations, &manager, &peer_id, &err);
+                                    }
+                                }
+                                ServerEvent::Disconnected(peer_id) => {
+                                    let _ = handle_server_event(&ev, &peers, &cache, &tx_gossip, &author_fn, &handles, &node_info);
+                                    manager.write().on_disconnected(&peer_id, Instant::now());
+                                }
                                 _ => {
-                                    let _ = handle_server_event(&ev, &peers, &cache, &author_fn, &handles, &node_info, &genesis);
+                                    let _ = handle_server_event(&ev, &peers, &cache, &tx_gossip, &author_fn, &handles, &node_info);
                                 }
                             }
                         } else {
@@ -186,6 +301,24 @@ impl TcpServer {
                         }
                         for peer in to_remove {
                             peers.write().remove(&peer);
+                            tx_gossip.write().remove_peer(&peer);
+                        }
+                        observe_peers(&peers.read());
+                    }
+                    _ = tx_flush.tick() => {
+                        flush_transactions(&peers, &tx_gossip);
+                    }
+                    _ = peer_exchange.tick() => {
+                        let connected: Vec<PeerId> = peers.read().keys().copied().collect();
+                        for peer_id in &connected {
+                            request_peers(&peers, &manager, &requests, &node_info, peer_id);
+                        }
+                    }
+                    _ = redial.tick() => {
+                        let dials = manager.write().due_dials(Instant::now());
+                        for (remote_id, addresses) in dials {
+                            debug!("Redial the peer {}", remote_id.to_base58());
+                            dial(key.clone(), node_info.0, genesis, server_handle_for_spawn.clone(), remote_id, addresses);
                         }
                     }
                 }
@@ -196,16 +329,42 @@ impl TcpServer {
         (server, server_handle)
     }
 
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        self.peers
+            .read()
+            .iter()
+            .map(|(peer_id, info)| PeerInfo {
+                peer_id: peer_id.to_base58(),
+                inbound: matches!(info.bound_type, BoundType::InBound),
+                last_seen: info.connect_time,
+                protocol: info.protocol,
+                capabilities: info.capabilities.clone(),
+            })
+            .collect()
+    }
+
+    /// Disconnects the peer, returns false if it is not connected
+    pub fn drop_peer(&self, peer_id: &PeerId) -> bool {
+        // the session closes once its sender is dropped
+        let dropped = self.peers.write().remove(peer_id).is_some();
+        if dropped {
+            self.tx_gossip.write().remove_peer(peer_id);
+            observe_peers(&self.peers.read());
+        }
+        dropped
+    }
+
     pub fn broadcast(&self, msg: &RawMessage) {
         let peers = self.peers.read();
+        let code = &msg.header().code;
         if let Some(peer_bytes) = &msg.header().peer_id {
             if let Ok(peer_id) = PeerId::from_bytes(peer_bytes.as_slice()) {
-                if let Some(info) = peers.get(&peer_id) {
+                if let Some(info) = peers.get(&peer_id).filter(|info| info.accepts(code)) {
                     let _ = info.write_tx.send(msg.clone());
                 }
             }
         } else {
-            for info in peers.values() {
+            for info in peers.values().filter(|info| info.accepts(code)) {
                 let _ = info.write_tx.send(msg.clone());
             }
         }
@@ -219,6 +378,12 @@ impl TcpServer {
                 let raw_msg = RawMessage::new(header, payload);
                 self.broadcast(&raw_msg);
             }
+            BroadcastEvent::Raft(msg) => {
+                let header = RawHeader::new(P2PMsgCode::Consensus, 10, chrono::Local::now().timestamp_millis() as u64, None);
+                let payload = msg.into_bytes();
+                let raw_msg = RawMessage::new(header, payload);
+                self.broadcast(&raw_msg);
+            }
             BroadcastEvent::Blocks(peer_id, blocks) => {
                 let mut header = RawHeader::new(P2PMsgCode::Block, 10, chrono::Local::now().timestamp_millis() as u64, None);
                 if let Some(pid) = peer_id {
@@ -228,15 +393,20 @@ impl TcpServer {
                 let raw_msg = RawMessage::new(header, payload);
                 self.broadcast(&raw_msg);
             }
-            BroadcastEvent::Sync(height) => {
-                if let Some(peer_id) = self.peers.read().keys().next().cloned() {
-                    let header = RawHeader::new(P2PMsgCode::Sync, 10, chrono::Local::now().timestamp_millis() as u64, Some(peer_id.to_bytes().to_vec()));
-                    let payload = height.into_bytes();
-                    let raw_msg = RawMessage::new(header, payload);
-                    self.broadcast(&raw_msg);
+            BroadcastEvent::Transaction(transaction) => {
+                // the transactions are sent in batches, a full batch is sent at once
+                let full = self.tx_gossip.write().queue(transaction);
+                if full {
+                    flush_transactions(&self.peers, &self.tx_gossip);
                 }
             }
-            _ => {}
+            BroadcastEvent::Sync(peer_id, msg) => {
+                let peer_id = peer_id.map(|peer_id| peer_id.to_bytes().to_vec());
+                let header = RawHeader::new(P2PMsgCode::Sync, 10, chrono::Local::now().timestamp_millis() as u64, peer_id);
+                let payload = msg.into_bytes();
+                let raw_msg = RawMessage::new(header, payload);
+                self.broadcast(&raw_msg);
+            }
         }
     }
 
@@ -246,42 +416,48 @@ impl TcpServer {
                 self.handle_broadcast_event(BroadcastEvent::Blocks(None, Blocks(vec![block])));
             }
             ChainEvent::SyncBlock(height) => {
-                self.handle_broadcast_event(BroadcastEvent::Sync(height));
+                // the peers ahead reply with their status, then the sync service catches up with them
+                let status = SyncMessage::Status(height.saturating_sub(1));
+                self.handle_broadcast_event(BroadcastEvent::Sync(None, status));
             }
             ChainEvent::PostBlock(peer_id, blocks) => {
                 self.handle_broadcast_event(BroadcastEvent::Blocks(peer_id, blocks));
             }
+            ChainEvent::Evidence(evidence) => {
+                let header = RawHeader::new(P2PMsgCode::Evidence, 10, chrono::Local::now().timestamp_millis() as u64, None);
+                let raw_msg = RawMessage::new(header, evidence.into_bytes());
+                self.broadcast(&raw_msg);
+            }
             _ => {}
         }
     }
 
+    /// Dials the discovered peer if the outbound quota allows, the reserved peers are left to the redial
     pub fn add_peer(&self, remote_id: PeerId, remote_addresses: Vec<Multiaddr>) {
+        self.manager.write().add_addresses(&remote_id, &remote_addresses, false);
         if self.peers.read().contains_key(&remote_id) {
             return;
         }
-        let mul_addr = remote_addresses[0].clone();
-        let local_id = self.node_info.0;
-        let genesis = self.genesis;
-        let handle = self.clone_handle();
-        tokio::spawn(async move {
-            let delay = rand::random::<u64>() % 100;
-            sleep(Duration::from_millis(delay)).await;
-            if let Ok(socket_addr) = multiaddr_to_ipv4(&mul_addr) {
-                if let Ok(stream) = TcpStream::connect(socket_addr).await {
-                    let (read, write) = stream.into_split();
-                    let (write_tx, write_rx) = tokio::sync::mpsc::unbounded_channel();
-                    let session = Session::new(
-                        remote_id,
-                        local_id,
-                        handle,
-                        BoundType::OutBound,
-                        genesis,
-                        write_tx,
-                    );
-                    session.run(read, write, write_rx).await;
-                }
+        {
+            let manager = self.manager.read();
+            if manager.is_reserved(&remote_id) || !manager.may_dial(&remote_id) {
+                return;
             }
-        });
+        }
+        dial(self.key.clone(), self.node_info.0, self.genesis, self.clone_handle(), remote_id, remote_addresses);
+    }
+
+    /// Keeps a connection to the peer, it is redialed with a backoff whenever it drops
+    pub fn add_reserved_peer(&self, remote_id: PeerId, remote_addresses: Vec<Multiaddr>) {
+        self.manager.write().add_addresses(&remote_id, &remote_addresses, true);
+    }
+
+    /// Forgets the expired addresses of the peer, a connection to a peer that is not reserved is dropped
+    pub fn remove_peer(&self, remote_id: &PeerId, remote_addresses: &[Multiaddr]) {
+        self.manager.write().remove_addresses(remote_id, remote_addresses);
+        if !self.manager.read().is_reserved(remote_id) {
+            self.drop_peer(remote_id);
+        }
     }
 
     fn clone_handle(&self) -> TcpServerHandle {
@@ -289,37 +465,241 @@ impl TcpServer {
     }
 }
 
+/// Connects to the first reachable address of the peer, the session runs in its own task
+fn dial(
+    key: Keypair,
+    local_id: PeerId,
+    genesis: Hash,
+    handle: TcpServerHandle,
+    remote_id: PeerId,
+    remote_addresses: Vec<Multiaddr>,
+) {
+    tokio::spawn(async move {
+        let delay = rand::random::<u64>() % 100;
+        sleep(Duration::from_millis(delay)).await;
+        for mul_addr in &remote_addresses {
+            let socket_addr = match multiaddr_to_ipv4(mul_addr) {
+                Ok(socket_addr) => socket_addr,
+                Err(_) => continue,
+            };
+            let stream = match TcpStream::connect(socket_addr).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!("Failed to dial {} at {}, err: {}", remote_id.to_base58(), mul_addr, err);
+                    continue;
+                }
+            };
+            let (read, write) = match secure_outbound(&key, stream, &remote_id).await {
+                Ok(secured) => secured,
+                Err(err) => {
+                    warn!("Failed to secure the connection to {}, err: {}", remote_id.to_base58(), err);
+                    return;
+                }
+            };
+            let (write_tx, write_rx) = tokio::sync::mpsc::unbounded_channel();
+            let session = Session::new(remote_id, local_id, handle, BoundType::OutBound, genesis, write_tx);
+            session.run(read, write, write_rx).await;
+            return;
+        }
+    });
+}
+
+/// Sends every peer the queued transactions it does not know
+fn flush_transactions(peers: &Arc<RwLock<HashMap<PeerId, ConnectInfo>>>, tx_gossip: &Arc<RwLock<TxGossip>>) {
+    let peers = peers.read();
+    let gossip_peers = peers
+        .iter()
+        .filter(|(_, info)| info.accepts(&P2PMsgCode::Transaction))
+        .map(|(peer_id, _)| peer_id);
+    let batches = tx_gossip.write().take_batches(gossip_peers);
+    for (peer_id, transactions) in batches {
+        if let Some(info) = peers.get(&peer_id) {
+            let header = RawHeader::new(
+                P2PMsgCode::Transaction,
+                10,
+                chrono::Local::now().timestamp_millis() as u64,
+                Some(peer_id.to_bytes().to_vec()),
+            );
+            let _ = info.write_tx.send(RawMessage::new(header, Transactions(transactions).into_bytes()));
+        }
+    }
+}
+
+/// Sends the peer exchange message to the peer if it supports it
+fn send_peer_exchange(peers: &Arc<RwLock<HashMap<PeerId, ConnectInfo>>>, peer_id: &PeerId, msg: PeerExchange) {
+    if let Some(info) = peers.read().get(peer_id).filter(|info| info.accepts(&P2PMsgCode::PeerExchange)) {
+        let header = RawHeader::new(
+            P2PMsgCode::PeerExchange,
+            10,
+            chrono::Local::now().timestamp_millis() as u64,
+            Some(peer_id.to_bytes().to_vec()),
+        );
+        let _ = info.write_tx.send(RawMessage::new(header, msg.into_bytes()));
+    }
+}
+
+/// Asks the peer for its peers, with the addresses the node is reachable at
+fn request_peers(
+    peers: &Arc<RwLock<HashMap<PeerId, ConnectInfo>>>,
+    manager: &Arc<RwLock<PeerManager>>,
+    requests: &Arc<RwLock<LruCache<PeerId, bool>>>,
+    node_info: &(PeerId, Multiaddr),
+    peer_id: &PeerId,
+) {
+    let mut addresses = manager.read().external_addresses().to_vec();
+    // an unspecified listen address, e.g. 0.0.0.0, can not be dialed
+    let listen = multiaddr_to_ipv4(&node_info.1).map_or(false, |addr| !addr.ip().is_unspecified());
+    if addresses.is_empty() && listen {
+        addresses.push(node_info.1.clone());
+    }
+    let addresses = addresses.iter().map(|addr| addr.to_string()).collect();
+    requests.write().insert(*peer_id, true);
+    send_peer_exchange(peers, peer_id, PeerExchange::Request(addresses));
+}
+
+// the session forwards the messages before the handshake, only the ones negotiated with a connected peer are handled
+fn check_accepted(
+    peers: &Arc<RwLock<HashMap<PeerId, ConnectInfo>>>,
+    peer_id: &PeerId,
+    code: &P2PMsgCode,
+) -> Result<(), P2PError> {
+    match peers.read().get(peer_id) {
+        Some(info) if info.accepts(code) => Ok(()),
+        Some(_) => Err(P2PError::Violation(format!("{:?} is not negotiated", code))),
+        None => Err(P2PError::Violation(format!("{:?} before the handshake", code))),
+    }
+}
+
+/// Answers a request with the known peers, the peers of a reply are dialed if the quota allows.
+/// A reply that was not requested is a violation.
+fn handle_peer_exchange(
+    peers: &Arc<RwLock<HashMap<PeerId, ConnectInfo>>>,
+    manager: &Arc<RwLock<PeerManager>>,
+    exchanges: &Arc<RwLock<LruCache<PeerId, bool>>>,
+    requests: &Arc<RwLock<LruCache<PeerId, bool>>>,
+    node_info: &(PeerId, Multiaddr),
+    peer_id: &PeerId,
+    payload: &[u8],
+) -> Result<(), P2PError> {
+    match PeerExchange::decode(payload)? {
+        PeerExchange::Request(addresses) => {
+            let addresses = parse_addresses(&addresses)?;
+            {
+                let mut exchanges = exchanges.write();
+                if exchanges.get(peer_id).is_some() {
+                    return Ok(());
+                }
+                exchanges.insert(*peer_id, true);
+            }
+            if !addresses.is_empty() {
+                manager.write().add_addresses(peer_id, &addresses, false);
+            }
+            let known = manager
+                .read()
+                .known_peers(MAX_EXCHANGED_PEERS + 1)
+                .into_iter()
+                .filter(|(known_id, _)| known_id != peer_id)
+                .take(MAX_EXCHANGED_PEERS)
+                .map(|(known_id, addresses)| PeerAddress::new(&known_id, &addresses))
+                .collect();
+            send_peer_exchange(peers, peer_id, PeerExchange::Peers(known));
+        }
+        PeerExchange::Peers(known) => {
+            if requests.write().remove(peer_id).is_none() {
+                return Err(P2PError::Violation("unsolicited peers".to_string()));
+            }
+            let known = known.iter().map(PeerAddress::parse).collect::<Result<Vec<_>, _>>()?;
+            let mut manager = manager.write();
+            for (known_id, addresses) in known {
+                if known_id != node_info.0 && !addresses.is_empty() {
+                    manager.discover(&known_id, &addresses);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Counts a protocol violation of the peer, it is dropped and banned once it reaches `MAX_PROTOCOL_VIOLATIONS`
+fn record_violation(
+    peers: &Arc<RwLock<HashMap<PeerId, ConnectInfo>>>,
+    tx_gossip: &Arc<RwLock<TxGossip>>,
+    violations: &Arc<RwLock<LruCache<PeerId, u32>>>,
+    manager: &Arc<RwLock<PeerManager>>,
+    peer_id: &PeerId,
+    err: &P2PError,
+) {
+    metrics::P2P_VIOLATIONS.inc();
+    let count = {
+        let mut violations = violations.write();
+        let count = violations.get(peer_id).copied().unwrap_or(0) + 1;
+        violations.insert(*peer_id, count);
+        count
+    };
+    debug!("Protocol violation of {}, count: {}, err: {}", peer_id.to_base58(), count, err);
+    if count < MAX_PROTOCOL_VIOLATIONS {
+        return;
+    }
+    manager.write().ban(peer_id, BAN_DURATION);
+    // the session closes once its sender is dropped
+    if peers.write().remove(peer_id).is_some() {
+        warn!("Drop the peer {}, protocol violations: {}, last: {}", peer_id.to_base58(), count, err);
+        tx_gossip.write().remove_peer(peer_id);
+        observe_peers(&peers.read());
+    }
+}
+
+fn observe_peers(peers: &HashMap<PeerId, ConnectInfo>) {
+    let inbound = peers.values().filter(|info| matches!(info.bound_type, BoundType::InBound)).count();
+    metrics::P2P_PEERS
+        .with_label_values(&[metrics::bound_label(&BoundType::InBound)])
+        .set(inbound as i64);
+    metrics::P2P_PEERS
+        .with_label_values(&[metrics::bound_label(&BoundType::OutBound)])
+        .set((peers.len() - inbound) as i64);
+}
+
 fn handle_server_event(
     event: &ServerEvent,
     peers: &Arc<RwLock<HashMap<PeerId, ConnectInfo>>>,
     cache: &Arc<RwLock<LruCache<Hash, bool>>>,
+    tx_gossip: &Arc<RwLock<TxGossip>>,
     author_fn: &Arc<AuthorFn>,
     handles: &Arc<HandleMsgFn>,
     node_info: &(PeerId, Multiaddr),
-    genesis: &Hash,
 ) -> Result<PeerId, P2PError> {
     match event {
-        ServerEvent::Connected(_peer_id, bound_type, write_tx, raw_msg) => {
-            use std::borrow::Cow;
-            let handshake: Handshake = StorageValue::from_bytes(Cow::from(raw_msg.payload().to_vec()));
-            let peer_id = handshake.peer_id();
+        ServerEvent::Connected(remote_id, bound_type, write_tx, raw_msg) => {
+            let handshake: Handshake = serde_json::from_slice(raw_msg.payload()).map_err(|_| P2PError::HandShakeFailed)?;
+            let peer_id = handshake.peer_id()?;
+            // the session knows the id the noise handshake authenticated
+            if peer_id != *remote_id {
+                return Err(P2PError::Impersonation(peer_id.to_base58()));
+            }
             if peers.read().contains_key(&peer_id) {
                 return Err(P2PError::DumpConnected);
             }
-            if node_info.0 == handshake.peer_id() {
+            if node_info.0 == peer_id {
                 return Err(P2PError::HandShakeFailed);
             }
+            let protocol = handshake.protocol();
+            let capabilities = handshake.negotiate(&local_capabilities());
             if !author_fn(handshake) {
                 return Err(P2PError::DifferentGenesis);
             }
-            let connect_info = ConnectInfo::new(chrono::Utc::now(), *bound_type, write_tx.clone());
+            debug!("Connected to {}, protocol: {}, capabilities: {:?}", peer_id.to_base58(), protocol, capabilities);
+            let connect_info = ConnectInfo::new(chrono::Utc::now(), *bound_type, write_tx.clone(), protocol, capabilities);
             peers.write().insert(peer_id, connect_info);
+            observe_peers(&peers.read());
             Ok(peer_id)
         }
         ServerEvent::Disconnected(peer_id) => {
             peers.write().remove(peer_id);
+            tx_gossip.write().remove_peer(peer_id);
+            observe_peers(&peers.read());
             Ok(*peer_id)
         }
+        ServerEvent::Violation(_, reason) => Err(P2PError::Violation(reason.clone())),
         ServerEvent::Ping(peer_id) => {
             if let Some(info) = peers.write().get_mut(peer_id) {
                 info.connect_time = chrono::Utc::now();
@@ -327,6 +707,7 @@ fn handle_server_event(
             Ok(*peer_id)
         }
         ServerEvent::Message(peer_id, raw_msg) => {
+            check_accepted(peers, peer_id, &raw_msg.header().code)?;
             let hash: Hash = raw_msg.hash();
             let now = chrono::Local::now().timestamp_millis() as u64;
             if now < raw_msg.header().create_time {
@@ -339,9 +720,20 @@ fn handle_server_event(
                 }
                 cache_guard.insert(hash, true);
             }
-            let _ = handles(*peer_id, raw_msg.clone());
+            if raw_msg.header().code == P2PMsgCode::Transaction {
+                let transactions: Transactions = serde_json::from_slice(raw_msg.payload()).map_err(|_| P2PError::InvalidMessage)?;
+                if let Err(err) = tx_gossip.write().on_received(peer_id, raw_msg.payload().len(), &transactions.0) {
+                    warn!("Drop the transactions from {}, err: {}", peer_id.to_base58(), err);
+                    return Err(err);
+                }
+            }
+            match handles(*peer_id, raw_msg.clone()) {
+                Err(err @ P2PError::Violation(_)) => return Err(err),
+                Err(err) => debug!("Failed to handle the message from {}, err: {}", peer_id.to_base58(), err),
+                Ok(()) => {}
+            }
             Ok(*peer_id)
         }
-        ServerEvent::WithReply(inner, _) => handle_server_event(inner, peers, cache, author_fn, handles, node_info, genesis),
+        ServerEvent::WithReply(inner, _) => handle_server_event(inner, peers, cache, tx_gossip, author_fn, handles, node_info),
     }
 }
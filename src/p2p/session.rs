@@ -21,7 +21,8 @@ pub struct Session {
     bound_type: BoundType,
     handshaked: bool,
     genesis: Hash,
-    write_tx: SessionTx,
+    // handed to the server on handshake, the session closes once the server drops it
+    write_tx: Option<SessionTx>,
 }
 
 impl Session {
@@ -40,7 +41,7 @@ impl Session {
             bound_type,
             handshaked: false,
             genesis,
-            write_tx,
+            write_tx: Some(write_tx),
         }
     }
 
@@ -55,7 +56,7 @@ impl Session {
 
         // Send handshake
         let handshake =
-            Handshake::new("0.1.1".to_string(), self.local_id, self.genesis);
+            Handshake::new(env!("CARGO_PKG_VERSION").to_string(), self.local_id, self.genesis);
         let raw_message = RawMessage::new(
             Header::new(
                 P2PMsgCode::Handshake,
@@ -84,6 +85,10 @@ impl Session {
                         }
                         Some(Err(e)) => {
                             debug!("Session read error: {:?}", e);
+                            // the stream can not be framed any more, so the session closes
+                            if e.kind() == std::io::ErrorKind::InvalidData {
+                                self.server.try_send(ServerEvent::Violation(self.peer_id, e.to_string()));
+                            }
                             break;
                         }
                         None => break,
@@ -139,12 +144,13 @@ impl Session {
         );
         match msg.header().code {
             P2PMsgCode::Handshake => {
+                let write_tx = self.write_tx.take().ok_or(())?;
                 let result = self
                     .server
                     .send(ServerEvent::Connected(
                         self.peer_id,
                         self.bound_type,
-                        self.write_tx.clone(),
+                        write_tx,
                         msg,
                     ))
                     .await;
@@ -156,7 +162,12 @@ impl Session {
                     _ => return Err(()),
                 }
             }
-            P2PMsgCode::Block | P2PMsgCode::Consensus | P2PMsgCode::Sync => {
+            P2PMsgCode::Block
+            | P2PMsgCode::Consensus
+            | P2PMsgCode::Sync
+            | P2PMsgCode::Transaction
+            | P2PMsgCode::Evidence
+            | P2PMsgCode::PeerExchange => {
                 self.server.try_send(ServerEvent::Message(self.peer_id, msg));
             }
             P2PMsgCode::Ping => {
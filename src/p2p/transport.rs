@@ -0,0 +1,166 @@
+//! The encrypted session layer, every tcp connection runs a Noise XX handshake before the sessions
+//! exchange messages. Both sides prove the node key their `PeerId` is derived from, so a peer can not
+//! claim the id of another node.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use cryptocurrency_kit::ethkey::{public_to_address, Address, Public, Secret};
+use libp2p::core::upgrade::{InboundConnectionUpgrade, OutboundConnectionUpgrade};
+use libp2p::identity::{secp256k1, Keypair, PublicKey};
+use libp2p::{noise, PeerId};
+use tokio::io::{ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio_util::compat::{Compat, FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt};
+
+use crate::error::P2PError;
+
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const NOISE_PROTOCOL: &str = "/noise";
+
+pub type SecureStream = Compat<noise::Output<Compat<TcpStream>>>;
+pub type SecureRead = ReadHalf<SecureStream>;
+pub type SecureWrite = WriteHalf<SecureStream>;
+
+/// The node key of the p2p layer, it is the secp256k1 key of the validator
+pub fn node_keypair(secret: &Secret) -> Result<Keypair, String> {
+    let secret_key = secp256k1::SecretKey::try_from_bytes(secret[..].to_vec()).map_err(|err| err.to_string())?;
+    Ok(Keypair::from(secp256k1::Keypair::from(secret_key)))
+}
+
+/// The validator address of the node key, the secp256k1 key is inlined in the peer id
+pub fn peer_address(peer_id: &PeerId) -> Option<Address> {
+    let bytes = peer_id.to_bytes();
+    // an identity multihash: the code 0, the length and the protobuf encoded key
+    if bytes.len() < 2 || bytes[0] != 0 || bytes[1] as usize != bytes.len() - 2 {
+        return None;
+    }
+    let public = PublicKey::try_decode_protobuf(&bytes[2..]).ok()?.try_into_secp256k1().ok()?;
+    let uncompressed = public.to_bytes_uncompressed();
+    Some(public_to_address(&Public::from_slice(&uncompressed[1..])))
+}
+
+/// The node keys allowed to connect, anyone can if it is empty
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist(HashSet<PeerId>);
+
+impl Allowlist {
+    pub fn new(peers: Vec<PeerId>) -> Self {
+        Allowlist(peers.into_iter().collect())
+    }
+
+    pub fn allows(&self, peer_id: &PeerId) -> bool {
+        self.0.is_empty() || self.0.contains(peer_id)
+    }
+}
+
+/// Secures the accepted connection, returns the authenticated id of the remote
+pub async fn secure_inbound(
+    keypair: &Keypair,
+    stream: TcpStream,
+) -> Result<(PeerId, SecureRead, SecureWrite), P2PError> {
+    let config = noise::Config::new(keypair).map_err(|_| P2PError::HandShakeFailed)?;
+    let upgrade = config.upgrade_inbound(stream.compat(), NOISE_PROTOCOL);
+    let (remote_id, output) = tokio::time::timeout(HANDSHAKE_TIMEOUT, upgrade)
+        .await
+        .map_err(|_| P2PError::Timeout)?
+        .map_err(|_| P2PError::HandShakeFailed)?;
+    let (read, write) = tokio::io::split(output.compat());
+    Ok((remote_id, read, write))
+}
+
+/// Secures the dialed connection, the remote must prove the key of `expect`
+pub async fn secure_outbound(
+    keypair: &Keypair,
+    stream: TcpStream,
+    expect: &PeerId,
+) -> Result<(SecureRead, SecureWrite), P2PError> {
+    let config = noise::Config::new(keypair).map_err(|_| P2PError::HandShakeFailed)?;
+    let upgrade = config.upgrade_outbound(stream.compat(), NOISE_PROTOCOL);
+    let (remote_id, output) = tokio::time::timeout(HANDSHAKE_TIMEOUT, upgrade)
+        .await
+        .map_err(|_| P2PError::Timeout)?
+        .map_err(|_| P2PError::HandShakeFailed)?;
+    if remote_id != *expect {
+        return Err(P2PError::Impersonation(remote_id.to_base58()));
+    }
+    let (read, write) = tokio::io::split(output.compat());
+    Ok((read, write))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn keypair(seed: u8) -> Keypair {
+        node_keypair(&Secret::from([seed; 32])).unwrap()
+    }
+
+    #[tokio::test]
+    async fn t_secure_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (server_key, client_key) = (keypair(1), keypair(2));
+        let server_id = server_key.public().to_peer_id();
+        let client_id = client_key.public().to_peer_id();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (remote_id, mut read, _write) = secure_inbound(&server_key, stream).await.unwrap();
+            let mut buf = [0u8; 5];
+            read.read_exact(&mut buf).await.unwrap();
+            (remote_id, buf)
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (_read, mut write) = secure_outbound(&client_key, stream, &server_id).await.unwrap();
+        write.write_all(b"hello").await.unwrap();
+        write.flush().await.unwrap();
+
+        let (remote_id, buf) = server.await.unwrap();
+        assert_eq!(remote_id, client_id);
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[tokio::test]
+    async fn t_impersonation() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_key = keypair(3);
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let _ = secure_inbound(&server_key, stream).await;
+        });
+
+        // the dialer expects another node behind the address
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let expect = keypair(4).public().to_peer_id();
+        match secure_outbound(&keypair(5), stream, &expect).await {
+            Err(P2PError::Impersonation(_)) => {}
+            _ => panic!("the impersonation is not detected"),
+        }
+    }
+
+    #[test]
+    fn t_peer_address() {
+        use cryptocurrency_kit::ethkey::KeyPair;
+
+        let secret = Secret::from([8; 32]);
+        let peer_id = node_keypair(&secret).unwrap().public().to_peer_id();
+        assert_eq!(peer_address(&peer_id), Some(KeyPair::from_secret(secret).unwrap().address()));
+        // the ids of other key types are no validators
+        assert_eq!(peer_address(&PeerId::random()), None);
+    }
+
+    #[test]
+    fn t_allowlist() {
+        let allowed = keypair(6).public().to_peer_id();
+        let other = keypair(7).public().to_peer_id();
+        assert!(Allowlist::default().allows(&other));
+        let allowlist = Allowlist::new(vec![allowed]);
+        assert!(allowlist.allows(&allowed));
+        assert!(!allowlist.allows(&other));
+    }
+}
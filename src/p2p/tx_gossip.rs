@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use cryptocurrency_kit::crypto::{CryptoHash, Hash};
+use libp2p::PeerId;
+use lru_time_cache::LruCache;
+
+use crate::{error::P2PError, types::transaction::Transaction};
+
+/// The transaction hashes remembered for every peer, a peer is not sent the ones it knows
+pub const MAX_KNOWN_TXS: usize = 1 << 14;
+/// Max transactions of a gossip message
+pub const MAX_TX_BATCH: usize = 256;
+/// Max payload bytes of a gossip message
+pub const MAX_TX_BATCH_BYTES: usize = 1 << 20;
+/// Max transactions received from a peer in a `RATE_WINDOW`
+pub const MAX_TXS_PER_WINDOW: usize = 4096;
+pub const RATE_WINDOW: Duration = Duration::from_secs(1);
+/// The queued transactions are sent at least once every interval
+pub const TX_FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+struct PeerTxState {
+    known: LruCache<Hash, ()>,
+    window_start: Instant,
+    received: usize,
+}
+
+impl PeerTxState {
+    fn new() -> Self {
+        PeerTxState {
+            known: LruCache::with_capacity(MAX_KNOWN_TXS),
+            window_start: Instant::now(),
+            received: 0,
+        }
+    }
+}
+
+/// Batches the transactions to gossip and tracks what every peer knows
+pub struct TxGossip {
+    queued: Vec<Transaction>,
+    peers: HashMap<PeerId, PeerTxState>,
+}
+
+impl Default for TxGossip {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TxGossip {
+    pub fn new() -> Self {
+        TxGossip {
+            queued: vec![],
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Queues the transaction, returns true if a batch is full and should be flushed
+    pub fn queue(&mut self, transaction: Transaction) -> bool {
+        self.queued.push(transaction);
+        self.queued.len() >= MAX_TX_BATCH
+    }
+
+    /// Takes the queued transactions, every peer gets the ones it does not know in batches
+    pub fn take_batches<'a, I>(&mut self, peers: I) -> Vec<(PeerId, Vec<Transaction>)>
+    where
+        I: IntoIterator<Item = &'a PeerId>,
+    {
+        if self.queued.is_empty() {
+            return vec![];
+        }
+        let queued: Vec<(Hash, Transaction)> = self
+            .queued
+            .drain(..)
+            .map(|transaction| (transaction.hash(), transaction))
+            .collect();
+        let mut batches = vec![];
+        for peer_id in peers {
+            let state = self.peers.entry(*peer_id).or_insert_with(PeerTxState::new);
+            let mut unknown = vec![];
+            for (tx_hash, transaction) in &queued {
+                if state.known.get(tx_hash).is_none() {
+                    state.known.insert(*tx_hash, ());
+                    unknown.push(transaction.clone());
+                }
+            }
+            let mut unknown = unknown.into_iter().peekable();
+            while unknown.peek().is_some() {
+                batches.push((*peer_id, unknown.by_ref().take(MAX_TX_BATCH).collect()));
+            }
+        }
+        batches
+    }
+
+    /// Checks the limits of a received batch and marks the transactions known by the peer
+    pub fn on_received(&mut self, peer_id: &PeerId, payload_size: usize, transactions: &[Transaction]) -> Result<(), P2PError> {
+        if payload_size > MAX_TX_BATCH_BYTES {
+            return Err(P2PError::TooLarge(MAX_TX_BATCH_BYTES, payload_size));
+        }
+        if transactions.len() > MAX_TX_BATCH {
+            return Err(P2PError::TooLarge(MAX_TX_BATCH, transactions.len()));
+        }
+        if !transactions.iter().all(Transaction::is_signed) {
+            return Err(P2PError::InvalidMessage);
+        }
+        let state = self.peers.entry(*peer_id).or_insert_with(PeerTxState::new);
+        let now = Instant::now();
+        if now.duration_since(state.window_start) >= RATE_WINDOW {
+            state.window_start = now;
+            state.received = 0;
+        }
+        state.received += transactions.len();
+        if state.received > MAX_TXS_PER_WINDOW {
+            return Err(P2PError::RateLimited(peer_id.to_base58()));
+        }
+        transactions.iter().for_each(|transaction| {
+            state.known.insert(transaction.hash(), ());
+        });
+        Ok(())
+    }
+
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptocurrency_kit::ethkey::{Address, Generator, Random};
+
+    fn transactions(n: u64) -> Vec<Transaction> {
+        let keypair = Random.generate().unwrap();
+        (0..n)
+            .map(|nonce| {
+                let mut tx = Transaction::new(nonce, Address::from(100), 10, 21_000, 1, vec![]);
+                tx.sign(1, keypair.secret());
+                tx
+            })
+            .collect()
+    }
+
+    #[test]
+    fn t_tx_gossip() {
+        let (sender, other) = (PeerId::random(), PeerId::random());
+        let mut gossip = TxGossip::new();
+        let txs = transactions(MAX_TX_BATCH as u64 + 1);
+
+        // the sender knows the ones it sent
+        assert!(gossip.on_received(&sender, 0, &txs[..2]).is_ok());
+        assert!(txs.iter().take(MAX_TX_BATCH - 1).all(|tx| !gossip.queue(tx.clone())));
+        assert!(gossip.queue(txs[MAX_TX_BATCH - 1].clone()));
+        gossip.queue(txs[MAX_TX_BATCH].clone());
+
+        let batches = gossip.take_batches(&[sender, other]);
+        let sizes: Vec<(PeerId, usize)> = batches.iter().map(|(peer_id, txs)| (*peer_id, txs.len())).collect();
+        assert_eq!(sizes, vec![(sender, MAX_TX_BATCH - 1), (other, MAX_TX_BATCH), (other, 1)]);
+
+        // nothing is sent twice
+        gossip.queue(txs[0].clone());
+        assert!(gossip.take_batches(&[sender, other]).is_empty());
+    }
+
+    #[test]
+    fn t_tx_gossip_limits() {
+        let peer_id = PeerId::random();
+        let mut gossip = TxGossip::new();
+        assert!(gossip.on_received(&peer_id, MAX_TX_BATCH_BYTES + 1, &[]).is_err());
+        assert!(gossip.on_received(&peer_id, 0, &transactions(MAX_TX_BATCH as u64 + 1)).is_err());
+        let unsigned = Transaction::new(0, Address::from(100), 10, 21_000, 1, vec![]);
+        assert!(gossip.on_received(&peer_id, 0, &[unsigned]).is_err());
+
+        let batch = transactions(MAX_TX_BATCH as u64);
+        (0..MAX_TXS_PER_WINDOW / MAX_TX_BATCH).for_each(|_| assert!(gossip.on_received(&peer_id, 0, &batch).is_ok()));
+        assert!(gossip.on_received(&peer_id, 0, &batch[..1]).is_err());
+    }
+}
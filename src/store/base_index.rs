@@ -5,7 +5,6 @@ use cryptocurrency_kit::crypto::{hash, CryptoHash, Hash};
 use cryptocurrency_kit::storage::keys::StorageKey;
 use cryptocurrency_kit::storage::values::StorageValue;
 use kvdb::{DBTransaction, KeyValueDB};
-use kvdb_rocksdb::Database;
 
 
 const COL: u32 = 0;
@@ -50,7 +49,7 @@ pub struct BaseIndex {
     name: String,
     index_id: Option<Vec<u8>>,
     index_type: IndexType,
-    view: Arc<Database>,
+    view: Arc<dyn KeyValueDB>,
 }
 
 pub struct BaseIndexIter<'a, K, V> {
@@ -87,7 +86,7 @@ impl std::fmt::Debug for BaseIndex {
 }
 
 impl BaseIndex {
-    pub fn new<S: AsRef<str>>(index_name: S, index_type: IndexType, view: Arc<Database>) -> Self {
+    pub fn new<S: AsRef<str>>(index_name: S, index_type: IndexType, view: Arc<dyn KeyValueDB>) -> Self {
         Self {
             name: index_name.as_ref().to_string(),
             index_id: None,
@@ -112,8 +111,8 @@ impl BaseIndex {
         prefix_key
     }
 
-    pub fn snapshot(&self) -> &Database {
-        &self.view
+    pub fn snapshot(&self) -> &dyn KeyValueDB {
+        self.view.as_ref()
     }
 
     pub fn get<K, V>(&self, key: &K) -> Option<V>
@@ -186,8 +185,8 @@ impl BaseIndex {
     }
 
     /////////////////////////////
-    pub fn fork(&mut self) -> &Database {
-        &self.view
+    pub fn fork(&mut self) -> &dyn KeyValueDB {
+        self.view.as_ref()
     }
 
     pub fn transaction(&self) -> DBTransaction {
@@ -209,6 +208,22 @@ impl BaseIndex {
         self.view.write(tx).unwrap();
     }
 
+    /// Adds the put to the batch instead of writing it at once
+    pub fn put_in<K, V>(&self, batch: &mut DBTransaction, key: &K, value: V)
+        where
+            K: StorageKey + ?Sized,
+            V: StorageValue,
+    {
+        batch.put_vec(Some(COL), &self.prefix_key(key), value.into_bytes().to_vec());
+    }
+
+    pub fn remove_in<K>(&self, batch: &mut DBTransaction, key: &K)
+        where
+            K: StorageKey + ?Sized,
+    {
+        batch.delete(Some(COL), &self.prefix_key(key));
+    }
+
     pub fn remove<K>(&mut self, key: &K)
         where
             K: StorageKey + ?Sized,
@@ -274,6 +289,7 @@ pub(crate) fn print_bytes(base_prefix_len: usize, idx: &[u8], key: &[u8], value:
 #[cfg(test)]
 mod tests {
     use super::*;
+    use kvdb_rocksdb::Database;
     use cryptocurrency_kit::types::Zero;
     use std::io::{self, Write};
 
@@ -4,7 +4,7 @@ use std::sync::Arc;
 use cryptocurrency_kit::crypto::Hash;
 use cryptocurrency_kit::storage::values::StorageValue;
 use cryptocurrency_kit::types::Zero;
-use kvdb_rocksdb::Database;
+use kvdb::{DBTransaction, KeyValueDB};
 
 use super::base_index::{BaseIndex, IndexType};
 
@@ -18,7 +18,7 @@ impl<V> Entry<V>
 where
     V: StorageValue,
 {
-    pub fn new<S: AsRef<str>>(index_name: S, view: Arc<Database>) -> Self {
+    pub fn new<S: AsRef<str>>(index_name: S, view: Arc<dyn KeyValueDB>) -> Self {
         Entry {
             base: BaseIndex::new(index_name, IndexType::Entry, view),
             _v: PhantomData,
@@ -48,6 +48,11 @@ where
         self.base.remove(&Zero)
     }
 
+    /// Adds the set to the batch, see `Schema::write_batch`
+    pub fn set_in(&self, batch: &mut DBTransaction, value: V) {
+        self.base.put_in(batch, &Zero, value)
+    }
+
     pub fn take(&mut self) -> Option<V> {
         let value: Option<V> = self.get();
         if value.is_some() {
@@ -68,6 +73,7 @@ mod tests {
     use std::io::{self, Write};
 
     use super::*;
+    use kvdb_rocksdb::Database;
     use crate::common::random_dir;
     use cryptocurrency_kit::crypto::EMPTY_HASH;
 
@@ -3,7 +3,7 @@ use std::{cell::Cell, marker::PhantomData};
 
 use cryptocurrency_kit::storage::values::StorageValue;
 use cryptocurrency_kit::types::Zero;
-use kvdb_rocksdb::Database;
+use kvdb::{DBTransaction, KeyValueDB};
 
 use super::base_index::{BaseIndex, BaseIndexIter, IndexType};
 
@@ -31,7 +31,7 @@ impl<V> ListIndex<V>
 where
     V: StorageValue,
 {
-    pub fn new<S: AsRef<str>>(index_name: S, view: Arc<Database>) -> Self {
+    pub fn new<S: AsRef<str>>(index_name: S, view: Arc<dyn KeyValueDB>) -> Self {
         Self {
             base: BaseIndex::new(index_name, IndexType::List, view),
             length: Cell::new(None),
@@ -93,6 +93,14 @@ where
         self.set_len(len + 1)
     }
 
+    /// Adds the push to the batch, the length is read again once the batch is written
+    pub fn push_in(&self, batch: &mut DBTransaction, value: V) {
+        let len = self.len();
+        self.base.put_in(batch, &len, value);
+        self.base.put_in(batch, &Zero, len + 1);
+        self.length.set(None);
+    }
+
     pub fn pop(&mut self) -> Option<V> {
         match self.len() {
             0 => None,
@@ -168,6 +176,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use kvdb_rocksdb::Database;
     use std::io::{self, Write};
 
     fn list_index_methods(list_index: &mut ListIndex<i32>) {
@@ -3,7 +3,7 @@ use std::{borrow::Borrow, marker::PhantomData};
 
 use cryptocurrency_kit::storage::{keys::StorageKey, values::StorageValue};
 use cryptocurrency_kit::types::Zero;
-use kvdb_rocksdb::Database;
+use kvdb::{DBTransaction, KeyValueDB};
 
 use super::base_index::{BaseIndex, BaseIndexIter, IndexType};
 
@@ -44,7 +44,7 @@ where
     K: StorageKey,
     V: StorageValue,
 {
-    pub fn new<S: AsRef<str>>(index_name: S, view: Arc<Database>) -> Self {
+    pub fn new<S: AsRef<str>>(index_name: S, view: Arc<dyn KeyValueDB>) -> Self {
         Self {
             base: BaseIndex::new(index_name, IndexType::Map, view),
             _k: PhantomData,
@@ -117,6 +117,19 @@ where
     pub fn clear(&mut self) {
         self.base.clear()
     }
+
+    /// Adds the put to the batch, see `Schema::write_batch`
+    pub fn put_in(&self, batch: &mut DBTransaction, key: &K, value: V) {
+        self.base.put_in(batch, key, value)
+    }
+
+    pub fn remove_in<Q>(&self, batch: &mut DBTransaction, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: StorageKey + ?Sized,
+    {
+        self.base.remove_in(batch, key)
+    }
 }
 
 impl<'a, K> Iterator for MapIndexKeys<'a, K>
@@ -145,6 +158,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use kvdb_rocksdb::Database;
     use crate::common::random_dir;
     use std::io::{self, Write};
 
@@ -183,6 +197,22 @@ mod tests {
         });
     }
 
+    #[test]
+    fn batch() {
+        let db = Arc::new(newdb());
+        let mut index: MapIndex<String, i32> = MapIndex::new(IDX_NAME, db.clone());
+        index.put(&"a".to_owned(), 1);
+        let mut batch = db.transaction();
+        index.remove_in(&mut batch, "a");
+        index.put_in(&mut batch, &"b".to_owned(), 2);
+        // nothing is written before the batch
+        assert!(index.contains("a"));
+        assert!(!index.contains("b"));
+        db.write(batch).unwrap();
+        assert!(!index.contains("a"));
+        assert_eq!(index.get("b"), Some(2));
+    }
+
     #[test]
     fn value_iter() {
         let db = Arc::new(newdb());
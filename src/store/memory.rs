@@ -0,0 +1,114 @@
+//! An in-memory key-value database, it backs the schema of the simulated nodes.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use kvdb::{DBOp, DBTransaction, DBValue, KeyValueDB};
+use parking_lot::RwLock;
+
+type Column = BTreeMap<Vec<u8>, DBValue>;
+
+/// The writes are applied at once, so there is nothing buffered to flush
+#[derive(Default)]
+pub struct MemoryDatabase {
+    columns: RwLock<HashMap<Option<u32>, Column>>,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        MemoryDatabase::default()
+    }
+
+    fn collect<F>(&self, col: Option<u32>, from: &[u8], filter: F) -> Vec<(Box<[u8]>, Box<[u8]>)>
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        self.columns
+            .read()
+            .get(&col)
+            .map(|column| {
+                column
+                    .range(from.to_vec()..)
+                    .filter(|(key, _)| filter(key))
+                    .map(|(key, value)| (key.clone().into_boxed_slice(), value.to_vec().into_boxed_slice()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl KeyValueDB for MemoryDatabase {
+    fn get(&self, col: Option<u32>, key: &[u8]) -> io::Result<Option<DBValue>> {
+        Ok(self.columns.read().get(&col).and_then(|column| column.get(key).cloned()))
+    }
+
+    fn get_by_prefix(&self, col: Option<u32>, prefix: &[u8]) -> Option<Box<[u8]>> {
+        self.collect(col, prefix, |key| key.starts_with(prefix))
+            .into_iter()
+            .next()
+            .map(|(_, value)| value)
+    }
+
+    fn write_buffered(&self, transaction: DBTransaction) {
+        let mut columns = self.columns.write();
+        for op in transaction.ops {
+            match op {
+                DBOp::Insert { col, key, value } => {
+                    columns.entry(col).or_default().insert(key.to_vec(), value);
+                }
+                DBOp::Delete { col, key } => {
+                    if let Some(column) = columns.get_mut(&col) {
+                        column.remove(&*key);
+                    }
+                }
+            }
+        }
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn iter<'a>(&'a self, col: Option<u32>) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        Box::new(self.collect(col, &[], |_| true).into_iter())
+    }
+
+    // like rocksdb, seeks to the prefix and iterates to the end
+    fn iter_from_prefix<'a>(
+        &'a self,
+        col: Option<u32>,
+        prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        Box::new(self.collect(col, prefix, |_| true).into_iter())
+    }
+
+    fn restore(&self, _new_db: &str) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other, "the in-memory database can not be restored"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_memory_database() {
+        let db = MemoryDatabase::new();
+        let mut transaction = db.transaction();
+        transaction.put(Some(0), b"a1", b"1");
+        transaction.put(Some(0), b"b1", b"2");
+        transaction.put(Some(0), b"b2", b"3");
+        db.write(transaction).unwrap();
+        assert_eq!(db.get(Some(0), b"b1").unwrap().unwrap().to_vec(), b"2".to_vec());
+        assert!(db.get(None, b"b1").unwrap().is_none());
+
+        let keys: Vec<Box<[u8]>> = db.iter_from_prefix(Some(0), b"b").map(|(key, _)| key).collect();
+        assert_eq!(keys, vec![b"b1".to_vec().into_boxed_slice(), b"b2".to_vec().into_boxed_slice()]);
+        assert_eq!(db.get_by_prefix(Some(0), b"b2").unwrap().to_vec(), b"3".to_vec());
+
+        let mut transaction = db.transaction();
+        transaction.delete(Some(0), b"b1");
+        db.write(transaction).unwrap();
+        assert_eq!(db.iter(Some(0)).count(), 2);
+    }
+}
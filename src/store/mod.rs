@@ -2,6 +2,9 @@ mod base_index;
 mod entry;
 mod list_index;
 mod map_index;
+#[cfg(any(test, feature = "sim"))]
+pub mod memory;
+pub mod proof_map_index;
 mod iter;
 pub mod schema;
 mod types;
\ No newline at end of file
@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::{borrow::Borrow, marker::PhantomData};
+
+use cryptocurrency_kit::crypto::{hash, Hash, EMPTY_HASH, HASH_SIZE};
+use cryptocurrency_kit::storage::{keys::StorageKey, values::StorageValue};
+use kvdb::{DBTransaction, KeyValueDB};
+
+use super::base_index::{BaseIndex, BaseIndexIter, IndexType};
+
+/// Depth of the sparse merkle tree, one level for every bit of the key path
+pub const TREE_DEPTH: usize = HASH_SIZE * 8;
+
+/// data format
+/// values: index_name + key => value
+/// nodes: "nodes." + index_name + hash(depth, path prefix) => node hash
+///
+/// The map is a sparse merkle tree of `TREE_DEPTH` levels, a key is placed at the leaf of
+/// path `hash(key)`. The hash of an empty subtree is `EMPTY_HASH` and is not stored,
+/// so the root of an empty map is `EMPTY_HASH`.
+pub struct ProofMapIndex<K, V> {
+    base: BaseIndex,
+    nodes: BaseIndex,
+    _k: PhantomData<K>,
+    _v: PhantomData<V>,
+}
+
+pub struct ProofMapIndexIter<'a, K, V> {
+    base_iter: BaseIndexIter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for ProofMapIndexIter<'a, K, V>
+where
+    K: StorageKey,
+    V: StorageValue,
+{
+    type Item = (K::Owned, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.base_iter.next()
+    }
+}
+
+/// The siblings along the path of a key, it proves the key is in the map with the value or not
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MapProof {
+    pub path: Hash,
+    /// the hash of the value, none if the key is absent
+    pub value_hash: Option<Hash>,
+    /// bit `i` is set if the sibling at depth `TREE_DEPTH - i` is not empty
+    pub bitmap: Vec<u8>,
+    /// the non-empty siblings from the leaf to the root
+    pub siblings: Vec<Hash>,
+}
+
+impl MapProof {
+    /// Returns the root hash implied by the proof, none if the proof is malformed
+    pub fn compute_root(&self) -> Option<Hash> {
+        if self.bitmap.len() != HASH_SIZE {
+            return None;
+        }
+        let mut current = self.value_hash.map_or(EMPTY_HASH, |value_hash| leaf_hash(&self.path, &value_hash));
+        let mut siblings = self.siblings.iter();
+        for depth in (0..TREE_DEPTH).rev() {
+            let sibling = if bit(&self.bitmap, TREE_DEPTH - 1 - depth) {
+                *siblings.next()?
+            } else {
+                EMPTY_HASH
+            };
+            current = if bit(self.path.as_ref(), depth) {
+                node_hash(&sibling, &current)
+            } else {
+                node_hash(&current, &sibling)
+            };
+        }
+        if siblings.next().is_some() {
+            return None;
+        }
+        Some(current)
+    }
+
+    /// Checks the key maps to the value(inclusion) or is absent(non-inclusion) under the root
+    pub fn verify<K, V>(&self, root: &Hash, key: &K, value: Option<V>) -> bool
+    where
+        K: StorageKey + ?Sized,
+        V: StorageValue,
+    {
+        if self.path != key_path(key) || self.value_hash != value.map(|value| hash(value.into_bytes())) {
+            return false;
+        }
+        self.compute_root().map_or(false, |computed| computed == *root)
+    }
+}
+
+/// The path of the key in the tree
+pub fn key_path<K: StorageKey + ?Sized>(key: &K) -> Hash {
+    let mut buffer = vec![0; key.size()];
+    key.write(&mut buffer);
+    hash(&buffer)
+}
+
+fn leaf_hash(path: &Hash, value_hash: &Hash) -> Hash {
+    let mut buffer = Vec::with_capacity(HASH_SIZE * 2);
+    buffer.extend_from_slice(path.as_ref());
+    buffer.extend_from_slice(value_hash.as_ref());
+    hash(&buffer)
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    if *left == EMPTY_HASH && *right == EMPTY_HASH {
+        return EMPTY_HASH;
+    }
+    let mut buffer = Vec::with_capacity(HASH_SIZE * 2);
+    buffer.extend_from_slice(left.as_ref());
+    buffer.extend_from_slice(right.as_ref());
+    hash(&buffer)
+}
+
+fn bit(bytes: &[u8], index: usize) -> bool {
+    bytes[index / 8] & (0x80 >> (index % 8)) != 0
+}
+
+fn set_bit(bytes: &mut [u8], index: usize) {
+    bytes[index / 8] |= 0x80 >> (index % 8);
+}
+
+/// The storage key of the node at the depth on the path, the bits below the depth are dropped
+fn node_key(depth: usize, path: &Hash) -> Hash {
+    let mut prefix = [0_u8; HASH_SIZE];
+    (0..depth).filter(|index| bit(path.as_ref(), *index)).for_each(|index| set_bit(&mut prefix, index));
+    let mut buffer = Vec::with_capacity(HASH_SIZE + 2);
+    buffer.extend_from_slice(&(depth as u16).to_be_bytes());
+    buffer.extend_from_slice(&prefix);
+    hash(&buffer)
+}
+
+/// The path of the sibling of the node at the depth
+fn sibling_path(depth: usize, path: &Hash) -> Hash {
+    let mut sibling = path.as_ref().to_vec();
+    sibling[(depth - 1) / 8] ^= 0x80 >> ((depth - 1) % 8);
+    Hash::new(&sibling)
+}
+
+impl<K, V> ProofMapIndex<K, V>
+where
+    K: StorageKey,
+    V: StorageValue,
+{
+    pub fn new<S: AsRef<str>>(index_name: S, view: Arc<dyn KeyValueDB>) -> Self {
+        let nodes_name = format!("nodes.{}", index_name.as_ref());
+        Self {
+            base: BaseIndex::new(index_name, IndexType::ProofMap, view.clone()),
+            nodes: BaseIndex::new(nodes_name, IndexType::ProofMap, view),
+            _k: PhantomData,
+            _v: PhantomData,
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: StorageKey + ?Sized,
+    {
+        self.base.get(key)
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: StorageKey + ?Sized,
+    {
+        self.base.contains(key)
+    }
+
+    pub fn iter(&self) -> ProofMapIndexIter<'_, K, V> {
+        ProofMapIndexIter {
+            base_iter: self.base.iter(&()),
+        }
+    }
+
+    pub fn root_hash(&self) -> Hash {
+        self.node(&HashMap::new(), 0, &EMPTY_HASH)
+    }
+
+    /// Returns the root hash after the changes without writing them, `None` removes the key
+    pub fn root_hash_with<'a, I>(&self, changes: I) -> Hash
+    where
+        I: IntoIterator<Item = (&'a K, Option<&'a V>)>,
+        K: 'a,
+        V: Clone + 'a,
+    {
+        let mut dirty = HashMap::new();
+        for (key, value) in changes {
+            self.update_path(&mut dirty, &key_path(key), value.cloned());
+        }
+        self.node(&dirty, 0, &EMPTY_HASH)
+    }
+
+    pub fn get_proof(&self, key: &K) -> MapProof {
+        let path = key_path(key);
+        let mut bitmap = vec![0_u8; HASH_SIZE];
+        let mut siblings = vec![];
+        let empty = HashMap::new();
+        for depth in (1..=TREE_DEPTH).rev() {
+            let sibling = self.node(&empty, depth, &sibling_path(depth, &path));
+            if sibling != EMPTY_HASH {
+                set_bit(&mut bitmap, TREE_DEPTH - depth);
+                siblings.push(sibling);
+            }
+        }
+        MapProof {
+            path,
+            value_hash: self.get(key).map(|value| hash(value.into_bytes())),
+            bitmap,
+            siblings,
+        }
+    }
+
+    fn node(&self, dirty: &HashMap<Hash, Hash>, depth: usize, path: &Hash) -> Hash {
+        let key = node_key(depth, path);
+        dirty
+            .get(&key)
+            .cloned()
+            .or_else(|| self.nodes.get(&key))
+            .unwrap_or(EMPTY_HASH)
+    }
+
+    // recomputes the nodes from the leaf of the path to the root
+    fn update_path(&self, dirty: &mut HashMap<Hash, Hash>, path: &Hash, value: Option<V>) {
+        let mut current = value.map_or(EMPTY_HASH, |value| leaf_hash(path, &hash(value.into_bytes())));
+        for depth in (1..=TREE_DEPTH).rev() {
+            dirty.insert(node_key(depth, path), current);
+            let sibling = self.node(dirty, depth, &sibling_path(depth, path));
+            current = if bit(path.as_ref(), depth - 1) {
+                node_hash(&sibling, &current)
+            } else {
+                node_hash(&current, &sibling)
+            };
+        }
+        dirty.insert(node_key(0, path), current);
+    }
+
+    fn write_nodes(&self, batch: &mut DBTransaction, dirty: HashMap<Hash, Hash>) {
+        for (key, node) in dirty {
+            if node == EMPTY_HASH {
+                self.nodes.remove_in(batch, &key);
+            } else {
+                self.nodes.put_in(batch, &key, node);
+            }
+        }
+    }
+}
+
+impl<K, V> ProofMapIndex<K, V>
+where
+    K: StorageKey,
+    V: StorageValue + Clone,
+{
+    /// Adds the changed values and the nodes along their paths to the batch, `None` removes the key.
+    /// A node shared by the paths is written once.
+    pub fn apply_in<'a, I>(&self, batch: &mut DBTransaction, changes: I)
+    where
+        I: IntoIterator<Item = (&'a K, Option<&'a V>)>,
+        K: 'a,
+        V: 'a,
+    {
+        let mut dirty = HashMap::new();
+        for (key, value) in changes {
+            self.update_path(&mut dirty, &key_path(key), value.cloned());
+            match value {
+                Some(value) => self.base.put_in(batch, key, value.clone()),
+                None => self.base.remove_in(batch, key),
+            }
+        }
+        self.write_nodes(batch, dirty);
+    }
+
+    pub fn put(&mut self, key: &K, value: V) {
+        let mut batch = self.base.transaction();
+        self.apply_in(&mut batch, vec![(key, Some(&value))]);
+        self.base.put_transaction(batch);
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        let mut batch = self.base.transaction();
+        self.apply_in(&mut batch, vec![(key, None)]);
+        self.base.put_transaction(batch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kvdb_rocksdb::Database;
+    use crate::common::random_dir;
+
+    const IDX_NAME: &'static str = "idx_name_";
+
+    fn newdb() -> Database {
+        Database::open(&kvdb_rocksdb::DatabaseConfig::with_columns(Some(1)), &random_dir()).unwrap()
+    }
+
+    #[test]
+    fn t_root_hash() {
+        let db = Arc::new(newdb());
+        let mut index: ProofMapIndex<u64, String> = ProofMapIndex::new(IDX_NAME, db.clone());
+        assert_eq!(index.root_hash(), EMPTY_HASH);
+
+        let (one, two) = ("1".to_string(), "2".to_string());
+        let expect = index.root_hash_with(vec![(&1, Some(&one)), (&2, Some(&two))]);
+        index.put(&1, one.clone());
+        index.put(&2, two.clone());
+        assert_eq!(index.root_hash(), expect);
+        assert_eq!(index.iter().count(), 2);
+
+        // the root does not depend on the insertion order
+        let mut other: ProofMapIndex<u64, String> = ProofMapIndex::new("other_", db.clone());
+        other.put(&2, two.clone());
+        other.put(&1, one.clone());
+        assert_eq!(other.root_hash(), expect);
+
+        // the changes of a batch are written together
+        let batched: ProofMapIndex<u64, String> = ProofMapIndex::new("batched_", db.clone());
+        let mut batch = db.transaction();
+        batched.apply_in(&mut batch, vec![(&1, Some(&one)), (&2, Some(&two))]);
+        assert_eq!(batched.root_hash(), EMPTY_HASH);
+        db.write(batch).unwrap();
+        assert_eq!(batched.root_hash(), expect);
+        assert_eq!(batched.get(&2), Some(two.clone()));
+
+        index.remove(&2);
+        index.remove(&1);
+        assert_eq!(index.root_hash(), EMPTY_HASH);
+    }
+
+    #[test]
+    fn t_proof() {
+        let db = Arc::new(newdb());
+        let mut index: ProofMapIndex<u64, String> = ProofMapIndex::new(IDX_NAME, db);
+        let empty_proof = index.get_proof(&1);
+        assert!(empty_proof.verify::<u64, String>(&EMPTY_HASH, &1, None));
+
+        (0..20_u64).for_each(|idx| index.put(&idx, idx.to_string()));
+        let root = index.root_hash();
+
+        // inclusion
+        let proof = index.get_proof(&7);
+        assert!(proof.verify(&root, &7_u64, Some("7".to_string())));
+        assert!(!proof.verify(&root, &7_u64, Some("8".to_string())));
+        assert!(!proof.verify(&root, &8_u64, Some("7".to_string())));
+        assert!(!proof.verify::<u64, String>(&root, &7, None));
+
+        // non-inclusion
+        let proof = index.get_proof(&100);
+        assert!(proof.verify::<u64, String>(&root, &100, None));
+        assert!(!proof.verify(&root, &100_u64, Some("100".to_string())));
+        assert!(!empty_proof.verify::<u64, String>(&root, &1, None));
+
+        // tampered
+        let mut proof = index.get_proof(&7);
+        proof.siblings.pop();
+        assert!(proof.compute_root().is_none());
+    }
+}
@@ -1,6 +1,11 @@
 use std::sync::Arc;
 
-use cryptocurrency_kit::crypto::Hash;
+use std::borrow::Cow;
+
+use cryptocurrency_kit::crypto::{hash, CryptoHash, Hash};
+use cryptocurrency_kit::ethkey::Address;
+use cryptocurrency_kit::storage::values::StorageValue;
+use kvdb::{DBTransaction, KeyValueDB};
 use kvdb_rocksdb::{Database, DatabaseConfig};
 
 /// Database config with 1 column (schema uses COL=0).
@@ -11,9 +16,15 @@ pub fn database_config() -> DatabaseConfig {
 use super::entry::Entry;
 use super::list_index::ListIndex;
 use super::map_index::MapIndex;
+use super::proof_map_index::ProofMapIndex;
 use crate::{
+    consensus::dpos::types::{Ballot, Delegate},
+    consensus::pbft::core::wal::ConsensusWal,
+    consensus::raft::types::{Entry as RaftEntry, HardState},
     types::block::{Block, Header},
-    types::{ValidatorArray, HashesEntry, Height, transaction::Transaction},
+    p2p::peer_manager::PeerRecord,
+    types::evidence::{Evidence, Evidences},
+    types::{ValidatorArray, HashesEntry, Height, account::Account, transaction::Transaction},
 };
 
 macro_rules! define_name {
@@ -36,21 +47,57 @@ define_name!(
     PRECOMMITS => "precommits";
     CONFIGS => "configs";
     CONSENSUS_MESSAGE_CACHE => "consensus_message_cache";
+    TRANSACTION_LOCATIONS => "transaction_locations";
     VALIDATORS => "validators";
+    ACCOUNTS => "accounts";
+    RAFT_HARD_STATE => "raft_hard_state";
+    RAFT_LOG => "raft_log";
+    DPOS_DELEGATES => "dpos_delegates";
+    DPOS_BALLOTS => "dpos_ballots";
+    DPOS_ACTIVE_DELEGATES => "dpos_active_delegates";
+    DPOS_APPLIED_HEIGHT => "dpos_applied_height";
+    EVIDENCES => "evidences";
+    COMMITTED_EVIDENCES => "committed_evidences";
+    BLOCK_EVIDENCES => "block_evidences";
+    PEERS => "peers";
 );
 
-struct TxLocation {
-    block_height: Height,
-    position_in_block: u64,
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TxLocation {
+    pub block_height: Height,
+    pub position_in_block: u64,
 }
 
+implement_cryptohash_traits! {TxLocation}
+implement_storagevalue_traits! {TxLocation}
+
 pub struct Schema {
-    db: Arc<Database>,
+    db: Arc<dyn KeyValueDB>,
+    // the rocksdb behind `db`, none for the in-memory database
+    rocksdb: Option<Arc<Database>>,
 }
 
 impl Schema {
     pub fn new(db: Arc<Database>) -> Self {
-        Schema { db }
+        Schema { db: db.clone(), rocksdb: Some(db) }
+    }
+
+    #[cfg(any(test, feature = "sim"))]
+    pub fn new_in_memory() -> Self {
+        Schema { db: Arc::new(super::memory::MemoryDatabase::new()), rocksdb: None }
+    }
+
+    pub fn database(&self) -> Option<&Arc<Database>> {
+        self.rocksdb.as_ref()
+    }
+
+    /// A batch of the writes to the indexes(`put_in`, `remove_in`), they are written at once
+    pub fn batch(&self) -> DBTransaction {
+        self.db.transaction()
+    }
+
+    pub fn write_batch(&self, batch: DBTransaction) {
+        self.db.write(batch).unwrap();
     }
 
     pub fn transaction(&self) -> MapIndex<Hash, Transaction> {
@@ -61,6 +108,11 @@ impl Schema {
         MapIndex::new(TRANSACTIONS_HASH, self.db.clone())
     }
 
+    /// transaction hash => the block height and position of it
+    pub fn transaction_locations(&self) -> MapIndex<Hash, TxLocation> {
+        MapIndex::new(TRANSACTION_LOCATIONS, self.db.clone())
+    }
+
     pub fn blocks(&self) -> MapIndex<Hash, Block> {
         MapIndex::new(BLOCKS, self.db.clone())
     }
@@ -85,8 +137,65 @@ impl Schema {
         Block::new2(header, vec![])
     }
 
-    pub fn validators(&self) -> Entry<ValidatorArray> {
-        Entry::new(VALIDATORS, self.db.clone())
+    /// height => the validators who seal the block of the height
+    pub fn validators(&self) -> MapIndex<Height, ValidatorArray> {
+        MapIndex::new(VALIDATORS, self.db.clone())
+    }
+
+    /// address => account, the state after the last block, its root hash is `Header::root`
+    pub fn accounts(&self) -> ProofMapIndex<Address, Account> {
+        ProofMapIndex::new(ACCOUNTS, self.db.clone())
+    }
+
+    pub fn consensus_wal(&self) -> Entry<ConsensusWal> {
+        Entry::new(CONSENSUS_MESSAGE_CACHE, self.db.clone())
+    }
+
+    pub fn raft_hard_state(&self) -> Entry<HardState> {
+        Entry::new(RAFT_HARD_STATE, self.db.clone())
+    }
+
+    /// height => uncommitted raft entry
+    pub fn raft_log(&self) -> MapIndex<Height, RaftEntry> {
+        MapIndex::new(RAFT_LOG, self.db.clone())
+    }
+
+    pub fn dpos_delegates(&self) -> MapIndex<Address, Delegate> {
+        MapIndex::new(DPOS_DELEGATES, self.db.clone())
+    }
+
+    /// voter => ballot
+    pub fn dpos_ballots(&self) -> MapIndex<Address, Ballot> {
+        MapIndex::new(DPOS_BALLOTS, self.db.clone())
+    }
+
+    /// round => active delegates
+    pub fn dpos_active_delegates(&self) -> MapIndex<u64, ValidatorArray> {
+        MapIndex::new(DPOS_ACTIVE_DELEGATES, self.db.clone())
+    }
+
+    pub fn dpos_applied_height(&self) -> Entry<Height> {
+        Entry::new(DPOS_APPLIED_HEIGHT, self.db.clone())
+    }
+
+    /// evidence hash => the evidence not included in a block yet
+    pub fn evidences(&self) -> MapIndex<Hash, Evidence> {
+        MapIndex::new(EVIDENCES, self.db.clone())
+    }
+
+    /// evidence hash => the height of the block including it
+    pub fn committed_evidences(&self) -> MapIndex<Hash, Height> {
+        MapIndex::new(COMMITTED_EVIDENCES, self.db.clone())
+    }
+
+    /// block hash => the evidences of the block, blocks without one are skipped
+    pub fn block_evidences(&self) -> MapIndex<Hash, Evidences> {
+        MapIndex::new(BLOCK_EVIDENCES, self.db.clone())
+    }
+
+    /// peer id => the addresses and the ban of a known peer
+    pub fn peers(&self) -> MapIndex<String, PeerRecord> {
+        MapIndex::new(PEERS, self.db.clone())
     }
 
     /// Returns the height of the last committed block.
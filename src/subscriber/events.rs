@@ -1,8 +1,11 @@
 //! Event types and buses (replaces actix-broker)
 
+use cryptocurrency_kit::ethkey::Address;
 use libp2p::PeerId;
 
+use crate::consensus::types::View;
 use crate::types::block::{Block, Blocks, Header};
+use crate::types::evidence::Evidence;
 use crate::types::Height;
 
 pub const MAX_MAILBOX_CAPACITY: usize = 1 << 11;
@@ -14,6 +17,10 @@ pub enum ChainEvent {
     NewHeader(Header),
     SyncBlock(Height),
     PostBlock(Option<PeerId>, Blocks),
+    /// the consensus moves to a new round of the height, with the proposer of the round
+    RoundChange(View, Option<Address>),
+    /// a new evidence of an equivocating validator, it is gossiped to the peers
+    Evidence(Evidence),
 }
 
 /// Chain event bus - replaces ProcessSignals for ChainEvent
@@ -39,6 +46,8 @@ impl ChainEventBus {
 
 use crate::types::transaction::Transaction;
 use crate::protocol::GossipMessage;
+use crate::consensus::raft::types::RaftMessage;
+use crate::sync::messages::SyncMessage;
 
 /// Broadcast events (consensus, blocks, sync)
 #[derive(Clone, Debug)]
@@ -46,7 +55,9 @@ pub enum BroadcastEvent {
     Transaction(Transaction),
     Blocks(Option<PeerId>, Blocks),
     Consensus(GossipMessage),
-    Sync(Height),
+    Raft(RaftMessage),
+    /// sends to the peer, or all peers if none
+    Sync(Option<PeerId>, SyncMessage),
 }
 
 /// Broadcast event bus - replaces BroadcastEventSubscriber
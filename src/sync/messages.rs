@@ -0,0 +1,51 @@
+use std::borrow::Cow;
+
+use cryptocurrency_kit::crypto::{hash, CryptoHash, Hash};
+use cryptocurrency_kit::storage::values::StorageValue;
+
+use crate::types::{block::Header, evidence::Evidence, transaction::Transaction, Height};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BlockBody {
+    pub hash: Hash,
+    pub transactions: Vec<Transaction>,
+    #[serde(default)]
+    pub evidences: Vec<Evidence>,
+}
+
+/// The payload of `P2PMsgCode::Sync`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum SyncMessage {
+    /// the last height of the sender
+    Status(Height),
+    GetHeaders { from: Height, count: u64 },
+    Headers(Vec<Header>),
+    /// block hashes
+    GetBodies(Vec<Hash>),
+    Bodies(Vec<BlockBody>),
+}
+
+implement_storagevalue_traits! {SyncMessage}
+implement_cryptohash_traits! {SyncMessage}
+
+impl SyncMessage {
+    /// Unlike `from_bytes`, it does not panic on the malformed payload sent by a bad peer
+    pub fn decode(payload: &[u8]) -> Option<SyncMessage> {
+        serde_json::from_slice(payload).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_decode() {
+        let msg = SyncMessage::GetHeaders { from: 10, count: 20 };
+        match SyncMessage::decode(&msg.into_bytes()) {
+            Some(SyncMessage::GetHeaders { from, count }) => assert_eq!((from, count), (10, 20)),
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert!(SyncMessage::decode(b"10").is_none());
+    }
+}
@@ -0,0 +1,44 @@
+//! Block synchronization: headers are downloaded first by range and verified against the
+//! validator set, then the bodies are fetched in parallel from several peers.
+
+use std::time::Duration;
+
+use crate::types::Height;
+
+pub mod messages;
+pub mod peers;
+pub mod service;
+pub mod verifier;
+
+/// Max headers served in one `GetHeaders`
+pub const MAX_HEADERS_PER_REQUEST: u64 = 192;
+/// Max bodies served in one `GetBodies`
+pub const MAX_BODIES_PER_REQUEST: usize = 32;
+/// Max verified headers waiting for their bodies
+pub const MAX_PENDING_HEADERS: usize = 2048;
+
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+pub const TICK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The last height whose validators are known after `height` is committed,
+/// the validator set may change after an epoch boundary.
+pub fn verifiable_height(height: Height, epoch: Height) -> Height {
+    if epoch == 0 {
+        return Height::max_value();
+    }
+    (height / epoch + 1) * epoch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_verifiable_height() {
+        assert_eq!(verifiable_height(0, 10), 10);
+        assert_eq!(verifiable_height(9, 10), 10);
+        assert_eq!(verifiable_height(10, 10), 20);
+        assert_eq!(verifiable_height(10, 0), Height::max_value());
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+
+use crate::types::Height;
+
+pub const INITIAL_SCORE: i32 = 0;
+pub const MAX_SCORE: i32 = 100;
+/// A peer is banned when its score drops below it
+pub const BAN_SCORE: i32 = -100;
+pub const BAN_DURATION: Duration = Duration::from_secs(600);
+/// A peer is forgotten if its status is not received in time
+pub const PEER_EXPIRY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Penalty {
+    Timeout,
+    InvalidData,
+    UnexpectedMessage,
+}
+
+impl Penalty {
+    fn score(self) -> i32 {
+        match self {
+            Penalty::Timeout => 10,
+            Penalty::InvalidData => 50,
+            Penalty::UnexpectedMessage => 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncPeer {
+    pub height: Height,
+    pub score: i32,
+    pub last_seen: Instant,
+    banned_until: Option<Instant>,
+}
+
+/// The sync peers and their scores, a peer earns by the valid responses and loses by
+/// the timeouts and invalid data.
+#[derive(Debug, Default)]
+pub struct PeerSet {
+    peers: HashMap<PeerId, SyncPeer>,
+}
+
+impl PeerSet {
+    pub fn new() -> Self {
+        PeerSet { peers: HashMap::new() }
+    }
+
+    pub fn update_height(&mut self, peer_id: PeerId, height: Height) {
+        let now = Instant::now();
+        let peer = self.peers.entry(peer_id).or_insert(SyncPeer {
+            height,
+            score: INITIAL_SCORE,
+            last_seen: now,
+            banned_until: None,
+        });
+        peer.height = peer.height.max(height);
+        peer.last_seen = now;
+    }
+
+    pub fn get(&self, peer_id: &PeerId) -> Option<&SyncPeer> {
+        self.peers.get(peer_id)
+    }
+
+    pub fn reward(&mut self, peer_id: &PeerId) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.score = (peer.score + 1).min(MAX_SCORE);
+        }
+    }
+
+    pub fn penalize(&mut self, peer_id: &PeerId, penalty: Penalty) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.score -= penalty.score();
+            warn!("Penalize sync peer {}, penalty: {:?}, score: {}", peer_id.to_base58(), penalty, peer.score);
+            if peer.score < BAN_SCORE {
+                peer.banned_until = Some(Instant::now() + BAN_DURATION);
+                peer.score = INITIAL_SCORE;
+            }
+        }
+    }
+
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        self.peers
+            .get(peer_id)
+            .and_then(|peer| peer.banned_until)
+            .map_or(false, |until| until > Instant::now())
+    }
+
+    /// Forgets the silent peers and lifts the expired bans
+    pub fn expire(&mut self) {
+        let now = Instant::now();
+        self.peers.retain(|_, peer| {
+            peer.banned_until.map_or(false, |until| until > now) || now.duration_since(peer.last_seen) < PEER_EXPIRY
+        });
+        self.peers.values_mut().for_each(|peer| {
+            if peer.banned_until.map_or(false, |until| until <= now) {
+                peer.banned_until = None;
+            }
+        });
+    }
+
+    pub fn best_height(&self) -> Option<Height> {
+        self.peers
+            .iter()
+            .filter(|(peer_id, _)| !self.is_banned(peer_id))
+            .map(|(_, peer)| peer.height)
+            .max()
+    }
+
+    /// The usable peers reach the height, the higher score comes first
+    pub fn candidates(&self, height: Height) -> Vec<PeerId> {
+        let mut candidates: Vec<(&PeerId, &SyncPeer)> = self
+            .peers
+            .iter()
+            .filter(|(peer_id, peer)| peer.height >= height && !self.is_banned(peer_id))
+            .collect();
+        candidates.sort_by(|a, b| b.1.score.cmp(&a.1.score).then(a.0.to_bytes().cmp(&b.0.to_bytes())));
+        candidates.into_iter().map(|(peer_id, _)| *peer_id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_peer_score() {
+        let (good, bad) = (PeerId::random(), PeerId::random());
+        let mut peers = PeerSet::new();
+        peers.update_height(good, 100);
+        peers.update_height(bad, 200);
+        assert_eq!(peers.best_height(), Some(200));
+        assert_eq!(peers.candidates(150), vec![bad]);
+
+        peers.reward(&good);
+        peers.penalize(&bad, Penalty::Timeout);
+        assert_eq!(peers.candidates(50), vec![good, bad]);
+
+        (0..3).for_each(|_| peers.penalize(&bad, Penalty::InvalidData));
+        assert!(peers.is_banned(&bad));
+        assert_eq!(peers.best_height(), Some(100));
+        assert!(peers.candidates(150).is_empty());
+    }
+}
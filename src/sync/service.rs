@@ -0,0 +1,367 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Sender};
+use cryptocurrency_kit::crypto::Hash;
+use libp2p::PeerId;
+
+use super::messages::{BlockBody, SyncMessage};
+use super::peers::{Penalty, PeerSet};
+use super::verifier::{verify_body, verify_headers};
+use super::{
+    verifiable_height, MAX_BODIES_PER_REQUEST, MAX_HEADERS_PER_REQUEST, MAX_PENDING_HEADERS, REQUEST_TIMEOUT,
+    STATUS_INTERVAL, TICK_INTERVAL,
+};
+use crate::{
+    consensus::consensus::{HeaderVerifier, SealVerifier},
+    core::chain::Chain,
+    error::ChainError,
+    subscriber::events::{BroadcastEvent, BroadcastEventBus},
+    types::{block::{Block, Header}, Height},
+};
+
+/// Feeds the sync messages received from network into the sync service
+#[derive(Clone)]
+pub struct SyncHandle {
+    tx: Sender<(PeerId, Vec<u8>)>,
+}
+
+impl SyncHandle {
+    pub fn send_message(&self, peer_id: PeerId, payload: Vec<u8>) {
+        let _ = self.tx.send((peer_id, payload));
+    }
+}
+
+struct HeaderRequest {
+    peer_id: PeerId,
+    from: Height,
+    time: Instant,
+}
+
+struct BodyRequest {
+    hashes: Vec<Hash>,
+    time: Instant,
+}
+
+pub struct SyncService {
+    chain: Arc<Chain>,
+    broadcast_bus: BroadcastEventBus,
+    seal_verifier: SealVerifier,
+    header_verifier: HeaderVerifier,
+    peers: PeerSet,
+    // verified headers waiting for their bodies
+    headers: BTreeMap<Height, Header>,
+    bodies: HashMap<Hash, BlockBody>,
+    // the block hashes whose bodies are not requested
+    pending_bodies: VecDeque<Hash>,
+    header_request: Option<HeaderRequest>,
+    body_requests: HashMap<PeerId, BodyRequest>,
+    last_status: Option<Instant>,
+}
+
+pub fn start_sync_service(
+    chain: Arc<Chain>,
+    broadcast_bus: BroadcastEventBus,
+    seal_verifier: SealVerifier,
+    header_verifier: HeaderVerifier,
+) -> SyncHandle {
+    let (tx, rx) = channel::unbounded();
+    let mut service = SyncService::new(chain, broadcast_bus, seal_verifier, header_verifier);
+    std::thread::spawn(move || {
+        info!("Start sync service");
+        service.run(rx);
+    });
+    SyncHandle { tx }
+}
+
+impl SyncService {
+    pub fn new(
+        chain: Arc<Chain>,
+        broadcast_bus: BroadcastEventBus,
+        seal_verifier: SealVerifier,
+        header_verifier: HeaderVerifier,
+    ) -> Self {
+        SyncService {
+            chain,
+            broadcast_bus,
+            seal_verifier,
+            header_verifier,
+            peers: PeerSet::new(),
+            headers: BTreeMap::new(),
+            bodies: HashMap::new(),
+            pending_bodies: VecDeque::new(),
+            header_request: None,
+            body_requests: HashMap::new(),
+            last_status: None,
+        }
+    }
+
+    fn run(&mut self, rx: Receiver<(PeerId, Vec<u8>)>) {
+        loop {
+            match rx.recv_timeout(TICK_INTERVAL) {
+                Ok((peer_id, payload)) => self.handle_message(peer_id, &payload),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.tick();
+        }
+    }
+
+    fn send(&self, peer_id: Option<PeerId>, msg: SyncMessage) {
+        self.broadcast_bus.send(BroadcastEvent::Sync(peer_id, msg));
+    }
+
+    pub fn handle_message(&mut self, peer_id: PeerId, payload: &[u8]) {
+        if self.peers.is_banned(&peer_id) {
+            return;
+        }
+        let msg = match SyncMessage::decode(payload) {
+            Some(msg) => msg,
+            None => {
+                self.peers.penalize(&peer_id, Penalty::InvalidData);
+                return;
+            }
+        };
+        match msg {
+            SyncMessage::Status(height) => {
+                self.peers.update_height(peer_id, height);
+                let last_height = self.chain.get_last_height();
+                // let the lagging peer know us
+                if height < last_height {
+                    self.send(Some(peer_id), SyncMessage::Status(last_height));
+                }
+            }
+            SyncMessage::GetHeaders { from, count } => {
+                let count = count.min(MAX_HEADERS_PER_REQUEST);
+                let headers: Vec<Header> = (from..from.saturating_add(count))
+                    .map_while(|height| self.chain.get_header_by_height(height))
+                    .collect();
+                self.send(Some(peer_id), SyncMessage::Headers(headers));
+            }
+            SyncMessage::GetBodies(hashes) => {
+                let bodies: Vec<BlockBody> = hashes
+                    .iter()
+                    .take(MAX_BODIES_PER_REQUEST)
+                    .filter_map(|hash| self.chain.get_block_by_hash(hash))
+                    .map(|block| BlockBody {
+                        hash: block.hash(),
+                        transactions: block.transactions().clone(),
+                        evidences: block.evidences().clone(),
+                    })
+                    .collect();
+                self.send(Some(peer_id), SyncMessage::Bodies(bodies));
+            }
+            SyncMessage::Headers(headers) => self.handle_headers(peer_id, headers),
+            SyncMessage::Bodies(bodies) => self.handle_bodies(peer_id, bodies),
+        }
+    }
+
+    fn handle_headers(&mut self, peer_id: PeerId, headers: Vec<Header>) {
+        let from = match self.header_request {
+            Some(ref request) if request.peer_id == peer_id => request.from,
+            _ => {
+                self.peers.penalize(&peer_id, Penalty::UnexpectedMessage);
+                return;
+            }
+        };
+        self.header_request = None;
+        if headers.is_empty() {
+            return;
+        }
+        let parent = match self.header_at(from - 1) {
+            Some(parent) => parent,
+            // the downloaded headers have been reset
+            None => return,
+        };
+        let limit = verifiable_height(self.chain.get_last_height(), self.chain.epoch());
+        let headers: Vec<Header> = headers
+            .into_iter()
+            .take(MAX_HEADERS_PER_REQUEST as usize)
+            .filter(|header| header.height <= limit)
+            .collect();
+        if let Err(err) = verify_headers(&parent, &headers, &self.seal_verifier) {
+            warn!("Invalid headers from {}, err: {}", peer_id.to_base58(), err);
+            self.peers.penalize(&peer_id, Penalty::InvalidData);
+            return;
+        }
+        self.peers.reward(&peer_id);
+        debug!("Download headers, from: {}, size: {}", from, headers.len());
+        for header in headers {
+            self.pending_bodies.push_back(header.block_hash());
+            self.headers.insert(header.height, header);
+        }
+    }
+
+    fn handle_bodies(&mut self, peer_id: PeerId, bodies: Vec<BlockBody>) {
+        let request = match self.body_requests.remove(&peer_id) {
+            Some(request) => request,
+            None => {
+                self.peers.penalize(&peer_id, Penalty::UnexpectedMessage);
+                return;
+            }
+        };
+        let mut requested: HashSet<Hash> = request.hashes.iter().cloned().collect();
+        let mut received = vec![];
+        for body in bodies {
+            let valid = requested.remove(&body.hash)
+                && match self.headers.values().find(|header| header.block_hash() == body.hash) {
+                    Some(header) => verify_body(header, &body, self.chain.padded_merkle_height()).is_ok(),
+                    // the header is stale
+                    None => continue,
+                };
+            if !valid {
+                warn!("Invalid block body from {}, hash: {:?}", peer_id.to_base58(), body.hash);
+                self.peers.penalize(&peer_id, Penalty::InvalidData);
+                self.pending_bodies.extend(request.hashes);
+                return;
+            }
+            received.push(body);
+        }
+        self.peers.reward(&peer_id);
+        // retry the missing ones
+        self.pending_bodies.extend(requested);
+        received.into_iter().for_each(|body| {
+            self.bodies.insert(body.hash, body);
+        });
+    }
+
+    // the header of the height from the chain or the downloaded headers
+    fn header_at(&self, height: Height) -> Option<Header> {
+        self.headers
+            .get(&height)
+            .cloned()
+            .or_else(|| self.chain.get_header_by_height(height))
+    }
+
+    fn tick(&mut self) {
+        let now = Instant::now();
+        if self.last_status.map_or(true, |last| now.duration_since(last) >= STATUS_INTERVAL) {
+            self.last_status = Some(now);
+            self.peers.expire();
+            self.send(None, SyncMessage::Status(self.chain.get_last_height()));
+        }
+        self.check_timeouts(now);
+        self.import_blocks();
+        self.request_headers(now);
+        self.request_bodies(now);
+    }
+
+    fn check_timeouts(&mut self, now: Instant) {
+        if let Some(request) = self.header_request.take() {
+            if now.duration_since(request.time) >= REQUEST_TIMEOUT {
+                self.peers.penalize(&request.peer_id, Penalty::Timeout);
+            } else {
+                self.header_request = Some(request);
+            }
+        }
+        let expired: Vec<PeerId> = self
+            .body_requests
+            .iter()
+            .filter(|(_, request)| now.duration_since(request.time) >= REQUEST_TIMEOUT)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in expired {
+            self.peers.penalize(&peer_id, Penalty::Timeout);
+            let request = self.body_requests.remove(&peer_id).unwrap();
+            self.pending_bodies.extend(request.hashes);
+        }
+    }
+
+    fn request_headers(&mut self, now: Instant) {
+        if self.header_request.is_some() || self.headers.len() >= MAX_PENDING_HEADERS {
+            return;
+        }
+        let last_height = self.chain.get_last_height();
+        let tip = self.headers.keys().next_back().cloned().unwrap_or(last_height).max(last_height);
+        let limit = verifiable_height(last_height, self.chain.epoch());
+        if tip >= limit || self.peers.best_height().map_or(true, |best| best <= tip) {
+            return;
+        }
+        let from = tip + 1;
+        let peer_id = match self.peers.candidates(from).first() {
+            Some(peer_id) => *peer_id,
+            None => return,
+        };
+        let count = MAX_HEADERS_PER_REQUEST.min(limit - tip);
+        self.header_request = Some(HeaderRequest { peer_id, from, time: now });
+        self.send(Some(peer_id), SyncMessage::GetHeaders { from, count });
+    }
+
+    // spreads the bodies over the idle peers
+    fn request_bodies(&mut self, now: Instant) {
+        let mut candidates: VecDeque<PeerId> = self
+            .peers
+            .candidates(self.chain.get_last_height() + 1)
+            .into_iter()
+            .filter(|peer_id| !self.body_requests.contains_key(peer_id))
+            .collect();
+        let downloaded: HashSet<Hash> = self.headers.values().map(|header| header.block_hash()).collect();
+        self.pending_bodies.retain(|hash| downloaded.contains(hash));
+        while !self.pending_bodies.is_empty() {
+            let hashes: Vec<Hash> = {
+                let size = self.pending_bodies.len().min(MAX_BODIES_PER_REQUEST);
+                self.pending_bodies.drain(..size).collect()
+            };
+            let max_height = self
+                .headers
+                .values()
+                .filter(|header| hashes.contains(&header.block_hash()))
+                .map(|header| header.height)
+                .max()
+                .unwrap_or(0);
+            let position = candidates
+                .iter()
+                .position(|peer_id| self.peers.get(peer_id).map_or(false, |peer| peer.height >= max_height));
+            let peer_id = match position.and_then(|position| candidates.remove(position)) {
+                Some(peer_id) => peer_id,
+                None => {
+                    hashes.into_iter().rev().for_each(|hash| self.pending_bodies.push_front(hash));
+                    return;
+                }
+            };
+            self.body_requests.insert(peer_id, BodyRequest { hashes: hashes.clone(), time: now });
+            self.send(Some(peer_id), SyncMessage::GetBodies(hashes));
+        }
+    }
+
+    fn import_blocks(&mut self) {
+        loop {
+            let height = self.chain.get_last_height() + 1;
+            // the downloaded headers are stale, e.g. the block has been committed by consensus
+            let stale: Vec<Height> = self.headers.range(..height).map(|(height, _)| *height).collect();
+            stale.into_iter().for_each(|height| {
+                if let Some(header) = self.headers.remove(&height) {
+                    self.bodies.remove(&header.block_hash());
+                }
+            });
+            let hash = match self.headers.get(&height) {
+                Some(header) => header.block_hash(),
+                None => return,
+            };
+            let body = match self.bodies.remove(&hash) {
+                Some(body) => body,
+                None => return,
+            };
+            let header = self.headers.remove(&height).unwrap();
+            let mut block = Block::new(header, body.transactions);
+            block.set_evidences(body.evidences);
+            match self.chain.import_block(&block, &self.header_verifier) {
+                Ok(()) | Err(ChainError::Exists(_)) => {}
+                Err(err) => {
+                    warn!("Failed to import synced block, height: {}, err: {}", height, err);
+                    self.reset();
+                    return;
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.headers.clear();
+        self.bodies.clear();
+        self.pending_bodies.clear();
+        self.header_request = None;
+        self.body_requests.clear();
+    }
+}
@@ -0,0 +1,77 @@
+use crate::{
+    consensus::consensus::SealVerifier,
+    error::SyncError,
+    types::{block::Header, evidence::evidence_root, transaction::transactions_root, Height},
+};
+
+use super::messages::BlockBody;
+
+/// Checks the headers are continuous from `parent` and every one is sealed by the validators
+pub fn verify_headers(parent: &Header, headers: &[Header], seal_verifier: &SealVerifier) -> Result<(), SyncError> {
+    let mut parent_height = parent.height;
+    let mut parent_hash = parent.block_hash();
+    for header in headers {
+        if header.height != parent_height + 1 {
+            return Err(SyncError::Discontinuous(parent_height + 1, header.height));
+        }
+        if header.prev_hash != parent_hash {
+            return Err(SyncError::UnknownParent(header.height));
+        }
+        seal_verifier(header).map_err(|err| SyncError::InvalidSeal(header.height, format!("{}", err)))?;
+        parent_height = header.height;
+        parent_hash = header.block_hash();
+    }
+    Ok(())
+}
+
+/// Checks the transactions and the evidences are the ones committed by the header, see `transactions_root`
+/// for `padded_height`
+pub fn verify_body(header: &Header, body: &BlockBody, padded_height: Height) -> Result<(), SyncError> {
+    let tx_hash = transactions_root(body.transactions.clone(), header.height, padded_height);
+    if tx_hash != header.tx_hash || evidence_root(&body.evidences) != header.evidence_hash {
+        return Err(SyncError::InvalidBody(header.block_hash()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use cryptocurrency_kit::ethkey::Address;
+
+    use cryptocurrency_kit::crypto::EMPTY_HASH;
+
+    use super::*;
+    use crate::consensus::error::EngineError;
+
+    fn child(parent: &Header) -> Header {
+        let mut header = Header::new_mock(parent.block_hash(), Address::from(1), EMPTY_HASH, parent.height + 1, parent.time + 1, None);
+        header.cache_hash(None);
+        header
+    }
+
+    #[test]
+    fn t_verify_headers() {
+        let accept: SealVerifier = Arc::new(|_: &Header| Ok(()));
+        let reject: SealVerifier = Arc::new(|_: &Header| Err(EngineError::InvalidSignature));
+
+        let parent = Header::new_mock(EMPTY_HASH, Address::from(1), EMPTY_HASH, 10, 100, None);
+        let first = child(&parent);
+        let second = child(&first);
+        assert!(verify_headers(&parent, &[first.clone(), second.clone()], &accept).is_ok());
+        assert!(verify_headers(&parent, &[first.clone(), second.clone()], &reject).is_err());
+        // gap
+        assert!(verify_headers(&parent, &[second.clone()], &accept).is_err());
+        // fork
+        let mut other = child(&parent);
+        other.time += 1;
+        other.cache_hash(None);
+        assert!(verify_headers(&parent, &[other, second], &accept).is_err());
+        let body = BlockBody { hash: first.block_hash(), transactions: vec![], evidences: vec![] };
+        assert!(verify_body(&first, &body, 0).is_ok());
+        let mut committed = first.clone();
+        committed.evidence_hash = Some(EMPTY_HASH);
+        assert!(verify_body(&committed, &body, 0).is_err());
+    }
+}
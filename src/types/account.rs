@@ -0,0 +1,23 @@
+use cryptocurrency_kit::crypto::{hash, CryptoHash, Hash};
+use cryptocurrency_kit::storage::values::StorageValue;
+
+use std::borrow::Cow;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Account {
+    pub balance: u64,
+    // the number of transactions sent from the account
+    pub nonce: u64,
+    // the stake of the dpos vote, not spendable until unvoted
+    #[serde(default)]
+    pub locked: u64,
+}
+
+implement_cryptohash_traits! {Account}
+implement_storagevalue_traits! {Account}
+
+impl Account {
+    pub fn new(balance: u64, nonce: u64) -> Self {
+        Account { balance, nonce, locked: 0 }
+    }
+}
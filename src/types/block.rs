@@ -6,6 +6,7 @@ use std::borrow::Cow;
 
 use super::transaction::Transaction;
 use super::votes::Votes;
+use super::evidence::Evidence;
 use super::{Bloom, Difficulty, Gas, Height, Timestamp};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -28,6 +29,12 @@ pub struct Header {
     pub extra: Option<Vec<u8>>,
     #[serde(default)]
     pub votes: Option<Votes>,
+    // the proposer's vote for a validator change, it is skipped if none so old headers keep their hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vote: Option<ValidatorVote>,
+    // the merkle root of the block's evidences, none if it has no evidence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evidence_hash: Option<Hash>,
     #[serde(skip_serializing, skip_deserializing)]
     hash_cache: Option<Hash>, // use atomic pre instant of it
 }
@@ -35,6 +42,13 @@ pub struct Header {
 implement_cryptohash_traits! {Header}
 implement_storagevalue_traits! {Header}
 
+/// A vote to add(`authorize`) or remove a validator, it takes effect at the next epoch boundary
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidatorVote {
+    pub address: Address,
+    pub authorize: bool,
+}
+
 impl Header {
     pub fn new(
         prev_hash: Hash,
@@ -65,6 +79,8 @@ impl Header {
             time: tm,
             extra,
             votes,
+            vote: None,
+            evidence_hash: None,
             hash_cache: None,
         }
     }
@@ -85,6 +101,8 @@ impl Header {
         if let Some(block_hash) = block_hash {
             self.hash_cache = Some(block_hash);
         } else {
+            // drop the stale cache first, the header may have been changed
+            self.hash_cache = None;
             let block_hash = self.block_hash();
             self.hash_cache = Some(block_hash);
         }
@@ -105,6 +123,8 @@ impl Header {
             time: 0,
             extra: None,
             votes: None,
+            vote: None,
+            evidence_hash: None,
             hash_cache: None,
         }
     }
@@ -154,6 +174,9 @@ struct HeaderBytes<'a> {
 pub struct Block {
     header: Header,
     transactions: Vec<Transaction>,
+    // the equivocations of validators, they are committed by `Header::evidence_hash`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    evidences: Vec<Evidence>,
 }
 
 implement_cryptohash_traits! {Block}
@@ -170,6 +193,7 @@ impl Block {
         Block {
             header,
             transactions: txs,
+            evidences: vec![],
         }
     }
 
@@ -177,6 +201,7 @@ impl Block {
         Block {
             header,
             transactions,
+            evidences: vec![],
         }
     }
 
@@ -202,6 +227,15 @@ impl Block {
         &mut self.transactions
     }
 
+    pub fn evidences(&self) -> &Vec<Evidence> {
+        &self.evidences
+    }
+
+    /// Sets the evidences, `Header::evidence_hash` should be set to the root of them before
+    pub fn set_evidences(&mut self, evidences: Vec<Evidence>) {
+        self.evidences = evidences;
+    }
+
     pub fn coinbase(&self) -> Address {
         
         self.header.proposer
@@ -0,0 +1,157 @@
+//! Evidence of a validator that signed two different messages of the same view, it is gossiped
+//! and included in the blocks so the governance or the slashing can act on it.
+
+use cryptocurrency_kit::crypto::{hash, CryptoHash, Hash};
+use cryptocurrency_kit::ethkey::Address;
+use cryptocurrency_kit::storage::values::StorageValue;
+
+use std::borrow::Cow;
+
+use crate::{
+    common::merkle_tree_root,
+    consensus::types::{PrePrepare, Subject, View},
+    protocol::{GossipMessage, MessageType},
+};
+
+/// Two signed messages of the same kind and view with different digests, it is self-contained,
+/// anyone can check it without the chain of the view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Evidence {
+    // the message with the smaller hash, so every node builds the same evidence
+    pub first: GossipMessage,
+    pub second: GossipMessage,
+}
+
+implement_cryptohash_traits! {Evidence}
+implement_storagevalue_traits! {Evidence}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Evidences(pub Vec<Evidence>);
+
+implement_cryptohash_traits! {Evidences}
+implement_storagevalue_traits! {Evidences}
+
+impl Evidence {
+    /// Builds the evidence of the conflicting messages, the order of them does not matter
+    pub fn new(first: GossipMessage, second: GossipMessage) -> Result<Self, String> {
+        let (first, second) = if CryptoHash::hash(&first).as_ref() < CryptoHash::hash(&second).as_ref() {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        let evidence = Evidence { first, second };
+        evidence.verify()?;
+        Ok(evidence)
+    }
+
+    pub fn hash(&self) -> Hash {
+        CryptoHash::hash(self)
+    }
+
+    /// Returns the validator who signed both messages and the view of them
+    pub fn verify(&self) -> Result<(Address, View), String> {
+        if self.first.code != self.second.code {
+            return Err("the messages are of different kinds".to_string());
+        }
+        if self.first.code == MessageType::RoundChange {
+            return Err("round changes never conflict".to_string());
+        }
+        if CryptoHash::hash(&self.first).as_ref() >= CryptoHash::hash(&self.second).as_ref() {
+            return Err("the messages are not in order".to_string());
+        }
+        let (mut first, mut second) = (self.first.clone(), self.second.clone());
+        let offender = first.address()?;
+        if second.address()? != offender {
+            return Err("the messages are signed by different validators".to_string());
+        }
+        let (view, digest) = message_digest(&first)?;
+        let (other_view, other_digest) = message_digest(&second)?;
+        if view != other_view {
+            return Err("the messages are of different views".to_string());
+        }
+        if digest == other_digest {
+            return Err("the messages do not conflict".to_string());
+        }
+        Ok((offender, view))
+    }
+
+    /// The view of the messages, it is not verified
+    pub fn view(&self) -> Result<View, String> {
+        message_digest(&self.first).map(|(view, _)| view)
+    }
+
+    pub fn offender(&self) -> Result<Address, String> {
+        self.verify().map(|(offender, _)| offender)
+    }
+}
+
+/// The root of the evidences committed by `Header::evidence_hash`, none if there is no evidence
+pub fn evidence_root(evidences: &[Evidence]) -> Option<Hash> {
+    if evidences.is_empty() {
+        None
+    } else {
+        Some(merkle_tree_root(evidences.to_vec()))
+    }
+}
+
+/// The view and the digest a validator signs at most once, the proposal hash of a preprepare
+/// and the subject digest of a prepare or commit. The payload may come from anyone, so it is
+/// decoded without panic.
+pub fn message_digest(msg: &GossipMessage) -> Result<(View, Hash), String> {
+    match msg.code {
+        MessageType::Preprepare => {
+            let preprepare: PrePrepare = serde_json::from_slice(msg.msg()).map_err(|err| err.to_string())?;
+            Ok((preprepare.view, preprepare.proposal.block().hash()))
+        }
+        MessageType::Prepare | MessageType::Commit => {
+            let subject: Subject = serde_json::from_slice(msg.msg()).map_err(|err| err.to_string())?;
+            Ok((subject.view, subject.digest))
+        }
+        MessageType::RoundChange => Err("a round change has no digest".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptocurrency_kit::crypto::hash;
+    use cryptocurrency_kit::ethkey::{Generator, KeyPair, Random};
+
+    fn vote(key_pair: &KeyPair, code: MessageType, view: View, digest: Hash) -> GossipMessage {
+        let subject = Subject { view, digest };
+        let mut msg = GossipMessage::new(code, subject.into_bytes(), None);
+        msg.set_sign(key_pair.secret());
+        msg
+    }
+
+    #[test]
+    fn t_evidence() {
+        let key_pair = Random.generate().unwrap();
+        let view = View::new(3, 1);
+        let first = vote(&key_pair, MessageType::Prepare, view, hash(b"a"));
+        let second = vote(&key_pair, MessageType::Prepare, view, hash(b"b"));
+
+        let evidence = Evidence::new(first.clone(), second.clone()).unwrap();
+        assert_eq!(evidence.verify().unwrap(), (key_pair.address(), view));
+        // the same evidence whatever order the messages are seen in
+        assert_eq!(Evidence::new(second.clone(), first.clone()).unwrap().hash(), evidence.hash());
+
+        // a message sent twice is no equivocation
+        assert!(Evidence::new(first.clone(), first.clone()).is_err());
+        // neither are votes of different views or kinds
+        let other_round = vote(&key_pair, MessageType::Prepare, View::new(3, 2), hash(b"b"));
+        assert!(Evidence::new(first.clone(), other_round).is_err());
+        let commit = vote(&key_pair, MessageType::Commit, view, hash(b"b"));
+        assert!(Evidence::new(first.clone(), commit).is_err());
+        // or votes of two validators
+        let other = vote(&Random.generate().unwrap(), MessageType::Prepare, view, hash(b"b"));
+        assert!(Evidence::new(first.clone(), other).is_err());
+
+        // the messages can not be swapped or forged
+        let swapped = Evidence { first: evidence.second.clone(), second: evidence.first.clone() };
+        assert!(swapped.verify().is_err());
+        let mut forged = evidence.clone();
+        forged.second.msg = Subject { view, digest: hash(b"c") }.into_bytes();
+        assert!(forged.verify().is_err());
+    }
+}
@@ -10,9 +10,11 @@ use std::clone::Clone;
 use std::cmp::{Ord, Ordering, PartialEq};
 use std::fmt::Display;
 
+pub mod account;
 pub mod transaction;
 pub mod block;
 pub mod votes;
+pub mod evidence;
 
 lazy_static! {
     pub static ref EMPTY_ADDRESS: Address = {
@@ -59,6 +61,10 @@ impl ValidatorArray {
     pub fn have(&self, address: &Address) -> bool {
         self.index.contains_key(address)
     }
+
+    pub fn addresses(&self) -> &Vec<Address> {
+        &self.inner
+    }
 }
 
 impl From<Vec<Validator>> for ValidatorArray {
@@ -1,13 +1,16 @@
 use cryptocurrency_kit::crypto::{hash, CryptoHash, Hash};
 use cryptocurrency_kit::ethkey::signature::*;
-use cryptocurrency_kit::ethkey::{Address, Secret, Signature};
+use cryptocurrency_kit::ethkey::{public_to_address, Address, Secret, Signature};
 use cryptocurrency_kit::storage::values::StorageValue;
 use serde_json::to_string;
 
 use std::borrow::Cow;
 
-use crate::common::merkle_tree_root;
-use super::Gas;
+use cryptocurrency_kit::merkle_tree::{leaf_hash, verify_audit_path, MerkleProof};
+
+use crate::common::{legacy_merkle_tree_root, merkle_tree_proof, merkle_tree_root};
+use super::block::Block;
+use super::{Gas, Height};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Transaction {
@@ -20,10 +23,20 @@ pub struct Transaction {
     amount: u64,
     #[serde(default)]
     payload: Vec<u8>,
+    // the chain the transaction is signed for, it is zero for the transactions signed before it was introduced
+    #[serde(default, skip_serializing_if = "is_zero")]
+    chain_id: u64,
     #[serde(rename = "sign")]
     signature: Option<Signature>,
     #[serde(skip_serializing, skip_deserializing)]
     hash: Option<Hash>,
+    // the recovered sender, it is set by the transaction pool after the signature is verified
+    #[serde(skip_serializing, skip_deserializing)]
+    from: Option<Address>,
+}
+
+fn is_zero(chain_id: &u64) -> bool {
+    *chain_id == 0
 }
 
 impl CryptoHash for Transaction {
@@ -50,8 +63,10 @@ impl Transaction {
             recipient: Some(to),
             amount,
             payload,
+            chain_id: 0,
             signature: None,
             hash: None,
+            from: None,
         }
     }
 
@@ -80,24 +95,52 @@ impl Transaction {
         to_string(self).unwrap()
     }
 
-    /// TODO
-    pub fn sign(&mut self, _chain_id: u64, secret: &Secret) {
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    /// Signs the transaction for the chain, the chain id is covered by the signature
+    /// so it can not be replayed on another chain.
+    pub fn sign(&mut self, chain_id: u64, secret: &Secret) {
+        self.chain_id = chain_id;
         let signature = sign_bytes(secret, &TransactionSignature::packet_signature(self));
         self.signature = Some(signature.unwrap());
     }
 
-    pub fn verify_sign(&self, _chai_id: u64) -> bool {
-        if self.signature.is_none() {
+    pub fn verify_sign(&self, chain_id: u64) -> bool {
+        if self.signature.is_none() || self.recipient.is_none() || self.chain_id != chain_id {
             return false;
         }
         let payload = self.signature_payload();
         recover_bytes(self.signature.as_ref().unwrap(), &payload).is_ok()
     }
 
+    /// The hash covers the signature, so an unsigned transaction has no hash
+    pub fn is_signed(&self) -> bool {
+        self.signature.is_some() && self.recipient.is_some()
+    }
+
+    /// Recovers the sender's address from the signature
+    pub fn sender(&self) -> Option<Address> {
+        self.recipient?;
+        let signature = self.signature.as_ref()?;
+        recover_bytes(signature, &self.signature_payload())
+            .map(|ref public| public_to_address(public))
+            .ok()
+    }
+
     pub fn set_hash(&mut self, hash: Hash) {
         self.hash = Some(hash)
     }
 
+    pub fn get_sender(&self) -> Option<&Address> {
+        self.from.as_ref()
+    }
+
+    pub fn set_sender(&mut self, sender: Address) {
+        self.from = Some(sender)
+    }
+
     pub fn set_signature(&mut self, sign: &Signature) {
         self.signature = Some(sign.clone());
     }
@@ -130,6 +173,8 @@ struct TransactionSignature {
     amount: u64,
     #[serde(default)]
     payload: Vec<u8>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    chain_id: u64,
     #[serde(rename = "sign")]
     signature: Option<Signature>,
 }
@@ -147,6 +192,7 @@ impl TransactionSignature {
             recipient: tx.recipient.unwrap(),
             amount: tx.amount,
             payload: tx.payload.clone(),
+            chain_id: tx.chain_id,
             signature: Some(sign),
         };
         signature.into_bytes()
@@ -160,15 +206,58 @@ impl TransactionSignature {
             recipient: tx.recipient.unwrap(),
             amount: tx.amount,
             payload: tx.payload.clone(),
+            chain_id: tx.chain_id,
             signature: None,
         };
         signature.into_bytes()
     }
 }
 
+/// A batch of transactions gossiped to a peer
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Transactions(pub Vec<Transaction>);
+implement_cryptohash_traits! {Transactions}
+implement_storagevalue_traits! {Transactions}
+
+/// The root of the transactions of the block at `height`, the blocks below `padded_height` keep the root
+/// of the first release
+pub fn transactions_root(transactions: Vec<Transaction>, height: Height, padded_height: Height) -> Hash {
+    if height < padded_height {
+        legacy_merkle_tree_root(transactions)
+    } else {
+        merkle_tree_root(transactions)
+    }
+}
+
+/// Proves the transaction is the `index`th one of the block, whose `tx_hash` is `tx_root`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionProof {
+    pub block_hash: Hash,
+    pub height: Height,
+    pub index: u64,
+    pub tx_root: Hash,
+    pub proof: MerkleProof,
+}
+
+impl TransactionProof {
+    pub fn new(block: &Block, index: usize) -> Option<Self> {
+        let proof = merkle_tree_proof(block.transactions().clone(), index)?;
+        Some(TransactionProof {
+            block_hash: block.hash(),
+            height: block.height(),
+            index: index as u64,
+            tx_root: block.header().tx_hash,
+            proof,
+        })
+    }
 
-pub fn merkle_root_transactions(transactions: Vec<Transaction>) -> Hash {
-    merkle_tree_root(transactions)
+    /// Checks the transaction is committed by the `tx_root` taken from a trusted header
+    pub fn verify(&self, tx_root: &Hash, transaction: &Transaction) -> bool {
+        self.tx_root == *tx_root
+            && self.proof.index == self.index
+            && self.proof.leaf == leaf_hash(&transaction.clone().into_bytes())
+            && verify_audit_path(tx_root, &self.proof)
+    }
 }
 
 #[cfg(test)]
@@ -186,5 +275,32 @@ mod tests {
         let hash = tx.hash();
         writeln!(io::stdout(), "hash: {:?}", hash).unwrap();
         writeln!(io::stdout(), "{}", tx.pretty_json()).unwrap();
+        assert!(tx.verify_sign(100));
+        assert!(!tx.verify_sign(101));
+        assert_eq!(tx.sender(), Some(keypair.address()));
+    }
+
+    #[test]
+    fn transaction_proof() {
+        use crate::types::block::Header;
+        use cryptocurrency_kit::crypto::EMPTY_HASH;
+
+        let keypair = Random.generate().unwrap();
+        let transactions: Vec<Transaction> = (0..5)
+            .map(|nonce| {
+                let mut tx = Transaction::new(nonce, Address::from(100), 89, 10, 90, vec![]);
+                tx.sign(100, keypair.secret());
+                tx
+            })
+            .collect();
+        let tx_root = transactions_root(transactions.clone(), 1, 0);
+        assert_ne!(tx_root, transactions_root(transactions.clone(), 1, 2));
+        let block = Block::new(Header::new_mock(EMPTY_HASH, Address::from(1), tx_root, 1, 100, None), transactions.clone());
+
+        let proof = TransactionProof::new(&block, 3).unwrap();
+        assert!(proof.verify(&tx_root, &transactions[3]));
+        assert!(!proof.verify(&tx_root, &transactions[2]));
+        assert!(!proof.verify(&EMPTY_HASH, &transactions[3]));
+        assert!(TransactionProof::new(&block, 5).is_none());
     }
 }
@@ -1,7 +1,7 @@
 use byteorder::WriteBytesExt;
 use cryptocurrency_kit::crypto::{hash, Hash, HASH_SIZE};
 use cryptocurrency_kit::ethkey::Secret;
-use cryptocurrency_kit::ethkey::{public_to_address, recover_bytes};
+use cryptocurrency_kit::ethkey::{public_to_address, recover, Message};
 use cryptocurrency_kit::ethkey::{Address, Signature};
 
 use crate::protocol::MessageType;
@@ -10,7 +10,6 @@ use crate::protocol::MessageType;
 const SIGN_OP_OFFSET: usize = 0;
 #[allow(dead_code)]
 const SIGN_ROUND_OFFSET: usize = 1;
-const SIGN_PACKET_SIZE: usize = 9;
 
 use std::io::Cursor;
 use std::io::Write;
@@ -65,6 +64,18 @@ impl Votes {
             },
         )
     }
+
+    /// Returns the distinct signers of the seals, the invalid seals are skipped
+    pub fn signers(&self, digest: Hash) -> Vec<Address> {
+        let mut signers: Vec<Address> = self
+            .0
+            .iter()
+            .filter_map(|signature| decrypt_commit_bytes(digest.as_ref(), signature).ok())
+            .collect();
+        signers.sort();
+        signers.dedup();
+        signers
+    }
 }
 
 pub fn decrypt_commit_bytes<T: AsRef<[u8]>>(
@@ -72,17 +83,15 @@ pub fn decrypt_commit_bytes<T: AsRef<[u8]>>(
     signture: &Signature,
 ) -> Result<Address, String> {
     let input = input.as_ref();
-    if input.len() != SIGN_PACKET_SIZE {
-        return Err("sign bytes size not equal SIGN_PACKET_SIZE".to_string());
+    if input.len() != HASH_SIZE {
+        return Err("sign bytes size not equal HASH_SIZE".to_string());
     }
-    let op_code = MessageType::Commit;
-    let digest = hash(input);
-    let mut input = Cursor::new(vec![0_u8; 1 + HASH_SIZE]);
-    input.write_u8(op_code as u8).unwrap();
-    input.write_all(digest.as_ref()).unwrap();
-    let buffer = input.into_inner();
-    let digest: Hash = hash(buffer);
-    match recover_bytes(signture, digest.as_ref()) {
+    // the same packet as `encrypt_commit_bytes`
+    let mut packet = Cursor::new(vec![0_u8; 1 + HASH_SIZE]);
+    packet.write_u8(MessageType::Commit as u8).unwrap();
+    packet.write_all(input).unwrap();
+    let digest: Hash = hash(packet.into_inner());
+    match recover(signture, &Message::from_slice(digest.as_ref())) {
         Ok(ref public) => {
             let address = public_to_address(public);
             Ok(address)
@@ -110,6 +119,18 @@ mod tests{
     use cryptocurrency_kit::ethkey::Generator;
     use cryptocurrency_kit::ethkey::Random;
 
+    #[test]
+    fn t_commit_seal() {
+        let keypair = Random{}.generate().unwrap();
+        let digest = hash(b"block");
+        let seal = encrypt_commit_bytes(&digest, keypair.secret());
+        assert_eq!(decrypt_commit_bytes(digest.as_ref(), &seal).unwrap(), keypair.address());
+        let votes = Votes::new(vec![seal]);
+        assert!(votes.verify_signs(digest, |address| address == keypair.address()));
+        assert!(!votes.verify_signs(hash(b"other"), |address| address == keypair.address()));
+        assert_eq!(votes.signers(digest), vec![keypair.address()]);
+    }
+
     #[test]
     fn t_random() {
         (0..10).for_each(|_|{